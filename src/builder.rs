@@ -0,0 +1,245 @@
+/*!
+Builds a tree from values at paths, which can come in any order, and with the arrays and objects
+around them missing.
+
+This is for input that isn't in document order, like gron assignments that have been through grep,
+and for looking at a whole record at once. The tree can be sent on in document order, eg to a
+JsonWriter, or walked.
+*/
+
+use std::collections::BTreeMap;
+
+use rustc_hash::FxHashMap;
+
+use crate::jsonpath::Step;
+use crate::parser::JsonEvent;
+use crate::projection::JsonWriter;
+use crate::projection::Positions;
+use crate::projection::ProjectionSender;
+use crate::sender::Event;
+use crate::sendpath::SendPath;
+
+/// A value, or an array or object with the values under it.
+#[derive(Debug)]
+pub enum Node {
+  /// a string, number, boolean or null
+  Leaf(JsonEvent<String>),
+  /// by index, which can have gaps
+  Array(BTreeMap<u64, Node>),
+  Object(Object),
+}
+
+/// The entries of an object, in the order they were first inserted.
+#[derive(Debug,Default)]
+pub struct Object {
+  entries : Vec<(String, Node)>,
+  positions : FxHashMap<String, usize>,
+}
+
+impl Object {
+  pub fn entries(&self) -> &[(String, Node)] {
+    &self.entries
+  }
+
+  pub fn get(&self, key : &str) -> Option<&Node> {
+    self.positions.get(key).map(|&position| &self.entries[position].1)
+  }
+}
+
+impl Node {
+  fn of(value : JsonEvent<String>) -> Node {
+    match value {
+      JsonEvent::StartArray => Node::Array(BTreeMap::new()),
+      JsonEvent::StartObject => Node::Object(Object::default()),
+      scalar => Node::Leaf(scalar),
+    }
+  }
+
+  // An empty container that can have a child at step.
+  fn parent_of(step : &Step) -> Node {
+    Node::of(match step {
+      Step::Index(_) => JsonEvent::StartArray,
+      Step::Key(_) => JsonEvent::StartObject,
+    })
+  }
+
+  // The child at step, which is made if it isn't there yet.
+  fn child(&mut self, step : &Step, make : impl FnOnce() -> Node) -> Result<&mut Node, String> {
+    match (self, step) {
+      (Node::Array(items), Step::Index(index)) => Ok(items.entry(*index).or_insert_with(make)),
+      (Node::Object(Object{entries, positions}), Step::Key(key)) => {
+        let position = *positions.entry(key.clone()).or_insert_with(|| {
+          entries.push((key.clone(), make()));
+          entries.len() - 1
+        });
+        Ok(&mut entries[position].1)
+      }
+      (Node::Array(_), Step::Key(_)) => Err("is an array, so it can't have a key".into()),
+      (Node::Object(_), Step::Index(_)) => Err("is an object, so it can't have an index".into()),
+      (Node::Leaf(value), _) => Err(format!("is {value}, so it can't have children")),
+    }
+  }
+
+  // Assign value to a node that's already there.
+  fn assign(&mut self, value : JsonEvent<String>) -> Result<(), String> {
+    match (&*self, &value) {
+      (Node::Array(_), JsonEvent::StartArray) | (Node::Object(_), JsonEvent::StartObject) => Ok(()),
+      (Node::Leaf(_), JsonEvent::StartArray | JsonEvent::StartObject) => Err("is already a value".into()),
+      (Node::Leaf(_), _) => {
+        *self = Node::Leaf(value);
+        Ok(())
+      }
+      (_, _) => Err("is already an array or an object".into()),
+    }
+  }
+
+  /// Send this node and everything under it, in document order, with steps as the path of this node.
+  ///
+  /// Arrays and objects are sent as StartArray and StartObject, like the Projector does.
+  pub fn send(&self, steps : &mut Vec<Step>, tx : &mut ProjectionSender<'_>) -> Result<(), Box<dyn std::error::Error>> {
+    match self {
+      Node::Leaf(value) => tx.send(Event::Value(SendPath::from(&steps[..]), value.clone()).into()),
+      Node::Array(items) => {
+        tx.send(Event::Value(SendPath::from(&steps[..]), JsonEvent::StartArray).into())?;
+        for (index, item) in items {
+          steps.push(Step::Index(*index));
+          item.send(steps, tx)?;
+          steps.pop();
+        }
+        Ok(())
+      }
+      Node::Object(object) => {
+        tx.send(Event::Value(SendPath::from(&steps[..]), JsonEvent::StartObject).into())?;
+        for (key, entry) in object.entries() {
+          steps.push(Step::Key(key.clone()));
+          entry.send(steps, tx)?;
+          steps.pop();
+        }
+        Ok(())
+      }
+    }
+  }
+
+  /// This node as json, with null in the gaps of arrays.
+  pub fn to_json(&self) -> String {
+    let mut out = vec![];
+    let mut writer = JsonWriter::new(&mut out, Positions::Preserve);
+    // writing to a Vec doesn't fail, and the tree only has values that JsonWriter takes
+    self.send(&mut vec![], &mut writer).expect("json of a tree");
+    writer.finish().expect("json of a tree");
+    let json = String::from_utf8(out).expect("json is utf8");
+    json.trim_end().to_string()
+  }
+}
+
+/// Why a value can't be inserted, because the node at steps already has something that doesn't fit.
+///
+/// The steps are kept rather than written into the message, so that callers can write them in their
+/// own notation, eg gron's.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Conflict {
+  pub steps : Vec<Step>,
+  pub reason : String,
+}
+
+impl std::fmt::Display for Conflict {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.steps.as_slice() {
+      [] => write!(f, "the top-level value {}", self.reason),
+      steps => write!(f, "{} {}", SendPath::from(steps), self.reason),
+    }
+  }
+}
+
+impl From<Conflict> for String {
+  fn from(conflict : Conflict) -> Self {
+    conflict.to_string()
+  }
+}
+
+// The conflict at steps.
+fn conflict(steps : &[Step]) -> impl FnOnce(String) -> Conflict + '_ {
+  |reason| Conflict{steps: steps.to_vec(), reason}
+}
+
+/// Collects values at paths in any order, and sends them sorted by path, ie the elements of
+/// arrays by index and the keys of objects in the order they were first seen.
+#[derive(Debug,Default)]
+pub struct Builder {
+  root : Option<Node>,
+}
+
+impl Builder {
+  /// Put value at the path with steps, along with the arrays and objects around it.
+  ///
+  /// value is StartArray or StartObject for an empty array or object.
+  pub fn insert(&mut self, steps : &[Step], value : JsonEvent<String>) -> Result<(), Conflict> {
+    let Some((last, parents)) = steps.split_last() else {
+      return match &mut self.root {
+        Some(root) => root.assign(value).map_err(conflict(&[])),
+        None => {
+          self.root = Some(Node::of(value));
+          Ok(())
+        }
+      }
+    };
+    let mut node = self.root.get_or_insert_with(|| Node::parent_of(&steps[0]));
+    for (depth, step) in parents.iter().enumerate() {
+      node = node.child(step, || Node::parent_of(&steps[depth + 1])).map_err(conflict(&steps[..depth]))?;
+    }
+    let mut value = Some(value);
+    let child = node.child(last, || Node::of(value.take().expect("only taken once"))).map_err(conflict(parents))?;
+    match value {
+      Some(value) => child.assign(value).map_err(conflict(steps)),
+      None => Ok(()),
+    }
+  }
+
+  /// Take the tree, leaving nothing.
+  pub fn take(&mut self) -> Option<Node> {
+    self.root.take()
+  }
+
+  /// Send everything that was inserted, sorted by path. Nothing is sent if nothing was inserted.
+  pub fn send(&self, tx : &mut ProjectionSender<'_>) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(root) = &self.root { root.send(&mut vec![], tx)? }
+    tx.send(Event::Finished.into())
+  }
+}
+
+/// Builds each record from values that come in document order, like the ones from a Projector
+/// that selects the records.
+#[derive(Debug)]
+pub struct Records {
+  // number of steps to the records
+  depth : usize,
+  record : Option<Vec<Step>>,
+  builder : Builder,
+}
+
+impl Records {
+  /// Records are at depth, ie they're the values at paths with depth steps.
+  pub fn new(depth : usize) -> Self {
+    Self{depth, record: None, builder: Builder::default()}
+  }
+
+  /// Add the value at path, which returns the previous record when value is in the next one.
+  pub fn push(&mut self, path : &SendPath, value : JsonEvent<String>) -> Result<Option<Node>, String> {
+    let mut steps = path.steps();
+    let relative = steps.split_off(self.depth.min(steps.len()));
+    let previous = if self.record.as_ref() != Some(&steps) {
+      self.record = Some(steps);
+      self.builder.take()
+    } else {
+      None
+    };
+    self.builder.insert(&relative, value)?;
+    Ok(previous)
+  }
+
+  /// The last record, if it hasn't been returned yet.
+  pub fn finish(&mut self) -> Option<Node> {
+    self.record = None;
+    self.builder.take()
+  }
+}
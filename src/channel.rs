@@ -137,6 +137,8 @@ pub fn ringbuffer(jev : &mut dyn JsonEventSource<String>) {
     visitor.value(jev, JsonPath::new(), 0, &mut tx as &mut dyn sender::Sender<Event<SendValue>, Arc<Event<SendValue>>>).unwrap_or_else(|_| println!("uhoh"));
   }
 
+  // close the channel, otherwise the consumer waits forever
+  drop(tx);
   cons_thr.join().unwrap();
 }
 
@@ -175,5 +177,7 @@ pub fn channels(jev : &mut dyn JsonEventSource<String>) {
     visitor.value(jev, JsonPath::new(), 0, tx).unwrap_or_else(|_| println!("uhoh"));
   }
 
+  // close the channel, otherwise the consumer waits forever
+  drop(tx);
   cons_thr.join().unwrap();
 }
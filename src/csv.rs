@@ -0,0 +1,326 @@
+/*!
+Records as the rows of a CSV or TSV table, for spreadsheets.
+
+The records are the values at a path, eg `[]` for the elements of the top-level array. Each record
+is flattened, and its columns are named by their paths relative to the record in dotted notation,
+eg `actor.login`. A record that is a string or a number is in the column `.`.
+
+Nested arrays are one of:
+- json: the array as json in one column
+- join: the elements joined with a separator in one column, with arrays and objects as json
+- explode: a row for each element, with `[]` in the names of the columns. A record with several
+  arrays has a row for each combination of their elements.
+
+The header is given, or comes from a schema, which is usually collected from the input in a
+first pass.
+
+CSV is quoted as in RFC 4180. TSV can't quote, so tabs, newlines and backslashes are escaped with a
+backslash.
+*/
+
+use rustc_hash::FxHashMap;
+
+use crate::builder::Node;
+use crate::builder::Records;
+use crate::handler::Handler;
+use crate::jsonpath::JsonPath;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::pathformat;
+use crate::pathformat::Part;
+use crate::pathformat::PathFormat;
+use crate::projection::Pattern;
+use crate::projection::ProjectionWrapper;
+use crate::projection::Projector;
+use crate::schema::saved::SavedNode;
+use crate::sender::Event;
+use crate::sender::Sender;
+
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum Format {
+  #[default]
+  Csv,
+  Tsv,
+}
+
+/// What to do with arrays inside records.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum Arrays {
+  #[default]
+  Json,
+  Join,
+  Explode,
+}
+
+impl std::str::FromStr for Arrays {
+  type Err = String;
+
+  fn from_str(s : &str) -> Result<Self, Self::Err> {
+    match s {
+      "json" => Ok(Arrays::Json),
+      "join" => Ok(Arrays::Join),
+      "explode" => Ok(Arrays::Explode),
+      other => Err(format!("unknown arrays {other}, expected json, join or explode")),
+    }
+  }
+}
+
+/// The name of the column at parts, relative to the record.
+fn column_name(parts : &[Part]) -> String {
+  if parts.is_empty() { ".".into() } else { pathformat::write(parts.iter().cloned(), PathFormat::Dotted) }
+}
+
+/// Column names from a list like `id,actor.login`, written the same way as the names of flattened columns.
+pub fn column_names(list : &str) -> Result<Vec<String>, String> {
+  list.split(',').map(|column| Ok(column_name(&pathformat::read(column.trim(), PathFormat::Dotted)?))).collect()
+}
+
+/// The nodes of schema for the records at record.
+pub(crate) fn record_nodes<'s>(schema : &'s SavedNode, record : &[Part]) -> Vec<&'s SavedNode> {
+  use crate::schema::Step;
+  let Some((first, rest)) = record.split_first() else { return vec![schema] };
+  schema.children.iter()
+    .filter(|child| match (first, &child.step) {
+      (Part::Key(key), Some(Step::Key(step))) => key == step,
+      (Part::Any | Part::Index(_), Some(Step::Index)) | (Part::Any, Some(Step::Position(_))) => true,
+      (Part::Index(index), Some(Step::Position(position))) => index == position,
+      _ => false,
+    })
+    .flat_map(|child| record_nodes(child, rest))
+    .collect()
+}
+
+fn schema_columns(node : &SavedNode, parts : &mut Vec<Part<'static>>, arrays : Arrays, columns : &mut Vec<String>) {
+  use crate::schema::Step;
+  let add = |columns : &mut Vec<String>, name : String| if !columns.contains(&name) { columns.push(name) };
+  if !node.kinds.is_empty() { add(columns, column_name(parts)) }
+  for child in &node.children {
+    match (&child.step, arrays) {
+      (Some(Step::Key(key)), _) => {
+        parts.push(Part::Key(key.clone().into()));
+        schema_columns(child, parts, arrays, columns);
+        parts.pop();
+      }
+      (Some(Step::Index | Step::Position(_)), Arrays::Explode) => {
+        parts.push(Part::Any);
+        schema_columns(child, parts, arrays, columns);
+        parts.pop();
+      }
+      (Some(Step::Index | Step::Position(_)), _) => add(columns, column_name(parts)),
+      (None, _) => (),
+    }
+  }
+}
+
+/// The columns of the records at record, from a schema of the whole input, in the order they were first seen.
+pub fn columns(schema : &SavedNode, record : &[Part], arrays : Arrays) -> Result<Vec<String>, String> {
+  let mut columns = vec![];
+  for node in record_nodes(schema, record) {
+    schema_columns(node, &mut vec![], arrays, &mut columns);
+  }
+  if columns.is_empty() { return Err(format!("no columns for records at {}", pathformat::write(record.iter().cloned(), PathFormat::global()))) }
+  Ok(columns)
+}
+
+// A string, number or boolean as text, and null as nothing.
+fn text(value : &JsonEvent<String>) -> String {
+  match value {
+    JsonEvent::String(v) | JsonEvent::Number(v) => v.clone(),
+    JsonEvent::Boolean(v) => v.to_string(),
+    _ => String::new(),
+  }
+}
+
+type Row = Vec<(String, String)>;
+
+// The rows of node, with (column, text) for each cell.
+fn rows(node : &Node, parts : &mut Vec<Part<'static>>, arrays : Arrays, separator : &str) -> Vec<Row> {
+  match (node, arrays) {
+    (Node::Leaf(value), _) => vec![vec![(column_name(parts), text(value))]],
+    (Node::Object(object), _) => {
+      let mut product = vec![vec![]];
+      for (key, entry) in object.entries() {
+        parts.push(Part::Key(key.clone().into()));
+        let entry_rows = rows(entry, parts, arrays, separator);
+        parts.pop();
+        product = product.iter()
+          .flat_map(|row| entry_rows.iter().map(move |entry_row| row.iter().chain(entry_row).cloned().collect()))
+          .collect();
+      }
+      product
+    }
+    (Node::Array(_), Arrays::Json) => vec![vec![(column_name(parts), node.to_json())]],
+    (Node::Array(items), Arrays::Join) => {
+      let joined = items.values().map(|item| match item {
+        Node::Leaf(value) => text(value),
+        item => item.to_json(),
+      }).collect::<Vec<String>>().join(separator);
+      vec![vec![(column_name(parts), joined)]]
+    }
+    (Node::Array(items), Arrays::Explode) => {
+      parts.push(Part::Any);
+      let exploded = items.values().flat_map(|item| rows(item, parts, arrays, separator)).collect::<Vec<Row>>();
+      parts.pop();
+      // so the rest of the record is still there
+      if exploded.is_empty() { vec![vec![]] } else { exploded }
+    }
+  }
+}
+
+/// Writes each record from a Projector as rows.
+pub struct TableWriter<'w> {
+  wr : &'w mut dyn std::io::Write,
+  format : Format,
+  arrays : Arrays,
+  separator : String,
+  // the index of each column
+  columns : FxHashMap<String, usize>,
+  records : Records,
+}
+
+impl<'w> TableWriter<'w> {
+  /// Write the header. Records are at depth, ie they're the values at paths with depth steps.
+  pub fn new(wr : &'w mut dyn std::io::Write, format : Format, columns : &[String], depth : usize) -> std::io::Result<Self> {
+    let mut writer = Self{
+      wr,
+      format,
+      arrays: Arrays::default(),
+      separator: ";".into(),
+      columns: columns.iter().enumerate().map(|(i, column)| (column.clone(), i)).collect(),
+      records: Records::new(depth),
+    };
+    writer.row(columns)?;
+    Ok(writer)
+  }
+
+  /// What to do with arrays inside records, and the separator for Arrays::Join.
+  pub fn with_arrays(self, arrays : Arrays, separator : &str) -> Self {
+    Self{arrays, separator: separator.into(), ..self}
+  }
+
+  fn row(&mut self, cells : &[String]) -> std::io::Result<()> {
+    let (delimiter, end) = match self.format {
+      Format::Csv => (",", "\r\n"),
+      Format::Tsv => ("\t", "\n"),
+    };
+    for (i, cell) in cells.iter().enumerate() {
+      if i > 0 { self.wr.write_all(delimiter.as_bytes())? }
+      match self.format {
+        Format::Csv if cell.contains([',', '"', '\r', '\n']) => write!(self.wr, "\"{}\"", cell.replace('"', "\"\""))?,
+        Format::Csv => self.wr.write_all(cell.as_bytes())?,
+        Format::Tsv => {
+          let escaped = cell.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r");
+          self.wr.write_all(escaped.as_bytes())?
+        }
+      }
+    }
+    self.wr.write_all(end.as_bytes())
+  }
+
+  fn record(&mut self, node : Node) -> std::io::Result<()> {
+    for row in rows(&node, &mut vec![], self.arrays, &self.separator) {
+      let mut cells = vec![String::new(); self.columns.len()];
+      for (column, text) in row {
+        if let Some(&i) = self.columns.get(&column) { cells[i] = text }
+      }
+      self.row(&cells)?;
+    }
+    Ok(())
+  }
+
+  /// Write the rows of the last record.
+  pub fn flush(&mut self) -> std::io::Result<()> {
+    match self.records.finish() {
+      Some(node) => self.record(node),
+      None => Ok(()),
+    }
+  }
+}
+
+impl Sender<Event<JsonEvent<String>>, ProjectionWrapper> for TableWriter<'_> {
+  fn send(&mut self, ev : ProjectionWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    match ev.as_ref() {
+      Event::Value(path, value) => match self.records.push(path, value.clone())? {
+        Some(node) => Ok(self.record(node)?),
+        None => Ok(()),
+      },
+      Event::Error(path, err) => Err(format!("{err} at path '{path}'").into()),
+      Event::Finished => Ok(self.flush()?),
+      Event::Path(..) => Ok(()),
+    }
+  }
+}
+
+/// Write the records at record as a table with columns.
+pub fn table(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, record : &[Part], columns : &[String], format : Format, arrays : Arrays, separator : &str)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let mut writer = TableWriter::new(wr, format, columns, record.len())?.with_arrays(arrays, separator);
+  Projector{patterns: vec![Pattern::from(record)]}.value(jev, JsonPath::new(), 0, &mut writer)?;
+  Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod test_csv {
+  use super::*;
+
+  const JSON : &str = r#"[
+  {"id": 1, "actor": {"login": "a,b"}, "tags": ["x", "y"], "commits": [{"sha": "s1"}, {"sha": "s2"}]},
+  {"id": 2, "actor": {"login": "say \"hi\""}, "tags": [], "commits": [], "extra": null},
+  {"id": 3, "tags": [["n"]], "note": "tab\there\nnewline"}
+]"#;
+
+  fn schema_columns_of(record : &str, arrays : Arrays) -> Vec<String> {
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(JSON.as_bytes()));
+    let collector = crate::schema::collect(&mut jevstream, crate::schema::SchemaCollector::new()).unwrap();
+    let record = pathformat::read(record, PathFormat::Slash).unwrap();
+    columns(&SavedNode::from(&collector), &record, arrays).unwrap()
+  }
+
+  fn table_of(record : &str, columns : &[String], format : Format, arrays : Arrays) -> String {
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(JSON.as_bytes()));
+    let record = pathformat::read(record, PathFormat::Slash).unwrap();
+    let mut out = vec![];
+    table(&mut out, &mut jevstream, &record, columns, format, arrays, "|").unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn header_from_schema() {
+    assert_eq!(schema_columns_of("[]", Arrays::Json), vec!["id", "actor.login", "tags", "commits", "extra", "note"]);
+    assert_eq!(schema_columns_of("[]", Arrays::Explode), vec!["id", "actor.login", "tags[]", "tags[][]", "commits[].sha", "extra", "note"]);
+    assert_eq!(schema_columns_of("[]/commits/[]", Arrays::Json), vec!["sha"]);
+    assert!(column_names("id,[\"a,b\"]").is_err());
+    assert_eq!(column_names("id, actor[\"login\"],.").unwrap(), vec!["id", "actor.login", "."]);
+  }
+
+  #[test]
+  fn json_arrays() {
+    let columns = schema_columns_of("[]", Arrays::Json);
+    assert_eq!(table_of("[]", &columns, Format::Csv, Arrays::Json),
+      "id,actor.login,tags,commits,extra,note\r\n\
+      1,\"a,b\",\"[\"\"x\"\",\"\"y\"\"]\",\"[{\"\"sha\"\":\"\"s1\"\"},{\"\"sha\"\":\"\"s2\"\"}]\",,\r\n\
+      2,\"say \"\"hi\"\"\",[],[],,\r\n\
+      3,,\"[[\"\"n\"\"]]\",,,\"tab\there\nnewline\"\r\n");
+  }
+
+  #[test]
+  fn joined_and_tsv() {
+    let columns = column_names("id,tags,note").unwrap();
+    assert_eq!(table_of("[]", &columns, Format::Tsv, Arrays::Join),
+      "id\ttags\tnote\n1\tx|y\t\n2\t\t\n3\t[\"n\"]\ttab\\there\\nnewline\n");
+  }
+
+  #[test]
+  fn exploded() {
+    let columns = column_names("id,tags[],commits[].sha").unwrap();
+    assert_eq!(table_of("[]", &columns, Format::Csv, Arrays::Explode),
+      "id,tags[],commits[].sha\r\n1,x,s1\r\n1,x,s2\r\n1,y,s1\r\n1,y,s2\r\n2,,\r\n3,,\r\n");
+  }
+
+  #[test]
+  fn nested_records() {
+    let columns = column_names(".").unwrap();
+    assert_eq!(table_of("[]/tags/[]", &columns, Format::Csv, Arrays::Json), ".\r\nx\r\ny\r\n\"[\"\"n\"\"]\"\r\n");
+  }
+}
@@ -0,0 +1,498 @@
+/*!
+Records as Parquet, with the repetition and definition levels of the dremel paper, so that nested
+arrays and objects, missing fields and nulls are all kept.
+
+The parquet schema comes from a schema of the input, usually collected in a first pass:
+- an object becomes a group with a field for each key
+- an array becomes a LIST, with the elements of all its indexes and positions as the element
+- a leaf becomes a BOOLEAN, INT64, DOUBLE or STRING column, or JSON when its values have more than
+  one kind, or when the same path is both a value and a container, or both an array and an object.
+  Negative numbers along with unsigned ones too big for an INT64 are JSON too, because a DOUBLE
+  would lose precision.
+
+Every field is optional, so that a missing field and null are both a definition level below the
+field. The two can't be told apart when reading back. Empty arrays and objects are kept, because an
+empty list is defined one level deeper than a missing one.
+
+When the records are objects, their keys are the top-level columns. Otherwise each record is in the
+column `value`.
+*/
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use parquet::basic::Compression;
+use parquet::basic::LogicalType;
+use parquet::basic::Repetition;
+use parquet::basic::Type as PhysicalType;
+use parquet::data_type::BoolType;
+use parquet::data_type::ByteArray;
+use parquet::data_type::ByteArrayType;
+use parquet::data_type::DoubleType;
+use parquet::data_type::Int64Type;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use parquet::schema::types::TypePtr;
+
+use crate::builder::Node;
+use crate::builder::Records;
+use crate::handler::Handler;
+use crate::jsonpath::JsonPath;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::pathformat;
+use crate::pathformat::Part;
+use crate::pathformat::PathFormat;
+use crate::projection::Pattern;
+use crate::projection::ProjectionWrapper;
+use crate::projection::Projector;
+use crate::schema::NumberType;
+use crate::schema::SchemaType;
+use crate::schema::saved::SavedNode;
+use crate::sender::Event;
+use crate::sender::Sender;
+
+/// Records in each row group. Each column holds that many records in memory before it's written.
+const ROW_GROUP_RECORDS : usize = 100_000;
+
+/// The type of a leaf column.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Kind {
+  Boolean,
+  Int64,
+  /// unsigned numbers that don't all fit in an i64
+  UInt64,
+  Double,
+  String,
+  /// anything, as json text
+  Json,
+}
+
+impl Kind {
+  // The kind of column for values of all of kinds.
+  fn of<'k>(kinds : impl Iterator<Item = &'k SchemaType>) -> Kind {
+    let (mut booleans, mut strings, mut numbers) = (false, false, false);
+    let (mut signed, mut float, mut unsigned_max) = (false, false, 0);
+    for kind in kinds {
+      match kind {
+        SchemaType::Boolean => booleans = true,
+        SchemaType::String(_) | SchemaType::Unknown(_) => strings = true,
+        SchemaType::Number(number) => {
+          numbers = true;
+          match number {
+            NumberType::Unsigned(max) => unsigned_max = unsigned_max.max(*max),
+            NumberType::Signed(..) => signed = true,
+            NumberType::Float(..) => float = true,
+          }
+        }
+        SchemaType::Null => (),
+      }
+    }
+    match (booleans, strings, numbers) {
+      (true, false, false) => Kind::Boolean,
+      (false, false, true) if float => Kind::Double,
+      (false, false, true) if unsigned_max <= i64::MAX as u64 => Kind::Int64,
+      // neither INT64 nor a double holds all of them exactly, but their json text does
+      (false, false, true) if signed => Kind::Json,
+      (false, false, true) => Kind::UInt64,
+      // only nulls
+      (false, _, false) => Kind::String,
+      _ => Kind::Json,
+    }
+  }
+
+  fn primitive(self, name : &str) -> parquet::errors::Result<Type> {
+    let (physical, logical) = match self {
+      Kind::Boolean => (PhysicalType::BOOLEAN, None),
+      Kind::Int64 => (PhysicalType::INT64, None),
+      Kind::UInt64 => (PhysicalType::INT64, Some(LogicalType::Integer{bit_width: 64, is_signed: false})),
+      Kind::Double => (PhysicalType::DOUBLE, None),
+      Kind::String => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+      Kind::Json => (PhysicalType::BYTE_ARRAY, Some(LogicalType::Json)),
+    };
+    Type::primitive_type_builder(name, physical)
+      .with_repetition(Repetition::OPTIONAL)
+      .with_logical_type(logical)
+      .build()
+  }
+}
+
+#[derive(Debug)]
+enum FieldKind {
+  Leaf(Kind),
+  Group(Vec<(String, Field)>),
+  /// the element
+  List(Box<Field>),
+}
+
+/// A field of the parquet schema, with the leaf columns under it.
+#[derive(Debug)]
+struct Field {
+  /// relative to the record, for errors
+  path : String,
+  /// the leaf columns under this field, which are numbered in document order
+  columns : Range<usize>,
+  kind : FieldKind,
+}
+
+impl Field {
+  // The field for the values at all of nodes, whose leaves are numbered from kinds.len().
+  fn of(nodes : &[&SavedNode], parts : &mut Vec<Part<'static>>, kinds : &mut Vec<Kind>) -> Field {
+    use crate::schema::Step;
+    let first = kinds.len();
+    let leaves = nodes.iter().flat_map(|node| node.kinds.iter()).filter(|kind| kind.kind != SchemaType::Null);
+    let children = nodes.iter().flat_map(|node| node.children.iter());
+    let has_keys = children.clone().any(|child| matches!(child.step, Some(Step::Key(_))));
+    let has_elements = children.clone().any(|child| matches!(child.step, Some(Step::Index | Step::Position(_))));
+
+    let kind = match (leaves.clone().next().is_some(), has_keys, has_elements) {
+      (false, true, false) => {
+        // keys in the order they were first seen
+        let mut keys : Vec<(String, Vec<&SavedNode>)> = vec![];
+        for child in children {
+          let Some(Step::Key(key)) = &child.step else { continue };
+          match keys.iter_mut().find(|(seen, _)| seen == key) {
+            Some((_, nodes)) => nodes.push(child),
+            None => keys.push((key.clone(), vec![child])),
+          }
+        }
+        FieldKind::Group(keys.into_iter().map(|(key, nodes)| {
+          parts.push(Part::Key(key.clone().into()));
+          let field = Field::of(&nodes, parts, kinds);
+          parts.pop();
+          (key, field)
+        }).collect())
+      }
+      (false, false, true) => {
+        let elements = children.collect::<Vec<&SavedNode>>();
+        parts.push(Part::Any);
+        let element = Field::of(&elements, parts, kinds);
+        parts.pop();
+        FieldKind::List(Box::new(element))
+      }
+      // only nulls, which are strings that are never there
+      (false, false, false) if nodes.iter().all(|node| node.container.is_none()) => FieldKind::Leaf(Kind::String),
+      // empty containers, or both kinds of container, or values and containers
+      (false, _, _) | (true, true, _) | (true, _, true) => FieldKind::Leaf(Kind::Json),
+      (true, false, false) => FieldKind::Leaf(Kind::of(leaves.map(|kind| &kind.kind))),
+    };
+    if let FieldKind::Leaf(kind) = kind { kinds.push(kind) }
+    let path = if parts.is_empty() { ".".into() } else { pathformat::write(parts.iter().cloned(), PathFormat::Dotted) };
+    Field{path, columns: first..kinds.len(), kind}
+  }
+
+  fn parquet_type(&self, name : &str) -> parquet::errors::Result<TypePtr> {
+    let field = match &self.kind {
+      FieldKind::Leaf(kind) => kind.primitive(name)?,
+      FieldKind::Group(fields) => Type::group_type_builder(name)
+        .with_repetition(Repetition::OPTIONAL)
+        .with_fields(fields.iter().map(|(key, field)| field.parquet_type(key)).collect::<parquet::errors::Result<_>>()?)
+        .build()?,
+      // the three levels of the LIST annotation
+      FieldKind::List(element) => Type::group_type_builder(name)
+        .with_repetition(Repetition::OPTIONAL)
+        .with_logical_type(Some(LogicalType::List))
+        .with_fields(vec![Arc::new(Type::group_type_builder("list")
+          .with_repetition(Repetition::REPEATED)
+          .with_fields(vec![element.parquet_type("element")?])
+          .build()?)])
+        .build()?,
+    };
+    Ok(Arc::new(field))
+  }
+}
+
+#[derive(Debug)]
+enum Values {
+  Boolean(Vec<bool>),
+  Int64(Vec<i64>),
+  Double(Vec<f64>),
+  Bytes(Vec<ByteArray>),
+}
+
+/// The values of a leaf column that haven't been written yet, with their levels.
+///
+/// There's a level for each value and for each null, but only the values are in values.
+#[derive(Debug)]
+struct Column {
+  kind : Kind,
+  values : Values,
+  definitions : Vec<i16>,
+  repetitions : Vec<i16>,
+}
+
+impl Column {
+  fn new(kind : Kind) -> Self {
+    let values = match kind {
+      Kind::Boolean => Values::Boolean(vec![]),
+      Kind::Int64 | Kind::UInt64 => Values::Int64(vec![]),
+      Kind::Double => Values::Double(vec![]),
+      Kind::String | Kind::Json => Values::Bytes(vec![]),
+    };
+    Self{kind, values, definitions: vec![], repetitions: vec![]}
+  }
+
+  fn null(&mut self, repetition : i16, definition : i16) {
+    self.repetitions.push(repetition);
+    self.definitions.push(definition);
+  }
+
+  fn push(&mut self, node : &Node, repetition : i16, definition : i16) -> Result<(), String> {
+    let doesnt_fit = || format!("is {}, which isn't {:?}", node.to_json(), self.kind);
+    match (&mut self.values, self.kind, node) {
+      (Values::Bytes(values), Kind::Json, node) => values.push(node.to_json().into_bytes().into()),
+      (Values::Boolean(values), _, Node::Leaf(JsonEvent::Boolean(v))) => values.push(*v),
+      (Values::Int64(values), Kind::Int64, Node::Leaf(JsonEvent::Number(v))) => values.push(v.parse().map_err(|_| doesnt_fit())?),
+      // bit for bit, as the UINT_64 annotation says
+      (Values::Int64(values), _, Node::Leaf(JsonEvent::Number(v))) => values.push(v.parse::<u64>().map_err(|_| doesnt_fit())? as i64),
+      (Values::Double(values), _, Node::Leaf(JsonEvent::Number(v))) => values.push(v.parse().map_err(|_| doesnt_fit())?),
+      (Values::Bytes(values), _, Node::Leaf(JsonEvent::String(v))) => values.push(v.clone().into_bytes().into()),
+      _ => return Err(doesnt_fit()),
+    }
+    self.null(repetition, definition);
+    Ok(())
+  }
+
+  fn clear(&mut self) {
+    *self = Column::new(self.kind)
+  }
+}
+
+/// Split node into columns, at the repetition and definition levels of field.
+///
+/// repetition is for the first value, definition is the level of whatever holds the field, and
+/// lists is the number of lists around the field, ie the repetition level of its own values.
+fn shred(field : &Field, node : Option<&Node>, repetition : i16, definition : i16, lists : i16, columns : &mut [Column]) -> Result<(), String> {
+  let node = node.filter(|node| !matches!(node, Node::Leaf(JsonEvent::Null)));
+  match (&field.kind, node) {
+    (_, None) => columns[field.columns.clone()].iter_mut().for_each(|column| column.null(repetition, definition)),
+    (FieldKind::Leaf(_), Some(node)) => columns[field.columns.start].push(node, repetition, definition + 1)
+      .map_err(|err| format!("{} {err}", field.path))?,
+    (FieldKind::Group(fields), Some(Node::Object(object))) => for (key, child) in fields {
+      shred(child, object.get(key), repetition, definition + 1, lists, columns)?
+    }
+    (FieldKind::List(element), Some(Node::Array(items))) => {
+      // the list is there, but the repeated group isn't
+      if items.is_empty() { columns[field.columns.clone()].iter_mut().for_each(|column| column.null(repetition, definition + 1)) }
+      for (i, item) in items.values().enumerate() {
+        shred(element, Some(item), if i == 0 { repetition } else { lists + 1 }, definition + 2, lists + 1, columns)?
+      }
+    }
+    (_, Some(node)) => return Err(format!("{} is {}, which doesn't fit the schema", field.path, node.to_json())),
+  }
+  Ok(())
+}
+
+/// Writes the records selected by a Projector as parquet.
+pub struct ParquetWriter<W : std::io::Write + Send> {
+  file : SerializedFileWriter<W>,
+  /// the top-level columns, which are the keys of the records, or `value`
+  fields : Vec<(String, Field)>,
+  /// whether the records are in value rather than split by key
+  wrapped : bool,
+  columns : Vec<Column>,
+  records : Records,
+  // in the current row group
+  count : usize,
+}
+
+impl<W : std::io::Write + Send> ParquetWriter<W> {
+  /// The records are at depth, and nodes are the schema of all of them.
+  pub fn new(wr : W, nodes : &[&SavedNode], depth : usize) -> Result<Self, Box<dyn std::error::Error>> {
+    if nodes.is_empty() { return Err("the schema has no records".into()) }
+    let mut kinds = vec![];
+    let root = Field::of(nodes, &mut vec![], &mut kinds);
+    let (fields, wrapped) = match root.kind {
+      FieldKind::Group(fields) => (fields, false),
+      _ => (vec![("value".into(), root)], true),
+    };
+    let message = Type::group_type_builder("schema")
+      .with_fields(fields.iter().map(|(key, field)| field.parquet_type(key)).collect::<parquet::errors::Result<_>>()?)
+      .build()?;
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    Ok(Self {
+      file: SerializedFileWriter::new(wr, Arc::new(message), Arc::new(properties))?,
+      fields,
+      wrapped,
+      columns: kinds.into_iter().map(Column::new).collect(),
+      records: Records::new(depth),
+      count: 0,
+    })
+  }
+
+  fn record(&mut self, node : Node) -> Result<(), Box<dyn std::error::Error>> {
+    match (&node, self.wrapped) {
+      (_, true) => shred(&self.fields[0].1, Some(&node), 0, 0, 0, &mut self.columns)?,
+      (Node::Object(object), false) => for (key, field) in &self.fields {
+        shred(field, object.get(key), 0, 0, 0, &mut self.columns)?
+      }
+      (Node::Leaf(JsonEvent::Null), false) => self.columns.iter_mut().for_each(|column| column.null(0, 0)),
+      (_, false) => return Err(format!("record {} isn't an object", node.to_json()).into()),
+    }
+    self.count += 1;
+    if self.count >= ROW_GROUP_RECORDS { self.write_row_group()? }
+    Ok(())
+  }
+
+  fn write_row_group(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if self.count == 0 { return Ok(()) }
+    let mut row_group = self.file.next_row_group()?;
+    for column in &mut self.columns {
+      let mut writer = row_group.next_column()?.ok_or("more columns than the parquet schema")?;
+      let (definitions, repetitions) = (Some(&column.definitions[..]), Some(&column.repetitions[..]));
+      match &column.values {
+        Values::Boolean(values) => writer.typed::<BoolType>().write_batch(values, definitions, repetitions)?,
+        Values::Int64(values) => writer.typed::<Int64Type>().write_batch(values, definitions, repetitions)?,
+        Values::Double(values) => writer.typed::<DoubleType>().write_batch(values, definitions, repetitions)?,
+        Values::Bytes(values) => writer.typed::<ByteArrayType>().write_batch(values, definitions, repetitions)?,
+      };
+      writer.close()?;
+      column.clear();
+    }
+    row_group.close()?;
+    self.count = 0;
+    Ok(())
+  }
+
+  /// Write the last record and the footer.
+  pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(node) = self.records.finish() { self.record(node)? }
+    self.write_row_group()?;
+    self.file.close()?;
+    Ok(())
+  }
+}
+
+impl<W : std::io::Write + Send> Sender<Event<JsonEvent<String>>, ProjectionWrapper> for ParquetWriter<W> {
+  fn send(&mut self, ev : ProjectionWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    match ev.as_ref() {
+      Event::Value(path, value) => match self.records.push(path, value.clone())? {
+        Some(node) => self.record(node),
+        None => Ok(()),
+      },
+      Event::Error(path, err) => Err(format!("{err} at path '{path}'").into()),
+      Event::Finished | Event::Path(..) => Ok(()),
+    }
+  }
+}
+
+/// Write the records at record as parquet, with columns from schema, which is a schema of the whole input.
+pub fn parquet<W : std::io::Write + Send>(wr : W, jev : &mut dyn JsonEventSource<String>, record : &[Part], schema : &SavedNode)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let nodes = crate::csv::record_nodes(schema, record);
+  let mut writer = ParquetWriter::new(wr, &nodes, record.len())
+    .map_err(|err| format!("{err} at {}", pathformat::write(record.iter().cloned(), PathFormat::global())))?;
+  Projector{patterns: vec![Pattern::from(record)]}.value(jev, JsonPath::new(), 0, &mut writer)?;
+  writer.finish()
+}
+
+#[cfg(test)]
+mod test_dremel {
+  use super::*;
+  use parquet::file::reader::FileReader;
+  use parquet::file::reader::SerializedFileReader;
+
+  const JSON : &str = r#"[
+  {"id": 1, "actor": {"login": "a"}, "commits": [{"sha": "s1", "files": ["x", "y"]}, {"sha": "s2", "files": []}], "n": 1.5},
+  {"id": 2, "commits": [], "n": 2, "mixed": "m"},
+  {"id": 3, "actor": null, "commits": [{"files": ["z"]}, {"sha": "s4"}], "mixed": 3, "nested": [[1, 2], [], [3]]}
+]"#;
+
+  // a reader of json as parquet
+  fn reader_of(json : &'static str, record : &str, name : &str) -> SerializedFileReader<std::fs::File> {
+    let record = pathformat::read(record, PathFormat::Slash).unwrap();
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = crate::schema::collect(&mut jevstream, crate::schema::SchemaCollector::new()).unwrap();
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(json.as_bytes()));
+    let filename = std::env::temp_dir().join(format!("jch-test-{}-{name}.parquet", std::process::id()));
+    parquet(std::fs::File::create(&filename).unwrap(), &mut jevstream, &record, &SavedNode::from(&collector)).unwrap();
+    let reader = SerializedFileReader::new(std::fs::File::open(&filename).unwrap()).unwrap();
+    // the open file can still be read
+    std::fs::remove_file(&filename).unwrap();
+    reader
+  }
+
+  fn records_of(json : &'static str, record : &str, name : &str) -> Vec<serde_json::Value> {
+    reader_of(json, record, name).get_row_iter(None).unwrap().map(|row| row.unwrap().to_json_value()).collect()
+  }
+
+  // a field of group by name
+  fn field<'t>(group : &'t Type, name : &str) -> &'t Type {
+    group.get_fields().iter().find(|field| field.name() == name).unwrap_or_else(|| panic!("no field {name}"))
+  }
+
+  #[test]
+  fn nested_arrays_of_objects() {
+    let rows = records_of(JSON, "[]", "nested");
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0], serde_json::json!({"id": 1, "actor": {"login": "a"},
+      "commits": [{"sha": "s1", "files": ["x", "y"]}, {"sha": "s2", "files": []}],
+      "n": 1.5, "mixed": null, "nested": null}));
+    assert_eq!(rows[1]["commits"], serde_json::json!([]));
+    assert_eq!(rows[1]["actor"], serde_json::Value::Null);
+    assert_eq!(rows[2]["commits"], serde_json::json!([{"sha": null, "files": ["z"]}, {"sha": "s4", "files": null}]));
+    assert_eq!(rows[2]["nested"], serde_json::json!([[1, 2], [], [3]]));
+  }
+
+  #[test]
+  fn mixed_kinds_are_json() {
+    let rows = records_of(JSON, "[]", "mixed");
+    assert_eq!(rows[1]["mixed"], serde_json::json!("\"m\""));
+    assert_eq!(rows[2]["mixed"], serde_json::json!("3"));
+    assert_eq!(rows[1]["n"], serde_json::json!(2.0));
+  }
+
+  #[test]
+  fn values_that_arent_objects() {
+    let rows = records_of(r#"[[1, null], "a", {"b": 2}]"#, "[]", "values");
+    assert_eq!(rows, vec![
+      serde_json::json!({"value": "[1,null]"}),
+      serde_json::json!({"value": "\"a\""}),
+      serde_json::json!({"value": "{\"b\":2}"}),
+    ]);
+    let rows = records_of(r#"{"xs": [1, 2, 3]}"#, "xs/[]", "xs");
+    assert_eq!(rows, vec![serde_json::json!({"value": 1}), serde_json::json!({"value": 2}), serde_json::json!({"value": 3})]);
+  }
+
+  #[test]
+  fn big_unsigned_and_signed() {
+    let rows = records_of(r#"[{"n": -1}, {"n": 18446744073709551615}]"#, "[]", "big");
+    assert_eq!(rows, vec![serde_json::json!({"n": "-1"}), serde_json::json!({"n": "18446744073709551615"})]);
+    let rows = records_of(r#"[{"n": 1}, {"n": 18446744073709551615}]"#, "[]", "unsigned");
+    assert_eq!(rows[1], serde_json::json!({"n": 18446744073709551615u64}));
+  }
+
+  // The layout of the LIST annotation in the parquet spec, which is what duckdb, pyarrow and spark read as lists:
+  // <optional> group <name> (LIST) { repeated group list { <optional> <element-type> element; } }
+  #[test]
+  fn list_layout() {
+    let reader = reader_of(JSON, "[]", "layout");
+    let schema = reader.metadata().file_metadata().schema();
+    let list_of = |field : &Type| {
+      let info = field.get_basic_info();
+      assert_eq!(info.logical_type(), Some(LogicalType::List), "{}", field.name());
+      assert_eq!(info.repetition(), Repetition::OPTIONAL, "{}", field.name());
+      assert_eq!(field.get_fields().len(), 1, "{}", field.name());
+      let list = &field.get_fields()[0];
+      assert_eq!((list.name(), list.get_basic_info().repetition(), list.get_fields().len()), ("list", Repetition::REPEATED, 1));
+      let element = list.get_fields()[0].clone();
+      assert_eq!((element.name(), element.get_basic_info().repetition()), ("element", Repetition::OPTIONAL));
+      element
+    };
+
+    let commit = list_of(field(schema, "commits"));
+    assert!(commit.is_group());
+    let file = list_of(field(&commit, "files"));
+    assert_eq!((file.get_physical_type(), file.get_basic_info().logical_type()), (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)));
+    // a list of lists
+    let inner = list_of(field(schema, "nested"));
+    let number = list_of(&inner);
+    assert_eq!(number.get_physical_type(), PhysicalType::INT64);
+    // and the leaf levels that follow from that
+    let descriptor = reader.metadata().file_metadata().schema_descr();
+    let sha = descriptor.columns().iter().find(|column| column.path().string() == "commits.list.element.sha").unwrap();
+    assert_eq!((sha.max_def_level(), sha.max_rep_level()), (4, 1));
+  }
+}
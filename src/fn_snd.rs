@@ -1,5 +1,7 @@
 //! Mimimal implementation for a Sender to have a function which receives the events.
 
+use crate::pathformat;
+use crate::pathformat::PathFormat;
 use crate::sender;
 use crate::sender::Event;
 use crate::sender::Sender;
@@ -16,3 +18,40 @@ impl<SendValue: Send> Sender<Event<SendValue>,sender::NonWrap<Event<SendValue>>>
     self.0(ev.as_ref())
   }
 }
+
+/// How -p and -v print each event, ie one per line, with the path in format.
+pub fn line<SendValue : std::fmt::Debug>(ev : &Event<SendValue>, format : PathFormat) -> String {
+  let written = |path : &crate::sendpath::SendPath| pathformat::write(path.steps().iter().map(Into::into), format);
+  match ev {
+    Event::Path(depth, path) => format!("Path({depth}, {})", written(path)),
+    Event::Value(path, value) => format!("Value({}, {value:?})", written(path)),
+    Event::Finished => "Finished".into(),
+    Event::Error(path, err) => format!("Error({}, {err:?})", written(path)),
+  }
+}
+
+#[cfg(test)]
+mod test_fn_snd {
+  use super::*;
+  use crate::jsonpath::Step;
+  use crate::parser::JsonEvent;
+  use crate::sendpath::SendPath;
+
+  #[test]
+  fn line_has_only_the_steps() {
+    let path = SendPath::from(&[Step::Key("a".into()), Step::Key("b".into()), Step::Index(0)][..]);
+    assert_eq!(line(&Event::Value(path.clone(), JsonEvent::Number("1")), PathFormat::Slash), r#"Value(a/b/[0], Number("1"))"#);
+    assert_eq!(line(&Event::<()>::Path(1, path.clone()), PathFormat::Slash), "Path(1, a/b/[0])");
+    assert_eq!(line(&Event::<()>::Error(path, "bad".into()), PathFormat::Slash), r#"Error(a/b/[0], "bad")"#);
+    assert_eq!(line(&Event::<()>::Finished, PathFormat::Slash), "Finished");
+  }
+
+  #[test]
+  fn line_in_path_format() {
+    let path = SendPath::from(&[Step::Key("a".into()), Step::Key("q\"".into()), Step::Index(0)][..]);
+    let ev = Event::Value(path, JsonEvent::Boolean::<&str>(true));
+    assert_eq!(line(&ev, PathFormat::Pointer), "Value(/a/q\"/0, Boolean(true))");
+    assert_eq!(line(&ev, PathFormat::Jq), r#"Value(["a","q\"",0], Boolean(true))"#);
+    assert_eq!(line(&ev, PathFormat::Dotted), r#"Value(a["q\""][0], Boolean(true))"#);
+  }
+}
@@ -0,0 +1,184 @@
+/*!
+The format of gron, ie one javascript assignment per leaf, so that json can be grepped.
+
+```text
+json = {};
+json.actor = {};
+json.actor.login = "foo";
+json.tags = [];
+json.tags[0] = "x";
+json["created at"] = "2024-01-01";
+```
+
+The paths are the dotted notation after `json`. The assignments are in the order of the input,
+unlike gron which sorts them.
+
+ungron goes the other way. The assignments can be in any order, and some of them can be missing,
+because the lines have usually been through grep. So they're collected by a Builder first, and
+written once the input has been read.
+*/
+
+use crate::builder::Builder;
+use crate::builder::Conflict;
+use crate::handler::Handler;
+use crate::jsonpath::JsonPath;
+use crate::jsonpath::Step;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::pathformat;
+use crate::pathformat::PathFormat;
+use crate::projection::JsonWriter;
+use crate::projection::Pattern;
+use crate::projection::Positions;
+use crate::projection::ProjectionWrapper;
+use crate::projection::Projector;
+use crate::sender::Event;
+use crate::sender::Sender;
+
+/// The left side of an assignment, eg `json.actor.login`
+fn statement_path(steps : &[Step]) -> String {
+  let dotted = pathformat::write(steps.iter().map(Into::into), PathFormat::Dotted);
+  if dotted.is_empty() || dotted.starts_with('[') { format!("json{dotted}") } else { format!("json.{dotted}") }
+}
+
+/// Writes the values from a Projector as gron assignments.
+pub struct GronWriter<'w>(pub &'w mut dyn std::io::Write);
+
+impl Sender<Event<JsonEvent<String>>, ProjectionWrapper> for GronWriter<'_> {
+  fn send(&mut self, ev : ProjectionWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    match ev.as_ref() {
+      Event::Value(path, value) => {
+        write!(self.0, "{} = ", statement_path(&path.steps()))?;
+        match value {
+          JsonEvent::StartArray => self.0.write_all(b"[]")?,
+          JsonEvent::StartObject => self.0.write_all(b"{}")?,
+          scalar => crate::projection::write_scalar(self.0, scalar)?,
+        }
+        Ok(self.0.write_all(b";\n")?)
+      }
+      Event::Error(path, err) => Err(format!("{err} at path '{path}'").into()),
+      Event::Finished | Event::Path(..) => Ok(()),
+    }
+  }
+}
+
+/// Write the parts of the input selected by any of patterns as gron assignments.
+pub fn gron(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, patterns : Vec<Pattern>)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  Projector{patterns}.value(jev, JsonPath::new(), 0, &mut GronWriter(wr))
+}
+
+/// The steps and the value of an assignment like `json.actor.login = "foo";`
+fn read_statement(line : &str) -> Result<(Vec<Step>, JsonEvent<String>), String> {
+  let rst = line.strip_prefix("json").ok_or("should start with json")?;
+  // the path ends at the first = that isn't in a quoted key
+  let mut quoted = false;
+  let mut escaped = false;
+  let equals = rst.char_indices().find(|&(_, c)| {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if quoted => escaped = true,
+      '"' => quoted = !quoted,
+      '=' if !quoted => return true,
+      _ => (),
+    }
+    false
+  }).map(|(pos, _)| pos).ok_or("there's no =")?;
+
+  let steps = pathformat::read(rst[..equals].trim_end(), PathFormat::Dotted)?
+    .into_iter()
+    .map(Step::try_from)
+    .collect::<Result<Vec<Step>, _>>()?;
+  let text = rst[equals + 1..].trim();
+  let text = text.strip_suffix(';').unwrap_or(text).trim_end();
+  let value = match serde_json::from_str(text).map_err(|err| format!("{text}: {err}"))? {
+    serde_json::Value::Null => JsonEvent::Null,
+    serde_json::Value::Bool(v) => JsonEvent::Boolean(v),
+    // as written, rather than as serde_json would write it again
+    serde_json::Value::Number(_) => JsonEvent::Number(text.to_string()),
+    serde_json::Value::String(v) => JsonEvent::String(v),
+    serde_json::Value::Array(items) if items.is_empty() => JsonEvent::StartArray,
+    serde_json::Value::Object(entries) if entries.is_empty() => JsonEvent::StartObject,
+    _ => return Err(format!("{text} should be a value, [] or {{}}")),
+  };
+  Ok((steps, value))
+}
+
+/// Read gron assignments and write the json they came from.
+///
+/// Elements missing from arrays are replaced by null with Positions::Preserve, like gron does, which
+/// keeps the others at their indexes. Positions::Compact leaves them out.
+pub fn ungron(wr : &mut dyn std::io::Write, rd : Box<dyn std::io::BufRead>, positions : Positions)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let mut builder = Builder::default();
+  for (number, line) in std::io::BufRead::lines(rd).enumerate() {
+    let line = line?;
+    if line.trim().is_empty() { continue }
+    let (steps, value) = read_statement(line.trim()).map_err(|err| format!("line {}: {err}", number + 1))?;
+    builder.insert(&steps, value).map_err(|Conflict{steps, reason}| format!("line {}: {} {reason}", number + 1, statement_path(&steps)))?;
+  }
+  builder.send(&mut JsonWriter::new(wr, positions))
+}
+
+#[cfg(test)]
+mod test_gron {
+  use super::*;
+
+  const JSON : &str = r#"{"actor":{"login":"foo"},"tags":["x",[]],"created at":"a\"b","n":1.50,"ok":true,"none":null,"e":{}}"#;
+
+  const GRON : &str = r#"json = {};
+json.actor = {};
+json.actor.login = "foo";
+json.tags = [];
+json.tags[0] = "x";
+json.tags[1] = [];
+json["created at"] = "a\"b";
+json.n = 1.50;
+json.ok = true;
+json.none = null;
+json.e = {};
+"#;
+
+  fn ungron_of(gron : &'static str, positions : Positions) -> Result<String, Box<dyn std::error::Error>> {
+    let mut out = vec![];
+    ungron(&mut out, Box::new(gron.as_bytes()), positions)?;
+    Ok(String::from_utf8(out)?)
+  }
+
+  #[test]
+  fn write() {
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(JSON.as_bytes()));
+    let mut out = vec![];
+    gron(&mut out, &mut jevstream, vec!["$".parse().unwrap()]).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), GRON);
+  }
+
+  #[test]
+  fn round_trip() {
+    assert_eq!(ungron_of(GRON, Positions::Compact).unwrap(), JSON.to_string() + "\n");
+  }
+
+  #[test]
+  fn unsorted_and_missing() {
+    let gron = "json.b[3].c = 2;\njson.a = \"=\";\njson.b[1] = 1;\n";
+    assert_eq!(ungron_of(gron, Positions::Compact).unwrap(), "{\"b\":[1,{\"c\":2}],\"a\":\"=\"}\n");
+    assert_eq!(ungron_of(gron, Positions::Preserve).unwrap(), "{\"b\":[null,1,null,{\"c\":2}],\"a\":\"=\"}\n");
+    assert_eq!(ungron_of("json[\"x = y\"] = 3;", Positions::Compact).unwrap(), "{\"x = y\":3}\n");
+    assert_eq!(ungron_of("json = 3;", Positions::Compact).unwrap(), "3\n");
+    assert_eq!(ungron_of("", Positions::Compact).unwrap(), "");
+  }
+
+  #[test]
+  fn conflicts() {
+    assert!(ungron_of("json.a = 1;\njson.a.b = 2;", Positions::Compact).is_err());
+    assert!(ungron_of("json.a = [];\njson.a.b = 2;", Positions::Compact).is_err());
+    assert!(ungron_of("json.a[0] = 1;\njson.a = {};", Positions::Compact).is_err());
+    assert!(ungron_of("json.a = [1];", Positions::Compact).is_err());
+    assert!(ungron_of("other.a = 1;", Positions::Compact).is_err());
+    // with the paths as gron writes them
+    assert_eq!(ungron_of("json.a = 1;\njson.a.b = 2;", Positions::Compact).unwrap_err().to_string(), "line 2: json.a is Number(\"1\"), so it can't have children");
+    assert_eq!(ungron_of("json = 1;\njson = [];", Positions::Compact).unwrap_err().to_string(), "line 2: json is already a value");
+  }
+}
@@ -6,6 +6,57 @@ use crate::parser::JsonEvent;
 use crate::sender::Event;
 use crate::jsonpath::*;
 
+/// What to do with an element of an array, see `Handler::sample`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Sample {
+  /// handle it as usual
+  Take,
+  /// read past it without sending anything
+  Skip,
+  /// stop reading the array, and the rest of the input
+  Stop,
+}
+
+/// Which kind of container `Handler::maybe_send_path` is called for.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Container {
+  Array,
+  Object,
+}
+
+impl Container {
+  /// The event that starts this kind of container.
+  pub fn start<T : AsRef<[u8]>>(self) -> JsonEvent<T> {
+    match self {
+      Container::Array => JsonEvent::StartArray,
+      Container::Object => JsonEvent::StartObject,
+    }
+  }
+}
+
+/// Read past the value that starts with ev, without sending anything.
+///
+/// If ev is borrowed from jevs, pass `ev.without_text()`, because only its kind matters.
+pub fn skip_value<T>(jevs : &mut dyn JsonEventSource<String>, ev : JsonEvent<T>) -> Result<(), Box<dyn std::error::Error>>
+where T : AsRef<[u8]> + std::fmt::Debug + std::fmt::Display
+{
+  use JsonEvent::*;
+  let mut depth = match ev {
+    StartArray | StartObject => 1,
+    String(_) | Number(_) | Boolean(_) | Null | ObjectKey(_) => return Ok(()),
+    EndArray | EndObject | Eof | Error{..} => return Err(format!("expected a value, got {ev}").into()),
+  };
+  while depth > 0 {
+    match jevs.next_borrowed()? {
+      StartArray | StartObject => depth += 1,
+      EndArray | EndObject => depth -= 1,
+      String(_) | Number(_) | Boolean(_) | Null | ObjectKey(_) => (),
+      ev@ (Eof | Error{..}) => return Err(format!("expected a value, got {ev}").into()),
+    }
+  }
+  Ok(())
+}
+
 /**
 The Handler trait.
 
@@ -34,111 +85,344 @@ where
   fn match_path(&self, path : &JsonPath) -> bool;
 
   /// This will be called for each leaf value, along with its path.
-  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<String>, tx : &mut Sender)
+  ///
+  /// ev is borrowed from the parser, so it has to be converted with `into_owned` to be sent.
+  /// Which only happens for values that are sent, so filtering out values doesn't copy their text.
+  /// The path is still built for every key, because it's a JsonPath, so a handler allocates for
+  /// each key whether or not it sends anything. The schema collector avoids that with its own Visitor.
+  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<&str>, tx : &mut Sender)
   -> Result<(),Box<dyn std::error::Error>>
   ;
 
+  /// This will be called at the start of each array and object, along with its path.
+  /// Default is to send nothing, because most handlers only care about leaf values.
+  #[inline]
+  fn maybe_send_path(&self, _path : &JsonPath, _depth : usize, _container : Container, _tx : &mut Sender)
+  -> Result<(),Box<dyn std::error::Error>>
+  {
+    Ok(())
+  }
+
+  /// Called before each element of each array, with the index of the element.
+  /// Default is to take everything, most handlers don't sample.
+  #[inline]
+  fn sample(&self, _parents : &JsonPath, _index : u64) -> Sample {
+    Sample::Take
+  }
+
+  /// Called before the value of each key of each object, with the key.
+  /// Default is to take everything, like sample.
+  #[inline]
+  fn sample_key(&self, _parents : &JsonPath, _key : &str) -> Sample {
+    Sample::Take
+  }
+
   /// Handle all arrays.
   /// values will be emitted via maybe_send_value
   /// nested arrays are recursive
   /// objects are sent to object(...)
+  ///
+  /// Every element has its index in its path, scalars too, eg the 2 in `{"a":[1,2]}` is at `a/1`.
+  /// Up to the tuples in the schema, scalar elements were sent with the path of their array, ie `a`.
   //
   // depth: parents.len < depth because depth additionally counts StartObject and StartArray
   fn array(&self, jevs : &mut dyn JsonEventSource<String>, parents : JsonPath, depth : usize, tx : &mut Sender )
   -> Result<(), Box<dyn std::error::Error>>
   {
-    let mut index = 0;
-    loop {
-      match jevs.next_event() {
-        Ok(ev) =>{
-          // NOTE rpds persistent vector
-          let loop_parents = parents.push_back(index.into());
-          use JsonEvent::*;
-          let res = match ev {
-            // ok we have a leaf, so match path then send value
-            String(_) | Number(_)  | Boolean(_) | Null => self.maybe_send_value(&parents, ev, tx),
-
-            StartArray => self.array(jevs, loop_parents, depth+1, tx),
-            EndArray => return Ok(()), // do not send path, this is +1 past the end of the array
-
-            // ObjectKey(key) => find_path(jevs, loop_parents.push_front(key.into()), depth+1, tx),
-            StartObject => self.object(jevs, loop_parents, depth+1, tx),
-            ObjectKey(_) => panic!("should never receive ObjectKey {parents}"),
-            EndObject => panic!("should never receive EndObject {parents}"),
-
-            Eof => break tx.send(SendWrapper::from(Event::Finished)),
-            err@ Error{..} => {
-              let ev = Event::Error(loop_parents.into(), format!("{err}"));
-              tx.send(SendWrapper::from(ev))
-            }
-          };
-          if res.is_err() { break res };
-          index += 1;
-        },
-        // This means some kind of io error, ie not a json parse error. So bail out.
-        Err(err) => break tx.send(SendWrapper::from(Event::Error(parents.into(), format!("{err}")))),
-      }
-    }
+    walk_array(&mut Handling::new(self, tx), jevs, parents, depth)
   }
 
   /// handle objects.
   fn object(&self, jevs : &mut dyn JsonEventSource<String>, parents : JsonPath, depth : usize, tx : &mut Sender )
   -> Result<(), Box<dyn std::error::Error>>
   {
-    loop {
-      match jevs.next_event() {
-        Ok(ev) => {
-          use JsonEvent::*;
-          let res = match ev {
-            // ok we have a leaf, so emit the value and path.
-            // no need to shunt this through value(...)
-            String(_) | Number(_)  | Boolean(_) | Null => self.maybe_send_value(&parents, ev, tx),
-
-            StartArray => self.array(jevs, parents.clone(), depth+1, tx),
-            EndArray => panic!("should never receive EndArray {parents}"),
-
-            StartObject => self.value(jevs, parents.clone(), depth+1, tx),
-            ObjectKey(ref key) => self.value(jevs, parents.push_back(key.into()), depth+1, tx),
-            EndObject => return Ok(()),
-
-            // fin
-            Eof => break tx.send(SendWrapper::from(Event::Finished)),
-            err@ Error{..} => tx.send(SendWrapper::from(Event::Error((&parents).into(), format!("{err}")))),
-          };
-          if res.is_err() { break res };
-        },
-        // This means some kind of io error, ie not a json parse error. So bail out.
-        Err(err) => break tx.send(SendWrapper::from(Event::Error(parents.into(),format!("{err}")))),
-      };
-    }
+    walk_object(&mut Handling::new(self, tx), jevs, parents, depth)
   }
 
   /// Handle String Number Boolean Null (ie non-recursive)
   fn value(&self, jevs : &mut dyn JsonEventSource<String>, parents : JsonPath, depth : usize, tx : &mut Sender)
   -> Result<(), Box<dyn std::error::Error>>
   {
-    // json has exactly one top-level object
-    match jevs.next_event() {
-      Ok(ev) => {
-        use JsonEvent::*;
-        match ev {
-          // ok we have a leaf, so emit the value and path
-          String(_) | Number(_)  | Boolean(_) | Null => self.maybe_send_value(&parents, ev, tx),
-
-          StartArray => self.array(jevs, parents, depth+1, tx),
-          EndArray => panic!("should never receive EndArray {parents}"),
-
-          StartObject => self.object(jevs, parents, depth+1, tx),
-          ObjectKey(_) => panic!("should never receive ObjectKey {parents}"),
-          EndObject => panic!("should never receive EndObject {parents}"),
-
-          // fin
-          Eof => tx.send(SendWrapper::from(Event::Finished)),
-          err@ Error{..} => tx.send(SendWrapper::from(Event::Error(parents.into(), format!("{err}")))),
+    walk_value(&mut Handling::new(self, tx), jevs, parents, depth)
+  }
+}
+
+/**
+What to do at each step of the traversal in `walk_value`, `walk_array` and `walk_object`.
+
+Handler is one of these, whose At is the JsonPath. The schema walker is another, whose At is a node
+of its trie, so that it doesn't build paths. Either way the events are traversed by the same code.
+*/
+pub trait Visitor {
+  /// Where a value is, eg its path.
+  type At;
+
+  /// A scalar value at at.
+  fn leaf(&mut self, at : Self::At, ev : JsonEvent<&str>) -> Result<(), Box<dyn std::error::Error>>;
+
+  /// The start of the array or object at at. Returns what its elements or keys are relative to.
+  fn start(&mut self, at : Self::At, depth : usize, container : Container) -> Result<Self::At, Box<dyn std::error::Error>>;
+
+  /// Where element index of array is.
+  fn element(&mut self, array : &Self::At, index : u64) -> Self::At;
+
+  /// Called after each element that element was called for.
+  #[inline]
+  fn end_element(&mut self) {}
+
+  /// Where the value of key in object is.
+  fn key(&mut self, object : &Self::At, key : &str) -> Self::At;
+
+  /// See Handler::sample
+  #[inline]
+  fn sample(&mut self, _array : &Self::At, _index : u64) -> Sample {
+    Sample::Take
+  }
+
+  /// See Handler::sample_key
+  #[inline]
+  fn sample_key(&mut self, _object : &Self::At, _key : &str) -> Sample {
+    Sample::Take
+  }
+
+  /// A parse error, or some other error from the source, at at.
+  fn error(&mut self, at : &Self::At, message : String) -> Result<(), Box<dyn std::error::Error>>;
+
+  /// The end of the input.
+  fn finished(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+
+  /// The next value, at at. Only needs to be implemented to do something other than walk_value.
+  #[inline]
+  fn value(&mut self, jevs : &mut dyn JsonEventSource<String>, at : Self::At, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+    walk_value(self, jevs, at, depth)
+  }
+
+  /// The array at at, whose StartArray has been read.
+  #[inline]
+  fn array(&mut self, jevs : &mut dyn JsonEventSource<String>, at : Self::At, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+    walk_array(self, jevs, at, depth)
+  }
+
+  /// The object at at, whose StartObject has been read.
+  #[inline]
+  fn object(&mut self, jevs : &mut dyn JsonEventSource<String>, at : Self::At, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+    walk_object(self, jevs, at, depth)
+  }
+}
+
+/// Read the next value from jevs, at at.
+pub fn walk_value<V : Visitor + ?Sized>(v : &mut V, jevs : &mut dyn JsonEventSource<String>, at : V::At, depth : usize)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  // json has exactly one top-level object
+  match jevs.next_borrowed() {
+    Ok(ev) => {
+      use JsonEvent::*;
+      match ev {
+        // ok we have a leaf, so emit the value and path
+        String(_) | Number(_)  | Boolean(_) | Null => v.leaf(at, ev),
+        StartArray => v.array(jevs, at, depth+1),
+        StartObject => v.object(jevs, at, depth+1),
+        EndArray | ObjectKey(_) | EndObject => panic!("should never receive {ev} as a value"),
+
+        // fin
+        Eof => v.finished(),
+        err@ Error{..} => v.error(&at, format!("{err}")),
+      }
+    },
+    // This means some kind of io error, ie not a json parse error. So bail out.
+    Err(err) => v.error(&at, format!("{err}")),
+  }
+}
+
+/// Read the elements of the array at at, up to its EndArray.
+//
+// depth: counts StartObject and StartArray, so it's more than the length of a path
+pub fn walk_array<V : Visitor + ?Sized>(v : &mut V, jevs : &mut dyn JsonEventSource<String>, at : V::At, depth : usize)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let array = v.start(at, depth, Container::Array)?;
+  let mut index = 0;
+  loop {
+    let ev = match jevs.next_borrowed() {
+      Ok(ev) => ev,
+      // This means some kind of io error, ie not a json parse error. So bail out.
+      Err(err) => return v.error(&array, format!("{err}")),
+    };
+    use JsonEvent::*;
+    if !matches!(ev, EndArray | Eof | Error{..}) {
+      match v.sample(&array, index) {
+        Sample::Take => (),
+        Sample::Skip => {
+          let ev = ev.without_text();
+          skip_value(jevs, ev)?;
+          index += 1;
+          continue
         }
-      },
+        Sample::Stop => return Ok(()),
+      }
+    }
+    let result = match ev {
+      EndArray => return Ok(()), // do not send path, this is +1 past the end of the array
+      ObjectKey(_) | EndObject => panic!("should never receive {ev} in an array"),
+      Eof => return v.finished(),
+      ev => {
+        let element = v.element(&array, index);
+        let result = match ev {
+          // ok we have a leaf, so match path then send value
+          String(_) | Number(_) | Boolean(_) | Null => v.leaf(element, ev),
+          StartArray => v.array(jevs, element, depth+1),
+          StartObject => v.object(jevs, element, depth+1),
+          err => v.error(&element, format!("{err}")),
+        };
+        v.end_element();
+        result
+      }
+    };
+    result?;
+    index += 1;
+  }
+}
+
+/// Read the keys and values of the object at at, up to its EndObject.
+pub fn walk_object<V : Visitor + ?Sized>(v : &mut V, jevs : &mut dyn JsonEventSource<String>, at : V::At, depth : usize)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let object = v.start(at, depth, Container::Object)?;
+  loop {
+    let ev = match jevs.next_borrowed() {
+      Ok(ev) => ev,
       // This means some kind of io error, ie not a json parse error. So bail out.
-      Err(err) => tx.send(SendWrapper::from(Event::Error(parents.into(),format!("{err}")))),
+      Err(err) => return v.error(&object, format!("{err}")),
+    };
+    use JsonEvent::*;
+    match ev {
+      ObjectKey(key) => match v.sample_key(&object, key) {
+        Sample::Take => {
+          // the key is borrowed from jevs, so it has to be in at before jevs is used again
+          let at = v.key(&object, key);
+          v.value(jevs, at, depth+1)?
+        }
+        Sample::Skip => {
+          let ev = jevs.next_borrowed()?.without_text();
+          skip_value(jevs, ev)?
+        }
+        Sample::Stop => return Ok(()),
+      }
+      EndObject => return Ok(()),
+      String(_) | Number(_) | Boolean(_) | Null | StartArray | StartObject | EndArray => panic!("should never receive {ev} in an object"),
+
+      // fin
+      Eof => return v.finished(),
+      err@ Error{..} => v.error(&object, format!("{err}"))?,
+    }
+  }
+}
+
+/// A Handler and its Sender as a Visitor, whose At is the JsonPath.
+struct Handling<'h, 'l, H : ?Sized, SendValue, SendWrapper, Sender : ?Sized> {
+  handler : &'h H,
+  tx : &'h mut Sender,
+  _marker : std::marker::PhantomData<(&'l (), SendValue, SendWrapper)>,
+}
+
+impl<'h, H : ?Sized, SendValue, SendWrapper, Sender : ?Sized> Handling<'h, '_, H, SendValue, SendWrapper, Sender> {
+  fn new(handler : &'h H, tx : &'h mut Sender) -> Self {
+    Self{handler, tx, _marker: std::marker::PhantomData}
+  }
+}
+
+impl<'l, H, SendValue, SendWrapper, Sender> Visitor for Handling<'_, 'l, H, SendValue, SendWrapper, Sender>
+where
+  H : Handler<'l, SendValue, SendWrapper, Sender> + ?Sized,
+  SendWrapper : Send + From<Event<SendValue>> + std::ops::Deref<Target=Event<SendValue>>,
+  Sender : crate::sender::Sender<SendWrapper::Target,SendWrapper> + ?Sized + 'l,
+{
+  type At = JsonPath;
+
+  fn leaf(&mut self, at : JsonPath, ev : JsonEvent<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    self.handler.maybe_send_value(&at, ev, self.tx)
+  }
+
+  fn start(&mut self, at : JsonPath, depth : usize, container : Container) -> Result<JsonPath, Box<dyn std::error::Error>> {
+    self.handler.maybe_send_path(&at, depth, container, self.tx)?;
+    Ok(at)
+  }
+
+  // NOTE rpds persistent vector
+  fn element(&mut self, array : &JsonPath, index : u64) -> JsonPath {
+    array.push_back(index.into())
+  }
+
+  fn key(&mut self, object : &JsonPath, key : &str) -> JsonPath {
+    object.push_back(key.into())
+  }
+
+  fn sample(&mut self, array : &JsonPath, index : u64) -> Sample {
+    self.handler.sample(array, index)
+  }
+
+  fn sample_key(&mut self, object : &JsonPath, key : &str) -> Sample {
+    self.handler.sample_key(object, key)
+  }
+
+  fn error(&mut self, at : &JsonPath, message : String) -> Result<(), Box<dyn std::error::Error>> {
+    self.tx.send(SendWrapper::from(Event::Error(at.into(), message)))
+  }
+
+  fn finished(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    self.tx.send(SendWrapper::from(Event::Finished))
+  }
+
+  // through the Handler, so that its own array and object are used for nested values
+  fn value(&mut self, jevs : &mut dyn JsonEventSource<String>, at : JsonPath, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+    self.handler.value(jevs, at, depth, self.tx)
+  }
+
+  fn array(&mut self, jevs : &mut dyn JsonEventSource<String>, at : JsonPath, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+    self.handler.array(jevs, at, depth, self.tx)
+  }
+
+  fn object(&mut self, jevs : &mut dyn JsonEventSource<String>, at : JsonPath, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+    self.handler.object(jevs, at, depth, self.tx)
+  }
+}
+
+#[cfg(test)]
+mod test_handler {
+  use super::*;
+  use crate::sender::NonWrap;
+  use crate::sender::Sender;
+
+  type Wrapper = NonWrap<Event<()>>;
+
+  // the path of every leaf
+  struct Paths(Vec<String>);
+
+  impl Sender<Event<()>, Wrapper> for Paths {
+    fn send(&mut self, ev : Wrapper) -> Result<(), Box<dyn std::error::Error>> {
+      if let Event::Value(path, ()) = &*ev { self.0.push(path.to_string()) }
+      Ok(())
     }
   }
+
+  struct Leaves;
+
+  impl<'l> Handler<'l, (), Wrapper, dyn Sender<Event<()>, Wrapper> + 'l> for Leaves {
+    fn match_path(&self, _path : &JsonPath) -> bool { true }
+
+    fn maybe_send_value(&self, path : &JsonPath, _ev : JsonEvent<&str>, tx : &mut (dyn Sender<Event<()>, Wrapper> + 'l))
+    -> Result<(),Box<dyn std::error::Error>>
+    {
+      tx.send(Event::Value(path.into(), ()).into())
+    }
+  }
+
+  #[test]
+  fn element_paths() {
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(r#"{"a": [1, [2, {"b": 3}]], "c": 4}"#.as_bytes()));
+    let mut paths = Paths(vec![]);
+    Leaves.value(&mut jevstream, JsonPath::new(), 0, &mut paths).unwrap();
+    assert_eq!(paths.0, ["a/0", "a/1/0", "a/1/1/b", "c"]);
+  }
 }
@@ -0,0 +1,403 @@
+/*!
+The format of `jq --stream`, written and read.
+
+Each leaf is written as `[[path],leaf]`, where path is in jq notation, and leaves are strings,
+numbers, booleans, null, and empty arrays and objects. When an array or object that has something
+in it ends, `[[path]]` is written with the path of its last child. So `{"a":[1,{"b":2}]}` is
+
+```text
+[["a",0],1]
+[["a",1,"b"],2]
+[["a",1,"b"]]
+[["a",1]]
+[["a"]]
+```
+
+JqStreamParser goes the other way, so that the output of `jq -c --stream` can be read wherever a
+JsonEventSource is.
+*/
+
+use std::collections::VecDeque;
+
+use crate::handler::Handler;
+use crate::jsonpath::JsonPath;
+use crate::jsonpath::Step;
+use crate::parser::ByteCounter;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventParser;
+use crate::parser::JsonEventSource;
+use crate::projection::Pattern;
+use crate::projection::ProjectionWrapper;
+use crate::projection::Projector;
+use crate::sender::Event;
+use crate::sender::Sender;
+use crate::sendpath::SendPath;
+
+/// Writes the values from a Projector in the format of `jq --stream`.
+pub struct JqStreamWriter<'w> {
+  wr : &'w mut dyn std::io::Write,
+  // each element of the top-level array is a separate value, like ndjson is for jq
+  records : bool,
+  record : Option<Step>,
+  // the path of the last leaf written, whose ancestors are still open
+  last : Vec<Step>,
+  // an array or object that was started, and is a leaf if nothing under it comes next
+  pending : Option<(Vec<Step>, bool)>,
+}
+
+impl<'w> JqStreamWriter<'w> {
+  pub fn new(wr : &'w mut dyn std::io::Write) -> Self {
+    Self{wr, records: false, record: None, last: vec![], pending: None}
+  }
+
+  /// Write the elements of the top-level array as separate values, for ndjson input.
+  pub fn with_records(self) -> Self {
+    Self{records: true, ..self}
+  }
+
+  fn leaf(&mut self, steps : Vec<Step>, value : &JsonEvent<String>) -> std::io::Result<()> {
+    write!(self.wr, "[{:o},", SendPath::from(&steps[..]))?;
+    match value {
+      JsonEvent::StartArray => self.wr.write_all(b"[]")?,
+      JsonEvent::StartObject => self.wr.write_all(b"{}")?,
+      scalar => crate::projection::write_scalar(self.wr, scalar)?,
+    }
+    self.wr.write_all(b"]\n")?;
+    self.last = steps;
+    Ok(())
+  }
+
+  // Close the ancestors of the last leaf that aren't also ancestors of steps.
+  fn close(&mut self, steps : &[Step]) -> std::io::Result<()> {
+    let common = self.last.iter().zip(steps).take_while(|(last, step)| last == step).count();
+    // the ancestor at depth d is last[..d]
+    for depth in ((common + 1).min(steps.len())..self.last.len()).rev() {
+      writeln!(self.wr, "[{:o}]", SendPath::from(&self.last[..=depth]))?;
+    }
+    self.last.truncate(common);
+    Ok(())
+  }
+
+  fn write(&mut self, steps : &[Step], value : &JsonEvent<String>) -> std::io::Result<()> {
+    let steps = if self.records {
+      // the top-level array itself
+      let Some((record, steps)) = steps.split_first() else { return Ok(()) };
+      if self.record.as_ref() != Some(record) {
+        self.finish()?;
+        self.record = Some(record.clone());
+      }
+      steps
+    } else {
+      steps
+    };
+
+    if let Some((pending, is_array)) = self.pending.take() {
+      if !(steps.len() > pending.len() && steps.starts_with(&pending)) {
+        self.close(&pending)?;
+        self.leaf(pending, if is_array { &JsonEvent::StartArray } else { &JsonEvent::StartObject })?;
+      }
+    }
+    self.close(steps)?;
+    match value {
+      JsonEvent::StartArray => self.pending = Some((steps.to_vec(), true)),
+      JsonEvent::StartObject => self.pending = Some((steps.to_vec(), false)),
+      scalar => self.leaf(steps.to_vec(), scalar)?,
+    }
+    Ok(())
+  }
+
+  /// Write whatever is pending, and close everything that's still open.
+  pub fn finish(&mut self) -> std::io::Result<()> {
+    if let Some((pending, is_array)) = self.pending.take() {
+      self.close(&pending)?;
+      self.leaf(pending, if is_array { &JsonEvent::StartArray } else { &JsonEvent::StartObject })?;
+    }
+    self.close(&[])
+  }
+}
+
+impl Sender<Event<JsonEvent<String>>, ProjectionWrapper> for JqStreamWriter<'_> {
+  fn send(&mut self, ev : ProjectionWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    match ev.as_ref() {
+      Event::Value(path, value) => Ok(self.write(&path.steps(), value)?),
+      Event::Error(path, err) => Err(format!("{err} at path '{path}'").into()),
+      Event::Finished => Ok(self.finish()?),
+      Event::Path(..) => Ok(()),
+    }
+  }
+}
+
+/// Write the parts of the input selected by any of patterns, in the format of `jq --stream`.
+///
+/// With records, each element of the top-level array is written as a separate value.
+pub fn stream(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, patterns : Vec<Pattern>, records : bool)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let mut writer = JqStreamWriter::new(wr);
+  if records { writer = writer.with_records() }
+  Projector{patterns}.value(jev, JsonPath::new(), 0, &mut writer)?;
+  Ok(writer.finish()?)
+}
+
+/// Reads the output of `jq --stream`, as the events of the json it came from.
+///
+/// The closing `[[path]]` lines are optional, because an array or object also ends when a leaf
+/// comes that isn't in it.
+pub struct JqStreamParser {
+  // reads each `[[path],leaf]` as an element of an array
+  inner : JsonEventParser,
+  wrapped : bool,
+  // whether the array around several top-level values was started
+  started : bool,
+  // several top-level values are the elements of an array, like with_ndjson does
+  records : bool,
+  ended : bool,
+  // whether the current top-level value has been read completely
+  complete : bool,
+  // whether each open container is an array
+  open : Vec<bool>,
+  // the step from each open container to its current child
+  steps : Vec<Step>,
+  queue : VecDeque<JsonEvent<String>>,
+  current : JsonEvent<String>,
+}
+
+impl JqStreamParser {
+  pub fn new(istream : Box<dyn std::io::BufRead>) -> Self {
+    Self{
+      inner: JsonEventParser::new(istream).with_ndjson(),
+      wrapped: false,
+      started: false,
+      records: false,
+      ended: false,
+      complete: false,
+      open: vec![],
+      steps: vec![],
+      queue: VecDeque::new(),
+      current: JsonEvent::Null,
+    }
+  }
+
+  /// Present several top-level values as the elements of an array.
+  pub fn with_ndjson(self) -> Self {
+    Self{records: true, ..self}
+  }
+
+  fn next(&mut self) -> Result<JsonEvent<String>, Box<dyn std::error::Error>> {
+    self.inner.next_event()
+  }
+
+  fn close(&mut self) {
+    if let Some(is_array) = self.open.pop() {
+      self.queue.push_back(if is_array { JsonEvent::EndArray } else { JsonEvent::EndObject });
+      self.steps.truncate(self.open.len());
+      if self.open.is_empty() { self.complete = true }
+    }
+  }
+
+  // the steps of a path, after its [
+  fn path(&mut self) -> Result<Vec<Step>, Box<dyn std::error::Error>> {
+    let mut steps = vec![];
+    loop {
+      match self.next()? {
+        JsonEvent::String(key) => steps.push(Step::Key(key)),
+        JsonEvent::Number(index) => steps.push(Step::Index(index.parse().map_err(|_| format!("{index} is not an index"))?)),
+        JsonEvent::EndArray => return Ok(steps),
+        ev => return Err(format!("expected a key or an index in a path, not {ev}").into()),
+      }
+    }
+  }
+
+  // the events of one leaf, starting with first
+  fn leaf(&mut self, first : JsonEvent<String>) -> Result<Vec<JsonEvent<String>>, Box<dyn std::error::Error>> {
+    let mut depth = 0;
+    let mut events = vec![];
+    let mut ev = first;
+    loop {
+      match ev {
+        JsonEvent::StartArray | JsonEvent::StartObject => depth += 1,
+        JsonEvent::EndArray | JsonEvent::EndObject => depth -= 1,
+        JsonEvent::Eof | JsonEvent::Error{..} => return Err(format!("expected a leaf, not {ev}").into()),
+        _ => (),
+      }
+      events.push(ev);
+      if depth == 0 { return Ok(events) }
+      ev = self.next()?;
+    }
+  }
+
+  // Open the containers of a leaf at steps, and queue its events.
+  fn open(&mut self, steps : Vec<Step>, leaf : Vec<JsonEvent<String>>) -> Result<(), Box<dyn std::error::Error>> {
+    // the open containers that are ancestors of the leaf
+    let ancestors = (0..self.open.len().min(steps.len()))
+      .take_while(|&depth| depth == 0 || self.steps[depth - 1] == steps[depth - 1])
+      .count();
+    while self.open.len() > ancestors { self.close() }
+    if self.open.is_empty() && self.complete {
+      if !self.records { return Err("more than one top-level value, which needs --ndjson".into()) }
+      self.complete = false;
+    }
+    if self.records && !self.started {
+      self.queue.push_back(JsonEvent::StartArray);
+      self.started = true;
+    }
+
+    for depth in self.open.len()..=steps.len() {
+      if depth > 0 {
+        let step = &steps[depth - 1];
+        match (self.open[depth - 1], step) {
+          (false, Step::Key(key)) => self.queue.push_back(JsonEvent::ObjectKey(key.clone())),
+          (true, Step::Index(_)) => (),
+          _ => return Err(format!("{step} doesn't match its parent at {:o}", SendPath::from(&steps[..depth - 1])).into()),
+        }
+        self.steps.truncate(depth - 1);
+        self.steps.push(step.clone());
+      }
+      if depth < steps.len() {
+        let is_array = matches!(steps[depth], Step::Index(_));
+        self.queue.push_back(if is_array { JsonEvent::StartArray } else { JsonEvent::StartObject });
+        self.open.push(is_array);
+      }
+    }
+    self.queue.extend(leaf);
+    if steps.is_empty() { self.complete = true }
+    Ok(())
+  }
+
+  // Queue the events of the next line, or the end of the input.
+  fn fill(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if self.ended {
+      self.queue.push_back(JsonEvent::Eof);
+      return Ok(())
+    }
+    // the array that the inner parser puts around the lines
+    if !self.wrapped {
+      let JsonEvent::StartArray = self.next()? else { return Err("expected the inner parser to start an array".into()) };
+      self.wrapped = true;
+    }
+    match self.next()? {
+      JsonEvent::StartArray => {
+        let JsonEvent::StartArray = self.next()? else { return Err("expected [[path],leaf] or [[path]]".into()) };
+        let steps = self.path()?;
+        match self.next()? {
+          JsonEvent::EndArray => {
+            if steps.is_empty() { return Err("[[]] doesn't close anything".into()) }
+            while self.open.len() >= steps.len() { self.close() }
+          }
+          first => {
+            let leaf = self.leaf(first)?;
+            let JsonEvent::EndArray = self.next()? else { return Err("expected ] after the leaf".into()) };
+            self.open(steps, leaf)?;
+          }
+        }
+      }
+      JsonEvent::EndArray | JsonEvent::Eof => {
+        while !self.open.is_empty() { self.close() }
+        if self.records && self.started { self.queue.push_back(JsonEvent::EndArray) }
+        self.queue.push_back(JsonEvent::Eof);
+        self.ended = true;
+      }
+      err @ JsonEvent::Error{..} => self.queue.push_back(err),
+      ev => return Err(format!("expected [[path],leaf] or [[path]], not {ev}").into()),
+    }
+    Ok(())
+  }
+}
+
+impl JsonEventSource<'_, String> for JqStreamParser {
+  fn next_event(&mut self) -> Result<JsonEvent<String>, Box<dyn std::error::Error>> {
+    loop {
+      if let Some(ev) = self.queue.pop_front() { return Ok(ev) }
+      self.fill()?;
+    }
+  }
+
+  // The events are built with owned text from the jq lines, so this only lends out the current one.
+  fn next_borrowed(&mut self) -> Result<JsonEvent<&str>, Box<dyn std::error::Error>> {
+    self.current = self.next_event()?;
+    Ok(self.current.as_deref())
+  }
+
+  fn byte_counter(&self) -> Option<ByteCounter> {
+    JsonEventSource::<String>::byte_counter(&self.inner)
+  }
+}
+
+#[cfg(test)]
+mod test_jqstream {
+  use super::*;
+
+  const JSON : &str = r#"{"a":[1,{"b":2},[]],"c":{},"d\"x":"s","e":[[true,null]]}"#;
+
+  // from jq -c --stream
+  const STREAM : &str = r#"[["a",0],1]
+[["a",1,"b"],2]
+[["a",1,"b"]]
+[["a",2],[]]
+[["a",2]]
+[["c"],{}]
+[["d\"x"],"s"]
+[["e",0,0],true]
+[["e",0,1],null]
+[["e",0,1]]
+[["e",0]]
+[["e"]]
+"#;
+
+  fn stream_of(json : &'static str, records : bool) -> String {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    if records { jevstream = jevstream.with_ndjson() }
+    let mut out = vec![];
+    stream(&mut out, &mut jevstream, vec!["$".parse().unwrap()], records).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  fn json_of(stream : &'static str, records : bool) -> serde_json::Value {
+    let mut parser = JqStreamParser::new(Box::new(stream.as_bytes()));
+    if records { parser = parser.with_ndjson() }
+    let mut out = vec![];
+    crate::projection::project(&mut out, &mut parser, vec!["$".parse().unwrap()], Default::default()).unwrap();
+    serde_json::from_slice(&out).unwrap()
+  }
+
+  #[test]
+  fn write() {
+    assert_eq!(stream_of(JSON, false), STREAM);
+    assert_eq!(stream_of("[]", false), "[[],[]]\n");
+    assert_eq!(stream_of("3", false), "[[],3]\n");
+    assert_eq!(stream_of("[[1]]", false), "[[0,0],1]\n[[0,0]]\n[[0]]\n");
+  }
+
+  #[test]
+  fn write_records() {
+    assert_eq!(stream_of("[1]\n[2]\n3\n{}", true), "[[0],1]\n[[0]]\n[[0],2]\n[[0]]\n[[],3]\n[[],{}]\n");
+  }
+
+  #[test]
+  fn read() {
+    assert_eq!(json_of(STREAM, false), serde_json::from_str::<serde_json::Value>(JSON).unwrap());
+    assert_eq!(json_of("[[],3]", false), serde_json::json!(3));
+    // without the closing lines
+    assert_eq!(json_of("[[\"a\",0],1]\n[[\"b\"],2]", false), serde_json::json!({"a": [1], "b": 2}));
+  }
+
+  #[test]
+  fn read_records() {
+    assert_eq!(json_of("[[0],1]\n[[0]]\n[[0],2]\n[[0]]\n[[],3]\n", true), serde_json::json!([[1], [2], 3]));
+  }
+
+  fn drain(stream : &'static str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut parser = JqStreamParser::new(Box::new(stream.as_bytes()));
+    while parser.next_event()? != JsonEvent::Eof {}
+    Ok(())
+  }
+
+  #[test]
+  fn read_errors() {
+    assert!(drain("[[0],1]\n[[0]]\n[[],2]").is_err());
+    assert!(drain("[[\"a\"],1]\n[[0,\"b\"],2]").is_err());
+    assert!(drain("[[\"a\"]]\n").is_ok());
+    assert!(drain("[[[]],1]").is_err());
+    assert!(drain("[[],1,2]").is_err());
+  }
+}
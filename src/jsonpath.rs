@@ -57,10 +57,11 @@ impl std::fmt::Display for Step {
   }
 }
 
+/// A step of a jq path, ie keys are json strings.
 impl std::fmt::Octal for Step {
   fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
     match &self {
-      Step::Key(v) => write!(f, "\"{v}\""),
+      Step::Key(v) => write!(f, "{}", serde_json::Value::String(v.clone())),
       Step::Index(v) => write!(f, "{v}"),
     }
   }
@@ -102,3 +103,16 @@ impl AsRef<Step> for Step {
 // https://docs.rs/rpds/latest/rpds/list/struct.List.html
 // type JsonPath = rpds::List<Step>;
 pub type JsonPath = rpds::Vector<Step>;
+
+#[cfg(test)]
+mod test_step {
+  use super::*;
+
+  #[test]
+  fn jq() {
+    assert_eq!(format!("{:o}", Step::Key("a".into())), r#""a""#);
+    assert_eq!(format!("{:o}", Step::Key(r#"q""#.into())), r#""q\"""#);
+    assert_eq!(format!("{:o}", Step::Key("a\\b\n".into())), r#""a\\b\n""#);
+    assert_eq!(format!("{:o}", Step::Index(3)), "3");
+  }
+}
@@ -20,6 +20,8 @@ Might grow some kind of path-filtering languages, like jsonpath or xpath.
 // parser and traits
 pub mod parser;
 pub mod jsonpath;
+pub mod pathformat;
+pub mod pathdict;
 pub mod handler;
 pub mod sendpath;
 pub mod sender;
@@ -28,6 +30,15 @@ pub mod sender;
 pub mod plain;
 pub mod shredder;
 pub mod schema;
+pub mod audit;
+pub mod outline;
+pub mod projection;
+pub mod jqstream;
+pub mod builder;
+pub mod gron;
+pub mod csv;
+pub mod dremel;
+pub mod validate;
 pub mod valuer;
 pub mod channel;
 pub mod fn_snd;
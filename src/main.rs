@@ -10,19 +10,290 @@ use jch::fn_snd;
 use std::marker::PhantomData;
 use std::process::exit;
 
+/// Remove `name` from args, and return whether it was there.
+fn take_flag(args : &mut Vec<&str>, name : &str) -> bool {
+  match args.iter().position(|arg| *arg == name) {
+    Some(pos) => { args.remove(pos); true }
+    None => false,
+  }
+}
+
+/// Remove `name value` from args, and return value.
+fn take_option<'a>(args : &mut Vec<&'a str>, name : &str) -> Option<&'a str> {
+  let pos = args.iter().position(|arg| *arg == name)?;
+  if pos + 1 >= args.len() {
+    eprintln!("{name} needs a value");
+    exit(1)
+  }
+  let value = args.remove(pos + 1);
+  args.remove(pos);
+  Some(value)
+}
+
+/// Like take_option, but only among the options before the mode, so a file or a value that
+/// happens to look like the option is left alone.
+fn take_leading_option<'a>(args : &mut Vec<&'a str>, name : &str) -> Option<&'a str> {
+  let mut pos = 1;
+  while pos < args.len() && args[pos] != name {
+    if !args[pos].starts_with("--") { return None }
+    pos += 1;
+  }
+  if pos >= args.len() { return None }
+  if pos + 1 >= args.len() {
+    eprintln!("{name} needs a value");
+    exit(1)
+  }
+  let value = args.remove(pos + 1);
+  args.remove(pos);
+  Some(value)
+}
+
+/// Parse the value of an option, or give up.
+fn parse_or_exit<T>(value : &str) -> T
+where T : std::str::FromStr, T::Err : std::fmt::Display
+{
+  value.parse::<T>().unwrap_or_else(|err| {eprintln!("{value}: {err}"); exit(1)})
+}
+
+/// The events of json, or ndjson, or of the output of `jq --stream`.
+fn event_source(istream : Box<dyn std::io::BufRead>, ndjson : bool, from_stream : bool) -> Box<dyn parser::JsonEventSource<'static, String>> {
+  match (from_stream, ndjson) {
+    (false, false) => Box::new(parser::JsonEventParser::new(istream)),
+    (false, true) => Box::new(parser::JsonEventParser::new(istream).with_ndjson()),
+    (true, false) => Box::new(jch::jqstream::JqStreamParser::new(istream)),
+    (true, true) => Box::new(jch::jqstream::JqStreamParser::new(istream).with_ndjson()),
+  }
+}
+
+/// Same as event_source, but tracking where each event is in the input, for the modes that say so.
+fn event_source_with_positions(istream : Box<dyn std::io::BufRead>, ndjson : bool, from_stream : bool) -> Box<dyn parser::JsonEventSource<'static, String>> {
+  match (from_stream, ndjson) {
+    (false, false) => Box::new(parser::JsonEventParser::new(istream).with_positions()),
+    (false, true) => Box::new(parser::JsonEventParser::new(istream).with_ndjson().with_positions()),
+    (true, false) => Box::new(jch::jqstream::JqStreamParser::new(istream)),
+    (true, true) => Box::new(jch::jqstream::JqStreamParser::new(istream).with_ndjson()),
+  }
+}
+
+/// Remove `--records <path>` from args, and return the path, which is the elements of the top-level array by default.
+fn take_records<'a>(args : &mut Vec<&'a str>) -> Vec<jch::pathformat::Part<'a>> {
+  match take_option(args, "--records") {
+    Some(record) => jch::pathformat::read(record, jch::pathformat::PathFormat::global()).unwrap_or_else(|err| {eprintln!("{err}"); exit(1)}),
+    None => vec![jch::pathformat::Part::Any],
+  }
+}
+
+/// The schema from `--schema <schema.json>` in args, or else a schema of the input file, which is then read twice.
+fn input_schema(args : &mut Vec<&str>, ndjson : bool, from_stream : bool, usage : &str) -> schema::saved::SavedNode {
+  if let Some(schema_file) = take_option(args, "--schema") {
+    return schema::saved::read(&mut jch::make_readable(&[schema_file])).unwrap_or_else(|err| {eprintln!("{schema_file}: {err}"); exit(1)})
+  }
+  let &[filename] = args.as_slice() else { eprintln!("{usage}"); exit(1) };
+  let mut jevstream = event_source(jch::make_readable(&[filename]), ndjson, from_stream);
+  let collector = schema::collect(&mut *jevstream, schema::SchemaCollector::new()).unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+  schema::saved::SavedNode::from(&collector)
+}
+
 /// The most useful thing this does is calculate a Schema for a json file. Really fast.
 /// The rest of it is a showcase and testbed for some of the other things that can be done.
 #[allow(clippy::unit_arg)]
 fn main() {
   // Quick'n'Dirty command line arg dispatch
   let args : Vec<String> = std::env::args().collect();
-  let args : Vec<&str> = args.iter().map(String::as_str).collect();
+  let mut args : Vec<&str> = args.iter().map(String::as_str).collect();
+  // for every mode, so it goes before the mode
+  if let Some(format) = take_leading_option(&mut args, "--path-format") {
+    parse_or_exit::<jch::pathformat::PathFormat>(format).set_global();
+  }
+  let from_stream = take_flag(&mut args, "--from-jq-stream");
   match &args[1..] {
     ["-s", "-z"] => schema::sizes(&mut std::io::stdout()).unwrap(),
-    ["-s", rst @ ..] => {
-      let istream = jch::make_readable(rst);
-      let mut jevstream = parser::JsonEventParser::new(istream);
-      schema::schema(&mut std::io::stdout(), &mut jevstream);
+    ["-s" | "schema", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let output = if take_flag(&mut rst, "--tree") {
+        schema::Output::Tree
+      } else if take_flag(&mut rst, "--json") {
+        schema::Output::Json
+      } else if let Some(output) = take_option(&mut rst, "--emit") {
+        parse_or_exit(output)
+      } else if let Some(format) = take_option(&mut rst, "--report") {
+        schema::Output::Report(parse_or_exit(format))
+      } else {
+        schema::Output::Flat
+      };
+      let enum_limit = take_option(&mut rst, "--enum-limit").map(parse_or_exit).unwrap_or(schema::DEFAULT_ENUM_LIMIT);
+      let tuple_limit = take_option(&mut rst, "--tuples").map(parse_or_exit).unwrap_or(0);
+      let mut collector = schema::SchemaCollector::new().with_enum_limit(enum_limit).with_tuple_limit(tuple_limit);
+      if let Some(max_errors) = take_option(&mut rst, "--max-errors") {
+        let max_errors = std::num::NonZeroU64::new(parse_or_exit(max_errors)).unwrap_or_else(|| {eprintln!("--max-errors must be at least 1"); exit(1)});
+        collector = collector.with_max_errors(max_errors);
+      }
+      if let Some(records) = take_option(&mut rst, "--sample-records") {
+        collector = collector.with_sampling(schema::Sampling::First(parse_or_exit(records)));
+      }
+      if let Some(fraction) = take_option(&mut rst, "--sample-fraction") {
+        let fraction : f64 = parse_or_exit(fraction);
+        if !(0.0..=1.0).contains(&fraction) { eprintln!("--sample-fraction must be between 0 and 1"); exit(1) }
+        collector = collector.with_sampling(schema::Sampling::Fraction(fraction));
+      }
+      if let Some(seed) = take_option(&mut rst, "--seed") {
+        collector = collector.with_seed(parse_or_exit(seed));
+      }
+      let ndjson = take_flag(&mut rst, "--ndjson");
+
+      let snapshot_seconds = take_option(&mut rst, "--snapshot-seconds");
+      let snapshot_leaves = take_option(&mut rst, "--snapshot-leaves");
+      let snapshot_file = take_option(&mut rst, "--snapshot-file");
+      // the signal handlers are only wanted along with snapshots, otherwise SIGINT should just stop
+      if snapshot_seconds.is_some() || snapshot_leaves.is_some() || snapshot_file.is_some() {
+        let mut snapshots = schema::snapshot::Snapshots::new(output)
+          .with_signals()
+          .unwrap_or_else(|err| {eprintln!("can't handle signals: {err}"); exit(1)});
+        if let Some(seconds) = snapshot_seconds {
+          snapshots = snapshots.every(std::time::Duration::from_secs_f64(parse_or_exit(seconds)));
+        }
+        if let Some(leaves) = snapshot_leaves {
+          snapshots = snapshots.every_leaves(parse_or_exit(leaves));
+        }
+        if let Some(file) = snapshot_file {
+          snapshots = snapshots.to_file(std::path::Path::new(file));
+        }
+        collector = collector.with_snapshots(snapshots);
+      }
+      let explain = take_option(&mut rst, "--explain");
+      let group_by = take_option(&mut rst, "--group-by");
+
+      let mut jevstream = match explain {
+        Some(_) => event_source_with_positions(jch::make_readable(&rst), ndjson, from_stream),
+        None => event_source(jch::make_readable(&rst), ndjson, from_stream),
+      };
+      match explain {
+        // because the records are found by reading the input as one value
+        Some(_) if ndjson => Err("--explain doesn't work with --ndjson".into()),
+        Some(_) if from_stream => Err("--explain doesn't work with --from-jq-stream".into()),
+        Some(path) => {
+          // because the records are read from the input again
+          let &[filename] = rst.as_slice() else { eprintln!("--explain needs a file"); exit(1) };
+          let collector = schema::collect(&mut *jevstream, collector).unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+          schema::explain::explain(&mut std::io::stdout(), &collector, path, &|| jch::make_readable(&[filename]))
+        }
+        None => match group_by {
+          Some(discriminator) => schema::group::schema_with(&mut std::io::stdout(), &mut *jevstream, collector, discriminator, output),
+          None => schema::schema_with(&mut std::io::stdout(), &mut *jevstream, collector, output),
+        }
+      }.unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-o" | "outline", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let depth = take_option(&mut rst, "--depth").map(parse_or_exit).unwrap_or(2);
+      let mut jevstream = event_source_with_positions(jch::make_readable(&rst), false, from_stream);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      jch::outline::outline(&mut stdout, &mut *jevstream, depth)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-j" | "project", rst @ ..] | rst @ ["--path", ..] => {
+      let mut rst = rst.to_vec();
+      let positions = take_option(&mut rst, "--positions").map(parse_or_exit).unwrap_or_default();
+      let mut patterns = vec![];
+      while let Some(path) = take_option(&mut rst, "--path") { patterns.push(parse_or_exit(path)) }
+      if patterns.is_empty() { eprintln!("-j needs at least one --path"); exit(1) }
+      let mut jevstream = event_source(jch::make_readable(&rst), false, from_stream);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      jch::projection::project(&mut stdout, &mut *jevstream, patterns, positions)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-q" | "stream", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let ndjson = take_flag(&mut rst, "--ndjson");
+      let mut patterns = vec![];
+      while let Some(path) = take_option(&mut rst, "--path") { patterns.push(parse_or_exit(path)) }
+      if patterns.is_empty() { patterns.push(parse_or_exit("$")) }
+      let mut jevstream = event_source(jch::make_readable(&rst), ndjson, from_stream);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      jch::jqstream::stream(&mut stdout, &mut *jevstream, patterns, ndjson)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-G" | "gron", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let ndjson = take_flag(&mut rst, "--ndjson");
+      let mut patterns = vec![];
+      while let Some(path) = take_option(&mut rst, "--path") { patterns.push(parse_or_exit(path)) }
+      if patterns.is_empty() { patterns.push(parse_or_exit("$")) }
+      let mut jevstream = event_source(jch::make_readable(&rst), ndjson, from_stream);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      jch::gron::gron(&mut stdout, &mut *jevstream, patterns)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-U" | "ungron", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      // like gron
+      let positions = take_option(&mut rst, "--positions").map(parse_or_exit).unwrap_or(jch::projection::Positions::Preserve);
+      let istream = jch::make_readable(&rst);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      jch::gron::ungron(&mut stdout, istream, positions)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-x" | "csv", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let ndjson = take_flag(&mut rst, "--ndjson");
+      let format = if take_flag(&mut rst, "--tsv") { jch::csv::Format::Tsv } else { jch::csv::Format::Csv };
+      let arrays = take_option(&mut rst, "--arrays").map(parse_or_exit).unwrap_or_default();
+      let separator = take_option(&mut rst, "--join-with").unwrap_or(";");
+      let record = take_records(&mut rst);
+      let columns = match take_option(&mut rst, "--columns") {
+        Some(list) => jch::csv::column_names(list),
+        None => {
+          let root = input_schema(&mut rst, ndjson, from_stream, "csv needs a file to read twice, or --columns or --schema");
+          jch::csv::columns(&root, &record, arrays)
+        }
+      }.unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+      let mut jevstream = event_source(jch::make_readable(&rst), ndjson, from_stream);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      jch::csv::table(&mut stdout, &mut *jevstream, &record, &columns, format, arrays, separator)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-P" | "parquet", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let ndjson = take_flag(&mut rst, "--ndjson");
+      let record = take_records(&mut rst);
+      if rst.is_empty() { eprintln!("parquet needs a file to write"); exit(1) }
+      let output = rst.remove(0);
+      let root = input_schema(&mut rst, ndjson, from_stream, "parquet needs a file to read twice, or --schema");
+      let file = std::fs::File::create(output).unwrap_or_else(|err| {eprintln!("{output}: {err}"); exit(1)});
+      let mut jevstream = event_source(jch::make_readable(&rst), ndjson, from_stream);
+      jch::dremel::parquet(file, &mut *jevstream, &record, &root).unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-a" | "audit", rst @ ..] => {
+      let mut jevstream = event_source(jch::make_readable(rst), false, from_stream);
+      jch::audit::audit(&mut std::io::stdout(), &mut *jevstream).unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-V" | "validate", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let ndjson = take_flag(&mut rst, "--ndjson");
+      let Some(schema_file) = take_option(&mut rst, "--schema") else { eprintln!("validate needs --schema <schema.json>"); exit(1) };
+      let contract = jch::validate::Contract::read(&mut jch::make_readable(&[schema_file])).unwrap_or_else(|err| {eprintln!("{schema_file}: {err}"); exit(1)});
+      let mut jevstream = event_source_with_positions(jch::make_readable(&rst), ndjson, from_stream);
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      let result = jch::validate::validate(&mut stdout, &mut *jevstream, &contract);
+      std::io::Write::flush(&mut stdout).unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+      result.unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
+    ["-g" | "generate", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      let format = if take_flag(&mut rst, "--ndjson") { schema::generate::Format::Ndjson } else { schema::generate::Format::Array };
+      let mut generator = schema::generate::Generator::new(take_option(&mut rst, "--seed").map(parse_or_exit));
+      let &[schema_file, records] = rst.as_slice() else { eprintln!("-g needs a saved schema and a number of records"); exit(1) };
+      let root = schema::saved::read(&mut jch::make_readable(&[schema_file])).unwrap_or_else(|err| {eprintln!("{schema_file}: {err}"); exit(1)});
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      schema::generate::generate(&mut stdout, &root, parse_or_exit(records), format, &mut generator)
+        .and_then(|()| std::io::Write::flush(&mut stdout))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
     }
     // This is PoC to see that the rest of the handlers and visitors work.
     ["-p", rst @ ..] => {
@@ -32,7 +303,7 @@ fn main() {
       // just use a (mostly) simple function wrapper
       // which just outputs the value if sent.
       // kak syntax.
-      let sender = &mut fn_snd::FnSnd( |ev| { println!("fn_snd {ev:?}"); Ok(())} );
+      let sender = &mut fn_snd::FnSnd( |ev| { println!("fn_snd {}", fn_snd::line(ev, jch::pathformat::PathFormat::global())); Ok(())} );
 
       // Sends things as copies rather than references, and always returns true for path matches.
       let visitor = plain::Plain(|_| true, PhantomData::<_>);
@@ -51,7 +322,7 @@ fn main() {
       let visitor = valuer::Valuer(|_path| true);
 
       // just print the output from visitor, which understands serde_json Value
-      let sender : &mut fn_snd::FnSnd<serde_json::value::Value> = &mut fn_snd::FnSnd(|ev| Ok(println!("{ev:?}")));
+      let sender : &mut fn_snd::FnSnd<serde_json::value::Value> = &mut fn_snd::FnSnd(|ev| Ok(println!("{}", fn_snd::line(ev, jch::pathformat::PathFormat::global()))));
 
       // go and doit
       use handler::Handler;
@@ -67,13 +338,26 @@ fn main() {
     }
     [ "-m", "-c", dir, rst @ ..] => shredder::channel_shred(&std::path::PathBuf::from(dir), rst),
     [ "-m", dir, rst @ ..] => shredder::shred(&std::path::PathBuf::from(dir), rst),
+    ["-M" | "unshred", rst @ ..] => {
+      let mut rst = rst.to_vec();
+      // dotted whatever --path-format is, like the column names of -x and the paths in the manifest
+      let columns = take_option(&mut rst, "--columns").map(|list| list.split(',')
+        .map(|column| jch::pathformat::read(column.trim(), jch::pathformat::PathFormat::Dotted))
+        .collect::<Result<Vec<_>, String>>()
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)}));
+      let &[dir] = rst.as_slice() else { eprintln!("unshred needs a directory written by -m"); exit(1) };
+      let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+      shredder::unshred(&mut stdout, std::path::Path::new(dir), columns.as_deref())
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdout)?))
+        .unwrap_or_else(|err| {eprintln!("{err}"); exit(1)});
+    }
     [ "-r", "-f", filename, _rst @ ..] => jch::rapid::schema_from_file(filename),
     [ "-r", rst @ ..] => {
       let istream = jch::make_readable(rst);
       jch::rapid::parse(istream)
     }
     _ =>  {
-      println!("-s [file] for schema\n-p [file] for plain\n-v [file] for valuer\n-c [file] for channel\n-m <dir> for shredder to MessagePack\n-m -c [dir] for fast shredder to MessagePack\n-r for RapidJson wrapper");
+      println!("-s [file] for schema\n-s --tree [file] for schema as a tree\n-s --report html|md [file] for schema as a document\n-s --json [file] for schema as json, to save for -g\n-s --emit flat|tree|json|dot|html|md [file] for schema in that format, dot is a Graphviz graph\n-s --enum-limit <n> [file] for string paths with at most n values as enums\n-s --tuples <n> [file] for arrays of constant length at most n as tuples\n-s --max-errors <n> [file] to stop after n parse errors\n-s --snapshot-seconds <n> | --snapshot-leaves <n> [--snapshot-file <f>] [file] for partial schemas while reading, also on SIGUSR1\n-s --explain <path> file for where the values at path were first and last seen\n-s --group-by <path> [file] for a schema for each value of path, relative to each record\n-s --sample-records <n> | --sample-fraction <p> [--seed <n>] [file] for the schema of the first n records, or about p of them\n-s --ndjson [file] for a schema of one record per line\n-o [--depth <n>] [file] for the first n levels of the document, with deeper levels summarised, default 2\n-j --path <jsonpath> [--path <jsonpath>..] [--positions compact|preserve] [file] for the parts of the document at those paths, as json\n-q [--ndjson] [--path <jsonpath>..] [file] for the document in the format of jq --stream\n-G [--ndjson] [--path <jsonpath>..] [file] for one assignment per leaf, like gron\n-U [--positions preserve|compact] [file] for the json from those assignments, like gron --ungron, with null for missing elements by default\n-x [--records <path>] [--columns a,b.c | --schema <schema.json>] [--arrays json|join|explode] [--join-with <s>] [--tsv] [--ndjson] [file] for records as csv, default records []\n-P <out.parquet> [--records <path>] [--schema <schema.json>] [--ndjson] [file] for records as parquet, with nested arrays and objects, where missing fields and nulls are both null\n-a [file] for paths with personal data or secrets, with redacted samples\n-V --schema <schema.json> [--ndjson] [file] for values that don't match a json schema\n-g [--ndjson] [--seed <n>] <schema.json> <n> for n records of synthetic data like a schema saved with -s --json\n-p [file] for plain\n-v [file] for valuer\n-c [file] for channel\n-m <dir> for shredder to MessagePack\n-m -c [dir] for fast shredder to MessagePack\n-M [--columns a,b.c] <dir> for the records from the files of -m, one per line, where the columns are always dotted paths\n-r for RapidJson wrapper\n--path-format slash|pointer|jq|dotted before any of those, for how paths are written and read, except --columns which is always dotted\n--from-jq-stream with -s, -o, -j, -q, -G, -x, -P, -a or -V to read the output of jq -c --stream instead of json");
       exit(1)
     }
   }
@@ -0,0 +1,254 @@
+/*!
+Prints the shape of the first few levels of a document, without statistics on every leaf.
+
+Containers down to the given depth are printed with their contents. Deeper containers are skipped
+without being converted, and replaced by a summary like `{… 3 keys, 120 bytes}` or
+`[… 20 items, 2048 bytes]`. The top-level value is at depth 1, so depth 0 only summarises that.
+
+This is not json: there are no commas, and long strings are truncated.
+*/
+
+use crate::handler::Handler;
+use crate::jsonpath::JsonPath;
+use crate::jsonpath::Step;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::sender;
+use crate::sender::Event;
+use crate::sender::Sender;
+
+/// Longer strings are truncated, because this is only for the shape.
+const STRING_LENGTH : usize = 64;
+
+/// What the Outliner sends for each line of the outline.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Outline {
+  /// `[` or `{`
+  Open(char),
+  /// `]` or `}`
+  Close(char),
+  /// a leaf, in json notation
+  Scalar(String),
+  /// a container that's deeper than the outline, with its number of items or keys, and its size.
+  Summary{open : char, children : u64, bytes : Option<u64>},
+}
+
+type OutlineWrapper = sender::NonWrap<Event<Outline>>;
+type OutlineSender<'l> = dyn Sender<Event<Outline>, OutlineWrapper> + 'l;
+
+/// Sends the outline of the input down to depth.
+pub struct Outliner {
+  pub depth : usize,
+}
+
+impl Outliner {
+  // Skip the container that was just opened, counting its direct children. Sizes need the parser to track positions.
+  // This is where most of the input goes, so it borrows the events.
+  fn summarize<'l>(&self, jevs : &mut dyn JsonEventSource<String>, parents : JsonPath, open : char, tx : &mut OutlineSender<'l>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    let tracker = jevs.position_tracker();
+    // the tracker is at the start of the most recent event, which is the opening bracket
+    let start = tracker.as_ref().map(|tracker| tracker.get().offset);
+    let mut level = 1;
+    let mut children = 0;
+    loop {
+      use JsonEvent::*;
+      match jevs.next_borrowed()? {
+        // keys are the children of objects, values are the children of arrays
+        ObjectKey(_) if level == 1 => children += 1,
+        ObjectKey(_) => (),
+        String(_) | Number(_) | Boolean(_) | Null => if level == 1 && open == '[' { children += 1 },
+        StartArray | StartObject => {
+          if level == 1 && open == '[' { children += 1 }
+          level += 1
+        }
+        EndArray | EndObject => {
+          level -= 1;
+          if level == 0 { break }
+        }
+        Eof => return Err(format!("unexpected end of input in {}", crate::sendpath::SendPath::from(parents)).into()),
+        err @ Error{..} => return Err(format!("{err}").into()),
+      }
+    }
+    let bytes = tracker.zip(start).map(|(tracker, start)| tracker.get().offset + 1 - start);
+    tx.send(Event::Value(parents.into(), Outline::Summary{open, children, bytes}).into())
+  }
+
+  // Send the value that starts with ev, which is a child of a container at depth.
+  fn child<'l>(&self, jevs : &mut dyn JsonEventSource<String>, path : JsonPath, ev : JsonEvent<String>, depth : usize, tx : &mut OutlineSender<'l>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    use JsonEvent::*;
+    match ev {
+      String(_) | Number(_) | Boolean(_) | Null => self.maybe_send_value(&path, ev.as_deref(), tx),
+      StartArray => self.array(jevs, path, depth + 1, tx),
+      StartObject => self.object(jevs, path, depth + 1, tx),
+      // this is always inside a container, so it's truncated, and an outline of the rest would look plausible
+      Eof => {
+        let container = path.drop_last().unwrap_or_default();
+        Err(format!("unexpected end of input in {}", crate::sendpath::SendPath::from(container)).into())
+      }
+      err @ Error{..} => Err(format!("{err}").into()),
+      ev => Err(format!("unexpected {ev} at {}", crate::sendpath::SendPath::from(path)).into()),
+    }
+  }
+}
+
+impl<'l> Handler<'l, Outline, OutlineWrapper, OutlineSender<'l>> for Outliner {
+  fn match_path(&self, _path : &JsonPath) -> bool {true}
+
+  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<&str>, tx : &mut OutlineSender<'l>)
+  -> Result<(),Box<dyn std::error::Error>>
+  {
+    let scalar = match ev {
+      JsonEvent::String(v) if v.chars().count() > STRING_LENGTH => {
+        let truncated = v.chars().take(STRING_LENGTH).collect::<String>();
+        format!("{}…", serde_json::Value::String(truncated))
+      }
+      JsonEvent::String(v) => serde_json::Value::String(v.into()).to_string(),
+      JsonEvent::Number(v) => v.into(),
+      JsonEvent::Boolean(v) => v.to_string(),
+      JsonEvent::Null => "null".into(),
+      ev => return Err(format!("{ev} is not a leaf").into()),
+    };
+    tx.send(Event::Value(path.into(), Outline::Scalar(scalar)).into())
+  }
+
+  // depth is 1 for the top-level container, and one more for each level of nesting.
+  fn array(&self, jevs : &mut dyn JsonEventSource<String>, parents : JsonPath, depth : usize, tx : &mut OutlineSender<'l>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    if depth > self.depth { return self.summarize(jevs, parents, '[', tx) }
+    tx.send(Event::Value((&parents).into(), Outline::Open('[')).into())?;
+    let mut index = 0;
+    loop {
+      match jevs.next_event()? {
+        JsonEvent::EndArray => return tx.send(Event::Value(parents.into(), Outline::Close(']')).into()),
+        ev => self.child(jevs, parents.push_back(index.into()), ev, depth, tx)?,
+      }
+      index += 1;
+    }
+  }
+
+  fn object(&self, jevs : &mut dyn JsonEventSource<String>, parents : JsonPath, depth : usize, tx : &mut OutlineSender<'l>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    if depth > self.depth { return self.summarize(jevs, parents, '{', tx) }
+    tx.send(Event::Value((&parents).into(), Outline::Open('{')).into())?;
+    loop {
+      match jevs.next_event()? {
+        JsonEvent::EndObject => return tx.send(Event::Value(parents.into(), Outline::Close('}')).into()),
+        JsonEvent::ObjectKey(key) => {
+          let ev = jevs.next_event()?;
+          self.child(jevs, parents.push_back(key.into()), ev, depth, tx)?
+        }
+        ev => self.child(jevs, parents.clone(), ev, depth, tx)?,
+      }
+    }
+  }
+}
+
+/// Writes the outline, indented by depth.
+pub struct OutlineWriter<'w>(pub &'w mut dyn std::io::Write);
+
+impl Sender<Event<Outline>, OutlineWrapper> for OutlineWriter<'_> {
+  fn send(&mut self, ev : OutlineWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    let (path, outline) = match ev.as_ref() {
+      Event::Value(path, outline) => (path, outline),
+      Event::Error(path, err) => return Err(format!("{err} at path '{path}'").into()),
+      Event::Path(..) | Event::Finished => return Ok(()),
+    };
+    let indent = "  ".repeat(path.len());
+    // closing brackets line up with their opening line, which has the key
+    let key = match (path.last(), outline) {
+      (_, Outline::Close(_)) | (None | Some(Step::Index(_)), _) => String::new(),
+      (Some(Step::Key(key)), _) => format!("{}: ", serde_json::Value::String(key)),
+    };
+    match outline {
+      Outline::Open(c) | Outline::Close(c) => writeln!(self.0, "{indent}{key}{c}")?,
+      Outline::Scalar(scalar) => writeln!(self.0, "{indent}{key}{scalar}")?,
+      Outline::Summary{open, children, bytes} => {
+        let (close, noun) = if *open == '[' { (']', "items") } else { ('}', "keys") };
+        let bytes = bytes.map(|bytes| format!(", {bytes} bytes")).unwrap_or_default();
+        writeln!(self.0, "{indent}{key}{open}… {children} {noun}{bytes}{close}")?
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Write the outline of the input down to depth.
+pub fn outline(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, depth : usize) -> Result<(), Box<dyn std::error::Error>> {
+  Outliner{depth}.value(jev, JsonPath::new(), 0, &mut OutlineWriter(wr))
+}
+
+#[cfg(test)]
+mod test_outline {
+  const JSON : &str = r#"[
+  {"id": 1, "tags": ["a", "b"], "payload": {"commits": [{"sha": "x"}], "size": 1}},
+  [1, [2, 3]]
+]"#;
+
+  fn outline_of(json : &'static str, depth : usize) -> String {
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(json.as_bytes())).with_positions();
+    let mut out = vec![];
+    super::outline(&mut out, &mut jevstream, depth).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn depth_2() {
+    let expected = r#"[
+  {
+    "id": 1
+    "tags": [… 2 items, 10 bytes]
+    "payload": {… 2 keys, 38 bytes}
+  }
+  [
+    1
+    [… 2 items, 6 bytes]
+  ]
+]
+"#;
+    assert_eq!(outline_of(JSON, 2), expected);
+  }
+
+  #[test]
+  fn depth_0() {
+    assert_eq!(outline_of(JSON, 0), format!("[… 2 items, {} bytes]\n", JSON.len()));
+  }
+
+  #[test]
+  fn truncated() {
+    use crate::parser::JsonEvent;
+
+    // events that stop without closing their containers, which the parser never does, but other sources might
+    struct Truncated(std::collections::VecDeque<JsonEvent<String>>, JsonEvent<String>);
+
+    impl crate::parser::JsonEventSource<'_, String> for Truncated {
+      fn next_event(&mut self) -> Result<JsonEvent<String>, Box<dyn std::error::Error>> {
+        Ok(self.0.pop_front().unwrap_or(JsonEvent::Eof))
+      }
+
+      fn next_borrowed(&mut self) -> Result<JsonEvent<&str>, Box<dyn std::error::Error>> {
+        self.1 = self.next_event()?;
+        Ok(self.1.as_deref())
+      }
+    }
+
+    // [{"a": 1}, [2
+    let events = vec![JsonEvent::StartArray, JsonEvent::StartObject, JsonEvent::ObjectKey("a".into()), JsonEvent::Number("1".into()), JsonEvent::EndObject, JsonEvent::StartArray, JsonEvent::Number("2".into())];
+    let err = super::outline(&mut vec![], &mut Truncated(events.clone().into(), JsonEvent::Eof), 2).unwrap_err();
+    assert_eq!(err.to_string(), "unexpected end of input in 1");
+    // and below the outline depth
+    let err = super::outline(&mut vec![], &mut Truncated(events.into(), JsonEvent::Eof), 1).unwrap_err();
+    assert_eq!(err.to_string(), "unexpected end of input in 1");
+  }
+
+  #[test]
+  fn scalars() {
+    assert_eq!(outline_of(r#""just a string""#, 0), "\"just a string\"\n");
+    assert_eq!(outline_of(r#"{"a": {}}"#, 1), "{\n  \"a\": {… 0 keys, 2 bytes}\n}\n");
+  }
+}
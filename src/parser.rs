@@ -3,9 +3,9 @@ The interface to the parser, currently json-event-parser.
 */
 
 /// Mirror of `json_event_parser::JsonEvent`
-/// But which doesn't have the `&str` reference into a buffer.
-/// Consequently it must be entirely cloned.
-/// Which is exactly what implements allows it to implement the `Send` trait.
+/// But with the text as T. Which is `String` for events that are sent to other
+/// threads, because then they must be entirely cloned. And `&str` for events
+/// that are handled before the next one is read, which borrow from the parser's buffer.
 #[derive(Eq, PartialEq, Debug, Clone, Hash, Copy)]
 pub enum JsonEvent<T>
 where T : AsRef<[u8]> // because we want storage
@@ -35,6 +35,71 @@ where T : AsRef<[u8]> + std::fmt::Debug + std::fmt::Display
   }
 }
 
+impl<T> JsonEvent<T>
+where T : AsRef<[u8]>
+{
+  /// The same event, with its text converted by f.
+  pub fn map<U : AsRef<[u8]>>(self, f : impl FnOnce(T) -> U) -> JsonEvent<U> {
+    match self {
+      JsonEvent::String(v) => JsonEvent::String(f(v)),
+      JsonEvent::Number(v) => JsonEvent::Number(f(v)),
+      JsonEvent::Boolean(v) => JsonEvent::Boolean(v),
+      JsonEvent::Null => JsonEvent::Null,
+      JsonEvent::StartArray => JsonEvent::StartArray,
+      JsonEvent::EndArray => JsonEvent::EndArray,
+      JsonEvent::StartObject => JsonEvent::StartObject,
+      JsonEvent::EndObject => JsonEvent::EndObject,
+      JsonEvent::ObjectKey(v) => JsonEvent::ObjectKey(f(v)),
+      JsonEvent::Eof => JsonEvent::Eof,
+      JsonEvent::Error{line, col, message} => JsonEvent::Error{line, col, message: f(message)},
+    }
+  }
+
+  /// The same event, borrowing its text from this one.
+  pub fn as_deref(&self) -> JsonEvent<&str>
+  where T : AsRef<str>
+  {
+    match self {
+      JsonEvent::String(v) => JsonEvent::String(v.as_ref()),
+      JsonEvent::Number(v) => JsonEvent::Number(v.as_ref()),
+      JsonEvent::ObjectKey(v) => JsonEvent::ObjectKey(v.as_ref()),
+      JsonEvent::Error{line, col, message} => JsonEvent::Error{line: *line, col: *col, message: message.as_ref()},
+      JsonEvent::Boolean(v) => JsonEvent::Boolean(*v),
+      JsonEvent::Null => JsonEvent::Null,
+      JsonEvent::StartArray => JsonEvent::StartArray,
+      JsonEvent::EndArray => JsonEvent::EndArray,
+      JsonEvent::StartObject => JsonEvent::StartObject,
+      JsonEvent::EndObject => JsonEvent::EndObject,
+      JsonEvent::Eof => JsonEvent::Eof,
+    }
+  }
+
+  /// The same event without its text, for when only the kind of event matters.
+  /// Which is useful when the text is borrowed from the source that's read next.
+  pub fn without_text(&self) -> JsonEvent<&'static str> {
+    match self {
+      JsonEvent::String(_) => JsonEvent::String(""),
+      JsonEvent::Number(_) => JsonEvent::Number(""),
+      JsonEvent::ObjectKey(_) => JsonEvent::ObjectKey(""),
+      JsonEvent::Error{line, col, ..} => JsonEvent::Error{line: *line, col: *col, message: ""},
+      JsonEvent::Boolean(v) => JsonEvent::Boolean(*v),
+      JsonEvent::Null => JsonEvent::Null,
+      JsonEvent::StartArray => JsonEvent::StartArray,
+      JsonEvent::EndArray => JsonEvent::EndArray,
+      JsonEvent::StartObject => JsonEvent::StartObject,
+      JsonEvent::EndObject => JsonEvent::EndObject,
+      JsonEvent::Eof => JsonEvent::Eof,
+    }
+  }
+}
+
+impl JsonEvent<&str> {
+  /// Copy the text, so the event can outlive the source it was borrowed from, eg to send it to another thread.
+  pub fn into_owned(self) -> JsonEvent<String> {
+    self.map(String::from)
+  }
+}
+
 #[test]
 fn create() {
   let _je = JsonEvent::String("hello".to_string());
@@ -94,14 +159,321 @@ pub trait JsonEventSource<'l, Stringish>
 where Stringish : 'l + AsRef<[u8]> + From<String> // because we want storage + conversion from Cow<'_,str>
 {
    fn next_event(&mut self) -> Result<JsonEvent<Stringish>, Box<dyn std::error::Error>>;
+
+   /// The next event, with its text borrowed from the source, which means it has to be finished
+   /// with before the next call.
+   ///
+   /// Only JsonEventParser lends its text straight from its buffer, so that there's no allocation
+   /// for each token. Sources whose events arrive already owned, like the channel from rapidjson and
+   /// the jq stream reader, keep the current event and lend that, so for them this saves nothing.
+   fn next_borrowed(&mut self) -> Result<JsonEvent<&str>, Box<dyn std::error::Error>>;
+
+   /// Number of bytes of the input parsed so far, if the source knows that.
+   ///
+   /// This is shared rather than a plain number, so it can be read while the source is busy parsing.
+   fn byte_counter(&self) -> Option<ByteCounter> { None }
+
+   /// Where the most recent event started in the input, if the source knows that.
+   ///
+   /// Shared for the same reason as byte_counter.
+   fn position_tracker(&self) -> Option<PositionTracker> { None }
+}
+
+/// Shared count of bytes parsed by a JsonEventSource.
+pub type ByteCounter = std::sync::Arc<std::sync::atomic::AtomicU64>;
+
+/// A place in the input. All 0-based, like the positions in parse errors. Column is in characters, not bytes.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,PartialOrd,Ord)]
+pub struct Position {
+  pub offset : u64,
+  pub line : u64,
+  pub column : u64,
+}
+
+impl Position {
+  // move past text
+  fn advance(&mut self, text : &[u8]) {
+    // continuation bytes are not the start of a character
+    let chars = |text : &[u8]| text.iter().filter(|b| (**b & 0xC0) != 0x80).count() as u64;
+    self.offset += text.len() as u64;
+    match text.iter().rposition(|b| *b == b'\n') {
+      Some(last) => {
+        self.line += text.iter().filter(|b| **b == b'\n').count() as u64;
+        self.column = chars(&text[last+1..]);
+      }
+      None => self.column += chars(text),
+    }
+  }
+
+  // move past text, with the column in bytes rather than characters, because counting those is most of the cost
+  fn advance_lines(&mut self, text : &[u8]) {
+    self.offset += text.len() as u64;
+    match text.iter().rposition(|b| *b == b'\n') {
+      Some(last) => {
+        self.line += text.iter().filter(|b| **b == b'\n').count() as u64;
+        self.column = (text.len() - last - 1) as u64;
+      }
+      None => self.column += text.len() as u64,
+    }
+  }
 }
 
+impl std::fmt::Display for Position {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+    // same as the parse errors
+    write!(f, "{}:{}", self.line+1, self.column+1)
+  }
+}
+
+/// Atomic version of Position, so that it can be shared.
+#[derive(Debug,Default)]
+pub struct SharedPosition {
+  offset : std::sync::atomic::AtomicU64,
+  line : std::sync::atomic::AtomicU64,
+  column : std::sync::atomic::AtomicU64,
+}
+
+impl SharedPosition {
+  pub fn get(&self) -> Position {
+    use std::sync::atomic::Ordering::Relaxed;
+    Position{offset: self.offset.load(Relaxed), line: self.line.load(Relaxed), column: self.column.load(Relaxed)}
+  }
+
+  fn set(&self, position : Position) {
+    use std::sync::atomic::Ordering::Relaxed;
+    self.offset.store(position.offset, Relaxed);
+    self.line.store(position.line, Relaxed);
+    self.column.store(position.column, Relaxed);
+  }
+}
+
+/// Shared position of the most recent event from a JsonEventSource.
+pub type PositionTracker = std::sync::Arc<SharedPosition>;
+
+// same as json_event_parser::FromReadJsonReader
+const MIN_BUFFER_SIZE : usize = 4096;
+const MAX_BUFFER_SIZE : usize = 4096 * 4096;
+
 /// Source of json events from json_event_parser
-pub struct JsonEventParser(json_event_parser::FromReadJsonReader<Box<dyn std::io::BufRead>>);
+///
+/// This does its own buffering, rather than using json_event_parser::FromReadJsonReader,
+/// because that doesn't say where in the input each event is.
+pub struct JsonEventParser {
+  reader : Box<dyn std::io::BufRead>,
+  parser : json_event_parser::LowLevelJsonReader,
+  buffer : Vec<u8>,
+  // buffer[start..end] is read but not yet parsed
+  start : usize,
+  end : usize,
+  is_ending : bool,
+  bytes : ByteCounter,
+  // end of the parsed input
+  parsed : Position,
+  // start of the first token after the previous event
+  token_start : Option<Position>,
+  // None unless asked for, because it means looking at every byte
+  position : Option<PositionTracker>,
+  // Some when reading a stream of values rather than a single value
+  ndjson : Option<Ndjson>,
+  // nesting of the current ndjson value
+  depth : usize,
+  // where the parser started, because its error positions are relative to that
+  origin : Position,
+  // the text of the current event, when it isn't in buffer, ie unescaped strings and error messages
+  scratch : String,
+}
+
+// Where an ndjson stream is, which is presented as the elements of an array.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Ndjson {
+  // the start of the array is next
+  Start,
+  Values,
+  // the end of the array has been sent
+  Done,
+}
 
 impl JsonEventParser {
   pub fn new(istream : Box<dyn std::io::BufRead>) -> Self {
-    Self(json_event_parser::FromReadJsonReader::new(istream))
+    Self {
+      reader: istream,
+      parser: json_event_parser::LowLevelJsonReader::new(),
+      buffer: Vec::new(),
+      start: 0,
+      end: 0,
+      is_ending: false,
+      bytes: ByteCounter::default(),
+      parsed: Position::default(),
+      token_start: None,
+      position: None,
+      ndjson: None,
+      depth: 0,
+      origin: Position::default(),
+      scratch: String::new(),
+    }
+  }
+
+  /// Read a stream of values separated by whitespace, usually one per line, as if they
+  /// were the elements of a top-level array. So their paths are the same as for an array.
+  pub fn with_ndjson(mut self) -> Self {
+    self.ndjson = Some(Ndjson::Start);
+    self
+  }
+
+  /// Track the line and column of each event, for position_tracker. Otherwise only ndjson
+  /// keeps count of lines, for its parse errors, with the columns of those in bytes.
+  pub fn with_positions(mut self) -> Self {
+    self.position = Some(PositionTracker::default());
+    self
+  }
+
+  fn set_position(&self, position : Position) {
+    if let Some(tracker) = &self.position { tracker.set(position) }
+  }
+
+  // Skip the whitespace between ndjson values. false at the end of the input.
+  fn skip_whitespace(&mut self) -> std::io::Result<bool> {
+    loop {
+      let text = &self.buffer[self.start..self.end];
+      let whitespace = text.iter().take_while(|b| b.is_ascii_whitespace()).count();
+      self.parsed.advance(&text[..whitespace]);
+      self.start += whitespace;
+      self.bytes.fetch_add(whitespace as u64, std::sync::atomic::Ordering::Relaxed);
+      if self.start < self.end { return Ok(true) }
+      if self.is_ending { return Ok(false) }
+      self.fill_buffer()?;
+    }
+  }
+
+  // The array around ndjson values, and a new parser for each value, because the parser only accepts one.
+  fn ndjson_event<Stringish : AsRef<[u8]>>(&mut self) -> Result<Option<JsonEvent<Stringish>>, Box<dyn std::error::Error>> {
+    match self.ndjson {
+      Some(Ndjson::Start) => {
+        self.ndjson = Some(Ndjson::Values);
+        self.set_position(self.parsed);
+        Ok(Some(JsonEvent::StartArray))
+      }
+      Some(Ndjson::Values) if self.depth == 0 => {
+        if self.skip_whitespace().map_err(|err| format!("{err:?}"))? {
+          self.parser = json_event_parser::LowLevelJsonReader::new();
+          self.origin = self.parsed;
+          Ok(None)
+        } else {
+          self.ndjson = Some(Ndjson::Done);
+          self.set_position(self.parsed);
+          Ok(Some(JsonEvent::EndArray))
+        }
+      }
+      Some(Ndjson::Done) => Ok(Some(JsonEvent::Eof)),
+      Some(Ndjson::Values) | None => Ok(None),
+    }
+  }
+
+  // Move parsed past text, and note where the first token in it is.
+  fn track(parsed : &mut Position, token_start : &mut Option<Position>, text : &[u8]) {
+    if token_start.is_none() {
+      // separators and whitespace are not tokens that have events
+      if let Some(skip) = text.iter().position(|b| !matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b',' | b':')) {
+        let mut start = *parsed;
+        start.advance(&text[..skip]);
+        *token_start = Some(start);
+      }
+    }
+    parsed.advance(text);
+  }
+
+  // Move the unparsed part of buffer to the front, and read some more after it.
+  fn fill_buffer(&mut self) -> std::io::Result<()> {
+    if self.start > 0 {
+      self.buffer.copy_within(self.start..self.end, 0);
+      self.end -= self.start;
+      self.start = 0;
+    }
+    if self.buffer.len() == MAX_BUFFER_SIZE {
+      return Err(std::io::Error::new(std::io::ErrorKind::OutOfMemory, format!("Reached the buffer maximal size of {MAX_BUFFER_SIZE}")))
+    }
+    let min_end = std::cmp::min(self.end + MIN_BUFFER_SIZE, MAX_BUFFER_SIZE);
+    if self.buffer.len() < min_end {
+      self.buffer.resize(min_end, 0);
+    }
+    let read = self.reader.read(&mut self.buffer[self.end..])?;
+    self.end += read;
+    self.is_ending = read == 0;
+    Ok(())
+  }
+}
+
+impl JsonEventParser {
+  // The text is in buffer, unless the parser had to unescape it.
+  fn text<'a>(scratch : &'a mut String, cow : std::borrow::Cow<'a, str>) -> &'a str {
+    match cow {
+      std::borrow::Cow::Borrowed(text) => text,
+      std::borrow::Cow::Owned(text) => { *scratch = text; scratch }
+    }
+  }
+
+  /// See JsonEventSource::next_borrowed
+  pub fn next_borrowed(&mut self) -> Result<JsonEvent<&str>, Box<dyn std::error::Error>> {
+    if let Some(ev) = self.ndjson_event()? { return Ok(ev) }
+    loop {
+      // SAFETY: Borrow checker workaround https://github.com/rust-lang/rust/issues/70255, same as
+      // json_event_parser::FromReadJsonReader. buffer is only changed by fill_buffer, which is
+      // only called when there's no event borrowing from it.
+      #[allow(unsafe_code)]
+      let input : &[u8] = unsafe { &*(&self.buffer[self.start..self.end] as *const [u8]) };
+      let json_event_parser::LowLevelJsonReaderResult{event, consumed_bytes} = self.parser.read_next_event(input, self.is_ending);
+      if self.position.is_some() {
+        Self::track(&mut self.parsed, &mut self.token_start, &input[..consumed_bytes]);
+      } else if self.ndjson.is_some() {
+        self.parsed.advance_lines(&input[..consumed_bytes]);
+      }
+      self.start += consumed_bytes;
+      // rather than counting what fill_buffer reads, which can be a whole buffer ahead
+      self.bytes.fetch_add(consumed_bytes as u64, std::sync::atomic::Ordering::Relaxed);
+
+      match event {
+        None => self.fill_buffer().map_err(|err| format!("{err:?}"))?,
+        Some(event) => {
+          let start = self.token_start.take().unwrap_or(self.parsed);
+          self.set_position(start);
+          return match event {
+            Ok(jep_event) => {
+              use json_event_parser::JsonEvent as jep;
+              if self.ndjson.is_some() {
+                match jep_event {
+                  jep::StartArray | jep::StartObject => self.depth += 1,
+                  jep::EndArray | jep::EndObject => self.depth -= 1,
+                  _ => (),
+                }
+              }
+              Ok(match jep_event {
+                jep::String(v) => JsonEvent::String(Self::text(&mut self.scratch, v)),
+                jep::Number(v) => JsonEvent::Number(Self::text(&mut self.scratch, v)),
+                jep::ObjectKey(v) => JsonEvent::ObjectKey(Self::text(&mut self.scratch, v)),
+                jep::Boolean(v) => JsonEvent::Boolean(v),
+                jep::Null => JsonEvent::Null,
+                jep::StartArray => JsonEvent::StartArray,
+                jep::EndArray => JsonEvent::EndArray,
+                jep::StartObject => JsonEvent::StartObject,
+                jep::EndObject => JsonEvent::EndObject,
+                jep::Eof => JsonEvent::Eof,
+              })
+            }
+            Err(syntax_error) => {
+              use std::ops::Range;
+              use json_event_parser::TextPosition;
+
+              // can't match because private fields
+              // json_event_parser::SyntaxError{location, message}
+              let Range{start, ..} : Range<TextPosition> = syntax_error.location();
+              self.scratch = syntax_error.message().into();
+              let col = if start.line == 0 { start.column + self.origin.column } else { start.column };
+              Ok(JsonEvent::Error{line : start.line + self.origin.line, col, message: &self.scratch})
+            }
+          }
+        }
+      }
+    }
   }
 }
 
@@ -110,23 +482,19 @@ where
   Stringish : AsRef<[u8]> + From<std::string::String> + 'l
 {
   fn next_event(&mut self) -> Result<JsonEvent<Stringish>, Box<dyn std::error::Error>> {
-    use json_event_parser::ParseError;
-    match self.0.read_next_event() {
-      Ok(ref jep_event) => Ok(jep_event.into()),
-      Err(ParseError::Io(err)) => Err(format!("{err:?}").into()),
-      Err(ParseError::Syntax(syntax_error)) => {
-        use std::ops::Range;
-        // use json_event_parser::SyntaxError;
-        use json_event_parser::TextPosition;
-
-        // can't match because private fields
-        // json_event_parser::SyntaxError{location, message}
-        let Range{start, ..} : Range<TextPosition> = syntax_error.location();
-        let msg : String = syntax_error.message().into();
-        let ev = JsonEvent::Error{line : start.line, col : start.column, message: msg.into()};
-        Ok(ev)
-      }
-    }
+    Ok(JsonEventParser::next_borrowed(self)?.map(|text| Stringish::from(text.to_string())))
+  }
+
+  fn next_borrowed(&mut self) -> Result<JsonEvent<&str>, Box<dyn std::error::Error>> {
+    JsonEventParser::next_borrowed(self)
+  }
+
+  fn byte_counter(&self) -> Option<ByteCounter> {
+    Some(self.bytes.clone())
+  }
+
+  fn position_tracker(&self) -> Option<PositionTracker> {
+    self.position.clone()
   }
 }
 
@@ -140,3 +508,122 @@ impl Iterator for JsonEventParser {
     }
   }
 }
+
+#[cfg(test)]
+mod test_position {
+  use super::*;
+
+  // (event, position) for all events
+  fn positions(json : &'static str) -> Vec<(JsonEvent<String>, String)> {
+    let mut parser = JsonEventParser::new(Box::new(json.as_bytes())).with_positions();
+    let tracker = JsonEventSource::<String>::position_tracker(&parser).unwrap();
+    let mut events = vec![];
+    loop {
+      let ev : JsonEvent<String> = parser.next_event().unwrap();
+      if ev == JsonEvent::Eof { break events }
+      let position = tracker.get();
+      events.push((ev, format!("{position} @{}", position.offset)));
+    }
+  }
+
+  #[test]
+  fn lines_and_columns() {
+    let events = positions("[\n  {\"ä\": 1,\n   \"b\" :\"x\"},\n  null]");
+    assert_eq!(events, vec![
+      (JsonEvent::StartArray, "1:1 @0".into()),
+      (JsonEvent::StartObject, "2:3 @4".into()),
+      (JsonEvent::ObjectKey("ä".into()), "2:4 @5".into()),
+      (JsonEvent::Number("1".into()), "2:9 @11".into()),
+      (JsonEvent::ObjectKey("b".into()), "3:4 @17".into()),
+      (JsonEvent::String("x".into()), "3:9 @22".into()),
+      (JsonEvent::EndObject, "3:12 @25".into()),
+      (JsonEvent::Null, "4:3 @30".into()),
+      (JsonEvent::EndArray, "4:7 @34".into()),
+    ]);
+  }
+
+  #[test]
+  fn across_buffers() {
+    // longer than MIN_BUFFER_SIZE, so tokens get split over reads
+    let json : &'static str = Box::leak(format!("[{}\"last\"]", "\"abcdefgh\",\n".repeat(1000)).into_boxed_str());
+    let events = positions(json);
+    assert_eq!(events[1000], (JsonEvent::String("abcdefgh".into()), "1000:1 @11989".into()));
+    assert_eq!(events[1001], (JsonEvent::String("last".into()), "1001:1 @12001".into()));
+  }
+
+  #[test]
+  fn ndjson() {
+    let mut parser = JsonEventParser::new(Box::new("{\"a\": [1]}\n\n2\n\"x\"".as_bytes())).with_ndjson().with_positions();
+    let tracker = JsonEventSource::<String>::position_tracker(&parser).unwrap();
+    let mut events = vec![];
+    loop {
+      let ev : JsonEvent<String> = parser.next_event().unwrap();
+      if ev == JsonEvent::Eof { break }
+      events.push((ev, tracker.get().to_string()));
+    }
+    assert_eq!(events, vec![
+      (JsonEvent::StartArray, "1:1".into()),
+      (JsonEvent::StartObject, "1:1".into()),
+      (JsonEvent::ObjectKey("a".into()), "1:2".into()),
+      (JsonEvent::StartArray, "1:7".into()),
+      (JsonEvent::Number("1".into()), "1:8".into()),
+      (JsonEvent::EndArray, "1:9".into()),
+      (JsonEvent::EndObject, "1:10".into()),
+      (JsonEvent::Number("2".into()), "3:1".into()),
+      (JsonEvent::String("x".into()), "4:1".into()),
+      (JsonEvent::EndArray, "4:4".into()),
+    ]);
+  }
+
+  #[test]
+  fn ndjson_error() {
+    let mut parser = JsonEventParser::new(Box::new("1\n{\"a\" 2}".as_bytes())).with_ndjson();
+    let events : Vec<JsonEvent<String>> = (0..5).map(|_| parser.next_event().unwrap()).collect();
+    match &events[4] {
+      JsonEvent::Error{line, col, ..} => assert_eq!((*line, *col), (1, 5), "{events:?}"),
+      ev => panic!("{ev}"),
+    }
+  }
+
+  #[test]
+  fn only_when_asked() {
+    let parser = JsonEventParser::new(Box::new("[1]".as_bytes()));
+    assert!(JsonEventSource::<String>::position_tracker(&parser).is_none());
+  }
+}
+
+#[cfg(test)]
+mod test_borrowed {
+  use super::*;
+
+  fn borrowed(json : &'static str) -> Vec<JsonEvent<String>> {
+    let mut parser = JsonEventParser::new(Box::new(json.as_bytes()));
+    let mut events = vec![];
+    loop {
+      let ev = parser.next_borrowed().unwrap();
+      if matches!(ev, JsonEvent::Eof | JsonEvent::Error{..}) { events.push(ev.into_owned()); break }
+      events.push(ev.into_owned());
+    }
+    events
+  }
+
+  #[test]
+  fn escaped_and_plain() {
+    use JsonEvent::*;
+    let events = borrowed(r#"{"a\nb": "plain", "n": [1.5, true]}"#);
+    assert_eq!(events, vec![
+      StartObject, ObjectKey("a\nb".into()), String("plain".into()),
+      ObjectKey("n".into()), StartArray, Number("1.5".into()), Boolean(true), EndArray,
+      EndObject, Eof,
+    ]);
+  }
+
+  #[test]
+  fn same_as_owned() {
+    let json = r#"[{"k": "été"}, 2, {"broken": }]"#;
+    let owned = JsonEventParser::new(Box::new(json.as_bytes())).take_while(|ev| !matches!(ev, JsonEvent::Eof | JsonEvent::Error{..})).collect::<Vec<_>>();
+    let borrowed = borrowed(json);
+    assert_eq!(&borrowed[..owned.len()], &owned[..]);
+    assert!(matches!(borrowed.last(), Some(JsonEvent::Error{line: 0, ..})), "{borrowed:?}");
+  }
+}
@@ -0,0 +1,331 @@
+/*!
+A dictionary of paths, so that a path can travel as a shared pointer to its interned steps instead of
+a Vec of steps.
+
+Paths are interned with their indexes taken out, ie `[4]/payload/ref` and `[5]/payload/ref` are
+the same entry. The number of concrete paths grows with the input, whereas the number of paths
+without indexes is the shape of the json, which is usually small. SendPath carries the indexes
+alongside the interned path.
+
+Each thread has its own dictionary, so interning doesn't take a lock. An interned path can't change,
+so it can be read on any thread, eg on the other side of a channel, without going through the
+dictionary, and it lives for as long as something refers to it. A dictionary keeps at most CAPACITY
+paths, after which the thread starts a new one, so that input with ever more distinct keys doesn't
+grow it without bound.
+
+Within a dictionary a PathId is a dense index, for consumers that keep something for each path in a
+Vec rather than looking up paths, see ById.
+*/
+
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+
+use rustc_hash::FxHashMap;
+
+use crate::jsonpath::Step;
+
+/// How many paths a thread's dictionary keeps before it starts again. Most inputs have far fewer.
+const CAPACITY : usize = 1 << 18;
+
+/// The id of a path, without its indexes.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct PathId {
+  // which dictionary, because every dictionary counts from 0
+  dictionary : u32,
+  index : u32,
+}
+
+impl PathId {
+  /// For indexing a Vec by PathId, which is only valid for paths from the same dictionary.
+  pub fn index(self) -> usize {
+    self.index as usize
+  }
+}
+
+/// One step of an interned path.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Link {
+  /// shared with the dictionary, so each key is only stored once
+  Key(Arc<str>),
+  /// any index, the actual one is kept by SendPath
+  Index,
+}
+
+#[derive(Debug)]
+struct Entry {
+  // None for the root
+  parent : Option<Interned>,
+  link : Option<Link>,
+  depth : usize,
+  id : PathId,
+}
+
+/// A path in a dictionary. Cloning it only counts a reference.
+#[derive(Clone)]
+pub struct Interned(Arc<Entry>);
+
+// the root of no dictionary, for SendPath::default
+static EMPTY : LazyLock<Interned> = LazyLock::new(|| Interned::root(0));
+
+impl Interned {
+  fn root(dictionary : u32) -> Self {
+    Self(Arc::new(Entry{parent: None, link: None, depth: 0, id: PathId{dictionary, index: 0}}))
+  }
+
+  /// The id of this path, which is the same for every path with the same steps from the same dictionary.
+  pub fn id(&self) -> PathId {
+    self.0.id
+  }
+
+  /// The number of links from the root.
+  pub fn depth(&self) -> usize {
+    self.0.depth
+  }
+
+  /// The path without its last link, or None for the root.
+  pub fn parent(&self) -> Option<&Interned> {
+    self.0.parent.as_ref()
+  }
+
+  /// The last link, or None for the root.
+  pub fn last(&self) -> Option<&Link> {
+    self.0.link.as_ref()
+  }
+
+  /// The first link, or None for the root.
+  pub fn first(&self) -> Option<&Link> {
+    self.links_rev().last()
+  }
+
+  /// The links from the last one back to the first, which doesn't allocate.
+  pub fn links_rev(&self) -> impl Iterator<Item = &Link> {
+    std::iter::successors(Some(self), |interned| interned.parent()).filter_map(Interned::last)
+  }
+
+  /// The links from the root.
+  pub fn links(&self) -> Vec<Link> {
+    let mut links = self.links_rev().cloned().collect::<Vec<Link>>();
+    links.reverse();
+    links
+  }
+}
+
+/// Only the links, eg `Interned([Key("a"), Index])`, rather than the chain of parents with their ids.
+impl std::fmt::Debug for Interned {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("Interned").field(&self.links()).finish()
+  }
+}
+
+impl Default for Interned {
+  fn default() -> Self {
+    EMPTY.clone()
+  }
+}
+
+impl PartialEq for Interned {
+  fn eq(&self, other : &Self) -> bool {
+    self.id() == other.id()
+  }
+}
+
+impl Eq for Interned {}
+
+// The children of an entry, for finding them by step.
+#[derive(Debug,Default)]
+struct Children {
+  keys : FxHashMap<Arc<str>, u32>,
+  index : Option<u32>,
+}
+
+// so that no two dictionaries have the same ids
+static SERIAL : AtomicU32 = AtomicU32::new(1);
+
+/// Interns paths. Each thread has one of these behind intern.
+#[derive(Debug)]
+pub struct Dictionary {
+  // both by PathId index
+  entries : Vec<Interned>,
+  children : Vec<Children>,
+}
+
+impl Default for Dictionary {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Dictionary {
+  pub fn new() -> Self {
+    let root = Interned::root(SERIAL.fetch_add(1, Ordering::Relaxed));
+    Self{entries: vec![root], children: vec![Children::default()]}
+  }
+
+  /// The number of paths, including the root.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Never, because there's always the root.
+  pub fn is_empty(&self) -> bool {
+    false
+  }
+
+  /// The path with steps, which is added if it isn't there yet.
+  pub fn intern<'a>(&mut self, steps : impl IntoIterator<Item = &'a Step>) -> Interned {
+    let index = steps.into_iter().fold(0, |parent, step| self.child(parent, step));
+    self.entries[index].clone()
+  }
+
+  // index of the child of parent at step
+  fn child(&mut self, parent : usize, step : &Step) -> usize {
+    let children = &mut self.children[parent];
+    let found = match step {
+      Step::Key(key) => children.keys.get(key.as_str()),
+      Step::Index(_) => children.index.as_ref(),
+    };
+    if let Some(child) = found { return *child as usize }
+
+    let index = u32::try_from(self.entries.len()).expect("more than 2^32 distinct paths");
+    let link = match step {
+      Step::Key(key) => {
+        let key : Arc<str> = key.as_str().into();
+        children.keys.insert(key.clone(), index);
+        Link::Key(key)
+      }
+      Step::Index(_) => {
+        children.index = Some(index);
+        Link::Index
+      }
+    };
+    let parent = self.entries[parent].clone();
+    let id = PathId{dictionary: parent.id().dictionary, index};
+    self.entries.push(Interned(Arc::new(Entry{depth: parent.depth() + 1, id, link: Some(link), parent: Some(parent)})));
+    self.children.push(Children::default());
+    index as usize
+  }
+}
+
+thread_local! {
+  static DICTIONARY : RefCell<Dictionary> = RefCell::new(Dictionary::new());
+}
+
+/// The path with steps from this thread's dictionary, which is added if it isn't there yet.
+pub fn intern<'a>(steps : impl IntoIterator<Item = &'a Step>) -> Interned {
+  DICTIONARY.with_borrow_mut(|dictionary| {
+    // the paths from the old one stay valid for as long as anything refers to them
+    if dictionary.len() >= CAPACITY { *dictionary = Dictionary::new() }
+    dictionary.intern(steps)
+  })
+}
+
+/// Start this thread on a new dictionary, as intern does when the old one is full.
+#[cfg(test)]
+pub(crate) fn restart() {
+  DICTIONARY.with_borrow_mut(|dictionary| *dictionary = Dictionary::new())
+}
+
+/// Something for each PathId, in a Vec, for consumers that would otherwise look up the path of every event.
+///
+/// It starts again whenever the ids come from a different dictionary.
+#[derive(Debug,Clone)]
+pub struct ById<T> {
+  dictionary : u32,
+  values : Vec<Option<T>>,
+}
+
+impl<T> Default for ById<T> {
+  fn default() -> Self {
+    Self{dictionary: 0, values: vec![]}
+  }
+}
+
+impl<T : Copy> ById<T> {
+  fn switch(&mut self, id : PathId) {
+    if id.dictionary != self.dictionary {
+      self.values.clear();
+      self.dictionary = id.dictionary;
+    }
+  }
+
+  pub fn get(&mut self, id : PathId) -> Option<T> {
+    self.switch(id);
+    self.values.get(id.index()).copied().flatten()
+  }
+
+  pub fn insert(&mut self, id : PathId, value : T) {
+    self.switch(id);
+    if self.values.len() <= id.index() { self.values.resize_with(id.index() + 1, || None) }
+    self.values[id.index()] = Some(value);
+  }
+}
+
+#[cfg(test)]
+mod test_pathdict {
+  use super::*;
+
+  fn key(key : &str) -> Link {
+    Link::Key(key.into())
+  }
+
+  #[test]
+  fn indexes_are_removed() {
+    let mut dictionary = Dictionary::new();
+    let four = dictionary.intern(&[Step::Index(4), Step::Key("payload".into()), Step::Key("ref".into())]);
+    let five = dictionary.intern(&[Step::Index(5), Step::Key("payload".into()), Step::Key("ref".into())]);
+    assert_eq!(four, five);
+    assert_eq!(four.links(), vec![Link::Index, key("payload"), key("ref")]);
+    assert_eq!(four.depth(), 3);
+    assert_eq!(four.last(), Some(&key("ref")));
+    assert_eq!(four.first(), Some(&Link::Index));
+    assert_eq!(format!("{four:?}"), r#"Interned([Index, Key("payload"), Key("ref")])"#);
+    assert_eq!(dictionary.len(), 4);
+  }
+
+  #[test]
+  fn distinct_paths() {
+    let mut dictionary = Dictionary::new();
+    let key_path = dictionary.intern(&[Step::Key("a".into()), Step::Key("b".into())]);
+    let index = dictionary.intern(&[Step::Key("a".into()), Step::Index(0)]);
+    let parent = dictionary.intern(&[Step::Key("a".into())]);
+    assert_ne!(key_path, index);
+    assert_ne!(key_path, parent);
+    assert_eq!(index.links(), vec![key("a"), Link::Index]);
+    assert_eq!(index.parent(), Some(&parent));
+  }
+
+  #[test]
+  fn keys_are_shared() {
+    let mut dictionary = Dictionary::new();
+    let path = dictionary.intern(&[Step::Key("a".into())]);
+    let (Some(Link::Key(link)), Some((key, _))) = (path.last(), dictionary.children[0].keys.iter().next()) else { panic!("{path:?}") };
+    assert!(Arc::ptr_eq(link, key));
+  }
+
+  #[test]
+  fn dictionaries() {
+    let steps = [Step::Key("a".into())];
+    let (one, other) = (Dictionary::new().intern(&steps), Dictionary::new().intern(&steps));
+    assert_eq!(one.id().index(), other.id().index());
+    assert_ne!(one, other);
+
+    let mut by_id = ById::default();
+    by_id.insert(one.id(), 1);
+    assert_eq!(by_id.get(one.id()), Some(1));
+    assert_eq!(by_id.get(other.id()), None);
+    assert_eq!(by_id.get(one.id()), None);
+  }
+
+  #[test]
+  fn root() {
+    let mut dictionary = Dictionary::new();
+    let root = dictionary.intern(&[]);
+    assert_eq!(root.id().index(), 0);
+    assert_eq!(root.links(), vec![]);
+    assert_eq!(root.last(), None);
+    assert_eq!(root.first(), None);
+  }
+}
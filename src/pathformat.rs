@@ -0,0 +1,285 @@
+/*!
+The notations for writing paths, and reading them back.
+
+The default `slash` notation is the one the schema output has always used, eg `[4]/payload/ref`.
+It's the easiest to read, but it isn't escaped, so a key containing `/`, or a key like `[4]`, is
+ambiguous. The others round-trip:
+
+- `pointer` is RFC 6901 JSON Pointer, eg `/4/payload/ref`, with `~1` for `/` and `~0` for `~`. As
+  in the RFC, a key that is all digits is read back as an index.
+- `jq` is a jq path array, as from `jq -c paths`, eg `[4,"payload","ref"]`.
+- `dotted` is like javascript, eg `[4].payload.ref`, with keys that aren't identifiers quoted, eg
+  `["a.b"]`.
+
+Schema paths also have "any index", which is `[]` in slash and dotted, `null` in jq, and `*` in
+pointer, where there's nothing better, so there a key `*` is ambiguous too.
+*/
+
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// The ways a path can be written.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum PathFormat {
+  /// `[4]/payload/ref`
+  #[default]
+  Slash,
+  /// `/4/payload/ref`
+  Pointer,
+  /// `[4,"payload","ref"]`
+  Jq,
+  /// `[4].payload.ref`
+  Dotted,
+}
+
+impl std::str::FromStr for PathFormat {
+  type Err = String;
+
+  fn from_str(s : &str) -> Result<Self, Self::Err> {
+    match s {
+      "slash" => Ok(PathFormat::Slash),
+      "pointer" => Ok(PathFormat::Pointer),
+      "jq" => Ok(PathFormat::Jq),
+      "dotted" => Ok(PathFormat::Dotted),
+      other => Err(format!("unknown path format {other}, expected slash, pointer, jq or dotted")),
+    }
+  }
+}
+
+// There's only one command line, so this is simpler than passing the format to everything that displays a path.
+static GLOBAL : AtomicU8 = AtomicU8::new(0);
+
+impl PathFormat {
+  /// Use this format wherever paths are displayed or read.
+  pub fn set_global(self) {
+    GLOBAL.store(self as u8, Relaxed)
+  }
+
+  /// The format set by set_global, otherwise Slash.
+  pub fn global() -> Self {
+    match GLOBAL.load(Relaxed) {
+      1 => PathFormat::Pointer,
+      2 => PathFormat::Jq,
+      3 => PathFormat::Dotted,
+      _ => PathFormat::Slash,
+    }
+  }
+}
+
+/// One step of a path, in a form that covers both the paths of values and the paths of schemas.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Part<'a> {
+  Key(std::borrow::Cow<'a, str>),
+  Index(u64),
+  /// any index, for schema paths
+  Any,
+}
+
+// Keys that are written without quotes in dotted paths. Only ascii, to be safe.
+fn is_identifier(key : &str) -> bool {
+  let mut chars = key.chars();
+  chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Keys that are read without quotes in dotted paths, which is anything javascript allows, because
+// that's what other tools write, eg gron writes `json.$id` and `json.café`.
+fn is_js_identifier(key : &str) -> bool {
+  let mut chars = key.chars();
+  chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+    && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == '\u{200c}' || c == '\u{200d}')
+}
+
+fn quoted(key : &str) -> String {
+  serde_json::Value::String(key.into()).to_string()
+}
+
+/// Write the path, with nothing for the root except in jq.
+pub fn write<'a>(parts : impl IntoIterator<Item = Part<'a>>, format : PathFormat) -> String {
+  let mut out = String::new();
+  for (i, part) in parts.into_iter().enumerate() {
+    match (format, part) {
+      (PathFormat::Slash, part) => {
+        if i > 0 { out.push('/') }
+        match part {
+          Part::Key(key) => out.push_str(&key),
+          Part::Index(index) => out.push_str(&format!("[{index}]")),
+          Part::Any => out.push_str("[]"),
+        }
+      }
+      (PathFormat::Pointer, Part::Key(key)) => out.push_str(&format!("/{}", key.replace('~', "~0").replace('/', "~1"))),
+      (PathFormat::Pointer, Part::Index(index)) => out.push_str(&format!("/{index}")),
+      (PathFormat::Pointer, Part::Any) => out.push_str("/*"),
+      (PathFormat::Jq, part) => {
+        out.push(if i == 0 { '[' } else { ',' });
+        match part {
+          Part::Key(key) => out.push_str(&quoted(&key)),
+          Part::Index(index) => out.push_str(&index.to_string()),
+          Part::Any => out.push_str("null"),
+        }
+      }
+      (PathFormat::Dotted, Part::Key(key)) if is_identifier(&key) => {
+        if i > 0 { out.push('.') }
+        out.push_str(&key)
+      }
+      (PathFormat::Dotted, Part::Key(key)) => out.push_str(&format!("[{}]", quoted(&key))),
+      (PathFormat::Dotted, Part::Index(index)) => out.push_str(&format!("[{index}]")),
+      (PathFormat::Dotted, Part::Any) => out.push_str("[]"),
+    }
+  }
+  match format {
+    PathFormat::Jq if out.is_empty() => "[]".into(),
+    PathFormat::Jq => out + "]",
+    _ => out,
+  }
+}
+
+// the parts of a dotted path
+fn read_dotted(path : &str) -> Result<Vec<Part<'static>>, String> {
+  let mut parts = vec![];
+  let mut rst = path;
+  while !rst.is_empty() {
+    if let Some(inside) = rst.strip_prefix('[') {
+      let (part, len) = if let Some(after) = inside.strip_prefix(']') {
+        (Part::Any, inside.len() - after.len() - 1)
+      } else if inside.starts_with('"') {
+        // the key is a json string, which ends at the first unescaped quote
+        let mut stream = serde_json::Deserializer::from_str(inside).into_iter::<String>();
+        let key = stream.next().ok_or("unterminated key")?.map_err(|err| format!("{err}"))?;
+        (Part::Key(key.into()), stream.byte_offset())
+      } else {
+        let digits = inside.find(']').ok_or("missing ]")?;
+        let index = inside[..digits].parse::<u64>().map_err(|err| format!("{}: {err}", &inside[..digits]))?;
+        (Part::Index(index), digits)
+      };
+      rst = inside[len..].strip_prefix(']').ok_or_else(|| format!("missing ] in {path}"))?;
+      parts.push(part);
+    } else {
+      let key = rst.strip_prefix('.').unwrap_or(rst);
+      let end = key.find(['.', '[']).unwrap_or(key.len());
+      if !is_js_identifier(&key[..end]) { return Err(format!("{} should be quoted in {path}", &key[..end])) }
+      parts.push(Part::Key(key[..end].to_string().into()));
+      rst = &key[end..];
+    }
+  }
+  Ok(parts)
+}
+
+/// Read a path written in format. `.` is the root in every format.
+pub fn read(path : &str, format : PathFormat) -> Result<Vec<Part<'static>>, String> {
+  if path == "." { return Ok(vec![]) }
+  match format {
+    PathFormat::Slash if path.is_empty() => Ok(vec![]),
+    PathFormat::Slash => Ok(path.split('/').map(|step| match step {
+      "[]" => Part::Any,
+      step => match step.strip_prefix('[').and_then(|rst| rst.strip_suffix(']')).map(str::parse::<u64>) {
+        Some(Ok(index)) => Part::Index(index),
+        _ => Part::Key(step.to_string().into()),
+      }
+    }).collect()),
+    PathFormat::Pointer if path.is_empty() => Ok(vec![]),
+    PathFormat::Pointer => {
+      let rst = path.strip_prefix('/').ok_or_else(|| format!("a json pointer starts with /, unlike {path}"))?;
+      Ok(rst.split('/').map(|step| match step {
+        "*" => Part::Any,
+        // no leading zeros, as in RFC 6901
+        step if step == "0" || (!step.starts_with('0') && !step.is_empty() && step.bytes().all(|b| b.is_ascii_digit())) => {
+          step.parse().map(Part::Index).unwrap_or_else(|_| Part::Key(step.to_string().into()))
+        }
+        step => Part::Key(step.replace("~1", "/").replace("~0", "~").into()),
+      }).collect())
+    }
+    PathFormat::Jq => {
+      let steps : Vec<serde_json::Value> = serde_json::from_str(path).map_err(|err| format!("{path}: {err}"))?;
+      steps.into_iter().map(|step| match step {
+        serde_json::Value::String(key) => Ok(Part::Key(key.into())),
+        serde_json::Value::Null => Ok(Part::Any),
+        serde_json::Value::Number(index) => index.as_u64().map(Part::Index).ok_or_else(|| format!("{index} is not an index")),
+        other => Err(format!("{other} is not a key or an index")),
+      }).collect()
+    }
+    PathFormat::Dotted => read_dotted(path),
+  }
+}
+
+impl<'a> From<&'a crate::jsonpath::Step> for Part<'a> {
+  fn from(step : &'a crate::jsonpath::Step) -> Self {
+    match step {
+      crate::jsonpath::Step::Key(key) => Part::Key(key.into()),
+      crate::jsonpath::Step::Index(index) => Part::Index(*index),
+    }
+  }
+}
+
+impl TryFrom<Part<'_>> for crate::jsonpath::Step {
+  type Error = String;
+
+  fn try_from(part : Part<'_>) -> Result<Self, Self::Error> {
+    match part {
+      Part::Key(key) => Ok(Self::Key(key.into_owned())),
+      Part::Index(index) => Ok(Self::Index(index)),
+      Part::Any => Err("any index is only for schema paths".into()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test_pathformat {
+  use super::*;
+  use crate::jsonpath::Step;
+
+  fn steps() -> Vec<Step> {
+    vec![Step::Index(4), Step::Key("a/b".into()), Step::Key("7".into()), Step::Key("say \"hi\"".into()), Step::Key("x~y".into()), Step::Key("ok_1".into())]
+  }
+
+  fn round_trip(format : PathFormat) -> (String, Vec<Step>) {
+    let written = write(steps().iter().map(Part::from), format);
+    let read_back = read(&written, format).unwrap().into_iter().map(|part| Step::try_from(part).unwrap()).collect();
+    (written, read_back)
+  }
+
+  #[test]
+  fn jq() {
+    let (written, read_back) = round_trip(PathFormat::Jq);
+    assert_eq!(written, r#"[4,"a/b","7","say \"hi\"","x~y","ok_1"]"#);
+    assert_eq!(read_back, steps());
+  }
+
+  #[test]
+  fn dotted() {
+    let (written, read_back) = round_trip(PathFormat::Dotted);
+    assert_eq!(written, r#"[4]["a/b"]["7"]["say \"hi\""]["x~y"].ok_1"#);
+    assert_eq!(read_back, steps());
+    let parts = read("payload.commits[].sha", PathFormat::Dotted).unwrap();
+    assert_eq!(parts, vec![Part::Key("payload".into()), Part::Key("commits".into()), Part::Any, Part::Key("sha".into())]);
+    // javascript identifiers, as gron writes them
+    assert_eq!(read("$id.café._x$", PathFormat::Dotted).unwrap(), vec![Part::Key("$id".into()), Part::Key("café".into()), Part::Key("_x$".into())]);
+    assert!(read("a.b-c", PathFormat::Dotted).is_err());
+  }
+
+  #[test]
+  fn pointer() {
+    let (written, read_back) = round_trip(PathFormat::Pointer);
+    assert_eq!(written, r#"/4/a~1b/7/say "hi"/x~0y/ok_1"#);
+    // as in RFC 6901, the digits-only key is an index
+    let mut expected = steps();
+    expected[2] = Step::Index(7);
+    assert_eq!(read_back, expected);
+    assert_eq!(read("/007/*", PathFormat::Pointer).unwrap(), vec![Part::Key("007".into()), Part::Any]);
+  }
+
+  #[test]
+  fn slash() {
+    assert_eq!(write(steps().iter().map(Part::from), PathFormat::Slash), r#"[4]/a/b/7/say "hi"/x~y/ok_1"#);
+    assert_eq!(read("[]/payload/[0]", PathFormat::Slash).unwrap(), vec![Part::Any, Part::Key("payload".into()), Part::Index(0)]);
+  }
+
+  #[test]
+  fn root() {
+    for format in [PathFormat::Slash, PathFormat::Pointer, PathFormat::Jq, PathFormat::Dotted] {
+      let written = write(vec![], format);
+      assert_eq!(read(&written, format).unwrap(), vec![], "{format:?}");
+      assert_eq!(read(".", format).unwrap(), vec![], "{format:?}");
+    }
+  }
+}
@@ -27,13 +27,13 @@ where SendWrapper : Send + std::ops::Deref<Target=sender::Event<SendValue>> + Fr
   }
 
   /// send the event provided the fn at self.0 returns true
-  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<String>, tx : &mut (dyn Sender<Event<SendValue>,SendWrapper> + 'l))
+  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<&str>, tx : &mut (dyn Sender<Event<SendValue>,SendWrapper> + 'l))
   -> Result<(),Box<dyn std::error::Error>>
   {
     if self.match_path(path) {
       use crate::sender::Event;
       tx
-        .send(Event::Value(path.into(), ev.clone()).into())
+        .send(Event::Value(path.into(), ev.into_owned()).into())
         .unwrap_or_else(|err| eprintln!("error sending {ev:?} because {err:?}"))
     };
     Ok(())
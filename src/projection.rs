@@ -0,0 +1,368 @@
+/*!
+Prints the parts of the input that match some paths, as json.
+
+The paths are a subset of JSONPath: `$` for the top-level value, then any of `.key`, `['key']`,
+`["key"]`, `[2]`, and `[*]` or `.*` for every element or key. There are no filters, slices, or
+recursive descent.
+
+A path selects the whole value at that path, and the output has the same nesting as the input,
+with everything that wasn't selected left out. So `$[*].actor.login` gives
+`[{"actor":{"login":"a"}},{"actor":{"login":"b"}}]`.
+
+Elements of arrays are compacted, ie they're numbered again without the ones that were left out.
+Or their positions are preserved, by putting `null` in the gaps. There's nothing after the last
+element that was selected, because the rest of the array was not read yet when it was written.
+*/
+
+use crate::handler::Container;
+use crate::handler::Handler;
+use crate::handler::Sample;
+use crate::jsonpath::JsonPath;
+use crate::jsonpath::Step;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::pathformat::Part;
+use crate::sender;
+use crate::sender::Event;
+use crate::sender::Sender;
+
+/// One step of a Pattern.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Selector {
+  Key(String),
+  Index(u64),
+  /// `[*]` or `.*`
+  Any,
+}
+
+impl Selector {
+  fn matches(&self, step : &Step) -> bool {
+    match (self, step) {
+      (Selector::Any, _) => true,
+      (Selector::Key(key), Step::Key(step)) => key == step,
+      (Selector::Index(index), Step::Index(step)) => index == step,
+      _ => false,
+    }
+  }
+}
+
+/// A JSONPath without filters, slices or recursive descent, eg `$[*].actor.login`
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Pattern(pub Vec<Selector>);
+
+impl std::str::FromStr for Pattern {
+  type Err = String;
+
+  fn from_str(path : &str) -> Result<Self, Self::Err> {
+    let mut rst = path.strip_prefix('$').ok_or_else(|| format!("{path} should start with $"))?;
+    let mut selectors = vec![];
+    while !rst.is_empty() {
+      if rst.starts_with("..") { return Err(format!("recursive descent isn't supported, in {path}")) }
+      if let Some(after) = rst.strip_prefix(".*") {
+        selectors.push(Selector::Any);
+        rst = after;
+      } else if let Some(after) = rst.strip_prefix('.') {
+        let end = after.find(['.', '[']).unwrap_or(after.len());
+        if end == 0 { return Err(format!("missing key after . in {path}")) }
+        selectors.push(Selector::Key(after[..end].into()));
+        rst = &after[end..];
+      } else if let Some(inside) = rst.strip_prefix('[') {
+        let (selector, len) = if inside.starts_with('*') {
+          (Selector::Any, 1)
+        } else if inside.starts_with('"') {
+          // a json string, which ends at the first unescaped quote
+          let mut stream = serde_json::Deserializer::from_str(inside).into_iter::<String>();
+          let key = stream.next().ok_or("unterminated key")?.map_err(|err| format!("{err} in {path}"))?;
+          (Selector::Key(key), stream.byte_offset())
+        } else if let Some(quoted) = inside.strip_prefix('\'') {
+          let end = quoted.find('\'').ok_or_else(|| format!("unterminated key in {path}"))?;
+          (Selector::Key(quoted[..end].into()), end + 2)
+        } else {
+          let end = inside.find(']').ok_or_else(|| format!("missing ] in {path}"))?;
+          let index = inside[..end].parse::<u64>().map_err(|_| format!("{} isn't supported in {path}, only indexes, keys and *", &inside[..end]))?;
+          (Selector::Index(index), end)
+        };
+        rst = inside[len..].strip_prefix(']').ok_or_else(|| format!("missing ] in {path}"))?;
+        selectors.push(selector);
+      } else {
+        return Err(format!("expected . or [ at {rst} in {path}"))
+      }
+    }
+    Ok(Pattern(selectors))
+  }
+}
+
+/// The pattern for a path written with `--path-format`, like the paths of records.
+impl From<&[Part<'_>]> for Pattern {
+  fn from(parts : &[Part<'_>]) -> Self {
+    Pattern(parts.iter().map(|part| match part {
+      Part::Key(key) => Selector::Key(key.to_string()),
+      Part::Index(index) => Selector::Index(*index),
+      Part::Any => Selector::Any,
+    }).collect())
+  }
+}
+
+impl Pattern {
+  /// Whether path is at or under a match of this pattern.
+  fn selects<'a>(&self, path : impl IntoIterator<Item = &'a Step>) -> bool {
+    let mut path = path.into_iter();
+    self.0.iter().all(|selector| path.next().is_some_and(|step| selector.matches(step)))
+  }
+
+  /// Whether something under path, which must not be selected already, could match this pattern.
+  fn could_select<'a>(&self, path : impl IntoIterator<Item = &'a Step>) -> bool {
+    self.0.iter().zip(path).all(|(selector, step)| selector.matches(step))
+  }
+}
+
+/// Whether elements of arrays keep their positions in the output.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub enum Positions {
+  /// numbered again, without the ones that were left out
+  #[default]
+  Compact,
+  /// with null for the ones that were left out
+  Preserve,
+}
+
+impl std::str::FromStr for Positions {
+  type Err = String;
+
+  fn from_str(s : &str) -> Result<Self, Self::Err> {
+    match s {
+      "compact" => Ok(Positions::Compact),
+      "preserve" => Ok(Positions::Preserve),
+      other => Err(format!("unknown positions {other}, expected compact or preserve")),
+    }
+  }
+}
+
+pub type ProjectionWrapper = sender::NonWrap<Event<JsonEvent<String>>>;
+pub type ProjectionSender<'l> = dyn Sender<Event<JsonEvent<String>>, ProjectionWrapper> + 'l;
+
+/// Sends the values selected by any of the patterns, and the arrays and objects selected as a
+/// whole, as a value of StartArray or StartObject.
+pub struct Projector {
+  pub patterns : Vec<Pattern>,
+}
+
+impl<'l> Handler<'l, JsonEvent<String>, ProjectionWrapper, ProjectionSender<'l>> for Projector {
+  fn match_path(&self, path : &JsonPath) -> bool {
+    self.patterns.iter().any(|pattern| pattern.selects(path))
+  }
+
+  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<&str>, tx : &mut ProjectionSender<'l>)
+  -> Result<(),Box<dyn std::error::Error>>
+  {
+    if !self.match_path(path) { return Ok(()) }
+    tx.send(Event::Value(path.into(), ev.into_owned()).into())
+  }
+
+  // so that empty arrays and objects are in the output too
+  fn maybe_send_path(&self, path : &JsonPath, _depth : usize, container : Container, tx : &mut ProjectionSender<'l>)
+  -> Result<(),Box<dyn std::error::Error>>
+  {
+    if !self.match_path(path) { return Ok(()) }
+    tx.send(Event::Value(path.into(), container.start()).into())
+  }
+
+  // elements that can't be selected are skipped without sending anything
+  fn sample(&self, parents : &JsonPath, index : u64) -> Sample {
+    self.sample_child(parents, index.into())
+  }
+
+  // and so are values of keys
+  fn sample_key(&self, parents : &JsonPath, key : &str) -> Sample {
+    self.sample_child(parents, key.into())
+  }
+}
+
+impl Projector {
+  fn sample_child(&self, parents : &JsonPath, step : Step) -> Sample {
+    if self.match_path(parents) { return Sample::Take }
+    let child = parents.push_back(step);
+    if self.patterns.iter().any(|pattern| pattern.could_select(&child)) { Sample::Take } else { Sample::Skip }
+  }
+}
+
+/// Write a string, number, boolean or null as json.
+pub(crate) fn write_scalar(wr : &mut dyn std::io::Write, value : &JsonEvent<String>) -> std::io::Result<()> {
+  match value {
+    JsonEvent::String(v) => write!(wr, "{}", serde_json::Value::String(v.clone())),
+    JsonEvent::Number(v) => wr.write_all(v.as_bytes()),
+    JsonEvent::Boolean(v) => write!(wr, "{v}"),
+    JsonEvent::Null => wr.write_all(b"null"),
+    ev => Err(std::io::Error::other(format!("{ev} is not a value"))),
+  }
+}
+
+// An array or object that has been started in the output.
+#[derive(Debug)]
+struct Open {
+  // the step from its parent, in the input, None for the top-level value
+  step : Option<Step>,
+  is_array : bool,
+  // number of children written so far
+  children : u64,
+  // next index in the input, for preserving positions
+  next : u64,
+}
+
+/// Writes the values from the Projector as one json value, with the nesting they had in the input.
+pub struct JsonWriter<'w> {
+  wr : &'w mut dyn std::io::Write,
+  positions : Positions,
+  open : Vec<Open>,
+  written : bool,
+}
+
+impl<'w> JsonWriter<'w> {
+  pub fn new(wr : &'w mut dyn std::io::Write, positions : Positions) -> Self {
+    Self{wr, positions, open: vec![], written: false}
+  }
+
+  fn start(&mut self, step : Option<Step>, is_array : bool) -> std::io::Result<()> {
+    self.wr.write_all(if is_array { b"[" } else { b"{" })?;
+    self.open.push(Open{step, is_array, children: 0, next: 0});
+    Ok(())
+  }
+
+  fn close(&mut self) -> std::io::Result<()> {
+    let open = self.open.pop().expect("only called with something open");
+    self.wr.write_all(if open.is_array { b"]" } else { b"}" })
+  }
+
+  // The separator, and the key or the gap, before the child of the innermost container at step.
+  fn child(&mut self, step : &Step) -> std::io::Result<()> {
+    let preserve = self.positions == Positions::Preserve;
+    let parent = self.open.last_mut().expect("only called with a parent");
+    if let (Step::Index(index), true) = (step, preserve) {
+      for _ in parent.next..*index {
+        self.wr.write_all(if parent.children > 0 { b",null" } else { b"null" })?;
+        parent.children += 1;
+      }
+      parent.next = index + 1;
+    }
+    if parent.children > 0 { self.wr.write_all(b",")? }
+    parent.children += 1;
+    match step {
+      Step::Key(key) if !parent.is_array => write!(self.wr, "{}:", serde_json::Value::String(key.clone())),
+      _ => Ok(()),
+    }
+  }
+
+  // Write the value at the path with steps, after closing what isn't its ancestor, and opening its ancestors.
+  fn write(&mut self, steps : &[Step], value : &JsonEvent<String>) -> std::io::Result<()> {
+    // open[0] is the top-level value, open[d] is at steps[..d]
+    let ancestors = self.open.iter().enumerate()
+      .take_while(|(depth, open)| *depth < steps.len() && (*depth == 0 || open.step.as_ref() == Some(&steps[depth - 1])))
+      .count();
+    while self.open.len() > ancestors { self.close()? }
+    for depth in self.open.len()..steps.len() {
+      let step = depth.checked_sub(1).map(|parent| steps[parent].clone());
+      if let Some(step) = &step { self.child(step)? }
+      self.start(step, matches!(steps[depth], Step::Index(_)))?;
+    }
+
+    if let Some(step) = steps.last() { self.child(step)? }
+    self.written = true;
+    match value {
+      JsonEvent::StartArray => self.start(steps.last().cloned(), true),
+      JsonEvent::StartObject => self.start(steps.last().cloned(), false),
+      scalar => write_scalar(self.wr, scalar),
+    }
+  }
+
+  /// Close everything that's still open. Nothing is written if nothing was selected.
+  pub fn finish(&mut self) -> std::io::Result<()> {
+    while !self.open.is_empty() { self.close()? }
+    if self.written { self.wr.write_all(b"\n")? }
+    self.written = false;
+    Ok(())
+  }
+}
+
+impl Sender<Event<JsonEvent<String>>, ProjectionWrapper> for JsonWriter<'_> {
+  fn send(&mut self, ev : ProjectionWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    match ev.as_ref() {
+      Event::Value(path, value) => Ok(self.write(&path.steps(), value)?),
+      Event::Error(path, err) => Err(format!("{err} at path '{path}'").into()),
+      Event::Finished => Ok(self.finish()?),
+      Event::Path(..) => Ok(()),
+    }
+  }
+}
+
+/// Write the parts of the input selected by any of patterns.
+pub fn project(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, patterns : Vec<Pattern>, positions : Positions)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let mut writer = JsonWriter::new(wr, positions);
+  Projector{patterns}.value(jev, JsonPath::new(), 0, &mut writer)?;
+  Ok(writer.finish()?)
+}
+
+#[cfg(test)]
+mod test_projection {
+  use super::*;
+
+  const JSON : &str = r#"[
+  {"id": 1, "actor": {"login": "a", "url": "u"}, "tags": ["x", "y"], "payload": {"commits": []}},
+  {"id": 2, "actor": {"login": "b\"q", "url": "v"}, "tags": [], "payload": {"commits": [{"sha": "s"}]}},
+  {"id": 3, "payload": {}}
+]"#;
+
+  fn project_of(json : &'static str, paths : &[&str], positions : Positions) -> String {
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(json.as_bytes()));
+    let patterns = paths.iter().map(|path| path.parse().unwrap()).collect();
+    let mut out = vec![];
+    super::project(&mut out, &mut jevstream, patterns, positions).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn parse() {
+    let pattern : Pattern = r#"$[*].actor['lo.gin']["x\"y"][2].*"#.parse().unwrap();
+    assert_eq!(pattern.0, vec![Selector::Any, Selector::Key("actor".into()), Selector::Key("lo.gin".into()), Selector::Key("x\"y".into()), Selector::Index(2), Selector::Any]);
+    assert!("$..id".parse::<Pattern>().is_err());
+    assert!("$[1:2]".parse::<Pattern>().is_err());
+    assert!("actor".parse::<Pattern>().is_err());
+  }
+
+  #[test]
+  fn leaves() {
+    assert_eq!(project_of(JSON, &["$[*].actor.login"], Positions::Compact), r#"[{"actor":{"login":"a"}},{"actor":{"login":"b\"q"}}]"#.to_string() + "\n");
+  }
+
+  #[test]
+  fn subtrees_and_empty_containers() {
+    let expected = r#"[{"id":1,"payload":{"commits":[]}},{"id":2,"payload":{"commits":[{"sha":"s"}]}},{"id":3,"payload":{}}]"#;
+    assert_eq!(project_of(JSON, &["$[*].id", "$[*].payload"], Positions::Compact), expected.to_string() + "\n");
+  }
+
+  #[test]
+  fn positions() {
+    assert_eq!(project_of(JSON, &["$[1].tags", "$[*].tags[1]"], Positions::Compact), r#"[{"tags":["y"]},{"tags":[]}]"#.to_string() + "\n");
+    assert_eq!(project_of(JSON, &["$[1].id", "$[0].tags[1]"], Positions::Preserve), r#"[{"tags":[null,"y"]},{"id":2}]"#.to_string() + "\n");
+    assert_eq!(project_of(JSON, &["$[2].id"], Positions::Preserve), r#"[null,null,{"id":3}]"#.to_string() + "\n");
+  }
+
+  #[test]
+  fn skipped_keys() {
+    let projector = Projector{patterns: vec!["$.b.c".parse().unwrap()]};
+    assert_eq!(projector.sample_key(&JsonPath::new(), "a"), Sample::Skip);
+    assert_eq!(projector.sample_key(&JsonPath::new(), "b"), Sample::Take);
+    let json = r#"{"a": {"x": [1, {"c": 2}], "b": {"c": 3}}, "b": {"a": [], "c": {"d": [4]}}, "c": 5}"#;
+    assert_eq!(project_of(json, &["$.b.c"], Positions::Compact), r#"{"b":{"c":{"d":[4]}}}"#.to_string() + "\n");
+  }
+
+  #[test]
+  fn everything_and_nothing() {
+    let everything = project_of(JSON, &["$"], Positions::Compact);
+    let expected : serde_json::Value = serde_json::from_str(JSON).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&everything).unwrap(), expected);
+    assert_eq!(project_of(JSON, &["$[*].missing"], Positions::Compact), "");
+    assert_eq!(project_of("7", &["$"], Positions::Compact), "7\n");
+  }
+}
@@ -229,7 +229,10 @@ pub fn parse( istream : Box<dyn std::io::BufRead> ) {
 }
 
 /// Shim to present a channel as a JsonEvents pull source.
-struct ChannelStreamer(rtrb::Consumer<JsonEvent<String>>, std::thread::Thread);
+///
+/// The last field is the current event, which next_borrowed lends out. The events come through the
+/// ring buffer with their text already owned, so borrowing them doesn't save an allocation.
+struct ChannelStreamer(rtrb::Consumer<JsonEvent<String>>, std::thread::Thread, JsonEvent<String>);
 
 use crate::parser::JsonEventSource;
 
@@ -248,6 +251,11 @@ impl JsonEventSource<'_,String> for ChannelStreamer {
     }
     Ok(JsonEvent::Eof)
   }
+
+  fn next_borrowed(&mut self) -> std::result::Result<JsonEvent<&str>, Box<dyn std::error::Error>> {
+    self.2 = self.next_event()?;
+    Ok(self.2.as_deref())
+  }
 }
 
 /// This constructs a rapidjson parser from the filename, thereby maximising read performance,
@@ -258,7 +266,7 @@ impl JsonEventSource<'_,String> for ChannelStreamer {
 pub fn schema_from_file( filename : &str ) {
   let (tx, rx) = rtrb::RingBuffer::new(RING_BUFFER_BOUND);
 
-  let mut streamer = ChannelStreamer(rx, std::thread::current());
+  let mut streamer = ChannelStreamer(rx, std::thread::current(), JsonEvent::Null);
   let cons_thr = std::thread::Builder::new()
     .name("jch rapid recv".into())
     .spawn( move || crate::schema::schema(&mut std::io::stdout(), &mut streamer) )
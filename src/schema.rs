@@ -5,15 +5,26 @@ This parses a json document and collects the type for each path, including some
 */
 
 use std::sync::Arc;
-use std::cell::RefCell;
 
 use crate::parser::JsonEventSource;
 use crate::handler::Handler;
+use crate::handler::Sample;
 use crate::sender::Sender;
 use crate::jsonpath::JsonPath;
 use crate::sender::Event;
 use crate::parser::JsonEvent;
 
+mod tree;
+mod trie;
+mod walker;
+pub mod report;
+pub mod snapshot;
+pub mod explain;
+pub mod group;
+pub mod saved;
+pub mod generate;
+pub mod dot;
+
 /*
 tree is a map of path => [(type, count)]
 
@@ -82,145 +93,448 @@ For each path in the tree, this stores the kind of value at this path, along
 with statistical type data about how many times and what values are stored
 there.
 */
-#[derive(Debug,Clone,Eq,PartialEq)]
+#[derive(Debug,Clone)]
 struct Leaf {
-  kind : SchemaType,
-  count : RefCell<u64>,
+  count : u64,
   // min/max length etc go here
-  aggregate : RefCell<SchemaType>,
+  aggregate : SchemaType,
+  // a few of the values, in json notation
+  samples : Vec<String>,
+  // string value => count, until there are too many distinct values for an enum.
+  distinct : Option<std::collections::HashMap<String,u64>>,
+  // where the values came from
+  first : Seen,
+  last : Seen,
 }
 
-impl Leaf {
-  fn new(kind : SchemaType) -> Self {
-    Self{ kind: kind.clone(), count: RefCell::new(1), aggregate: RefCell::new(kind.clone())}
-  }
+/// Where a leaf value came from: the indexes of its path, and where it starts in the input.
+///
+/// The rest of its path is the path of its leaf, so only the indexes are kept for each value.
+#[derive(Debug,Clone)]
+pub struct Seen {
+  indexes : crate::sendpath::Indexes,
+  position : Option<crate::parser::Position>,
 }
 
-impl std::fmt::Display for Leaf {
-  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-    let kind = &self.kind;
-    let count = &self.count;
-    write!(f, "{kind:?}:{}", count.borrow())
+impl Seen {
+  fn new(indexes : &[u64], position : Option<crate::parser::Position>) -> Self {
+    Self{indexes: indexes.into(), position}
   }
-}
 
-impl std::hash::Hash for Leaf {
-  /// Hash only the part that will be stable, and since count and aggregate are
-  /// both RefCell, they won't be stable.
-  fn hash<H>(&self, hsh: &mut H) where H: std::hash::Hasher {
-    self.kind.hash(hsh);
+  // reuses the memory of the previous indexes
+  fn set(&mut self, indexes : &[u64], position : Option<crate::parser::Position>) {
+    self.indexes.set(indexes);
+    self.position = position;
   }
-}
 
-const STEP_LENGTH_WORTH_HASHING : usize = 32;
+  /// The full path, with indexes, eg `[4211]/payload/release/name`, where at is the path of the leaf.
+  fn path(&self, at : &SchemaPath) -> crate::sendpath::SendPath {
+    let mut indexes = self.indexes.as_slice().iter();
+    let steps = at.0.iter().map(|step| match step {
+      Step::Key(key) => crate::jsonpath::Step::Key(key.clone()),
+      Step::Index | Step::Position(_) => crate::jsonpath::Step::Index(*indexes.next().expect("an index for each array")),
+    }).collect::<Vec<crate::jsonpath::Step>>();
+    crate::sendpath::SendPath::from(&steps[..])
+  }
 
-/// Allow for pre-hashing this, since it will be used in many comparisons
-#[derive(Debug,Clone,PartialOrd,Ord,Eq)]
-pub enum Step {
-  Key(String,RefCell<Option<u64>>),
-  Index,
+  /// Where the value starts in the input, if the parser knows that.
+  pub fn position(&self) -> Option<crate::parser::Position> {
+    self.position
+  }
 }
 
-impl Step {
-  // greedily calculate hash
-  fn key_with_hash(val: &str) -> Step {
-    if val.len() < STEP_LENGTH_WORTH_HASHING {
-      use std::hash::Hash;
-      use std::hash::Hasher;
-      let mut hasher = <rustc_hash::FxHasher as std::default::Default>::default();
-      val.hash(&mut hasher);
-      Self::Key(val.into(),RefCell::new(Some(hasher.finish())))
-    } else {
-      Self::Key(val.into(),RefCell::new(None))
+/// A leaf value on its way into a Leaf, either straight from the parser or from a LeafValue.
+struct Incoming<'a> {
+  kind : &'a SchemaType,
+  value : JsonEvent<&'a str>,
+  // of the value's path
+  indexes : &'a [u64],
+  position : Option<crate::parser::Position>,
+}
+
+/// Default for the maximum number of distinct values a string path can have and still be an enum.
+pub const DEFAULT_ENUM_LIMIT : usize = 16;
+
+/// How many distinct sample values to keep for each Leaf
+const SAMPLE_LIMIT : usize = 3;
+/// Only look for samples in the first few values, otherwise a path that always
+/// has the same value would cost an allocation for every leaf.
+const SAMPLE_SEARCH : u64 = 64;
+/// Longer strings are truncated, because these are for display only.
+const SAMPLE_LENGTH : usize = 64;
+
+impl Leaf {
+  fn new(incoming : &Incoming, enum_limit : usize) -> Self {
+    let Incoming{kind, value, indexes, position} = incoming;
+    // only strings can be enums, and a limit of 0 means don't look for enums.
+    let distinct = match kind {
+      SchemaType::String(_) if enum_limit > 0 => Some(std::collections::HashMap::new()),
+      _ => None,
+    };
+    let mut leaf = Self{ count: 1, aggregate: (*kind).clone(), samples: vec![], distinct, first: Seen::new(indexes, *position), last: Seen::new(indexes, *position)};
+    leaf.add_sample(value);
+    leaf.add_distinct(value, enum_limit);
+    leaf
+  }
+
+  // another value of this kind
+  fn add(&mut self, incoming : &Incoming, enum_limit : usize) {
+    let Incoming{kind, value, indexes, position} = incoming;
+    self.count += 1;
+    self.add_sample(value);
+    self.add_distinct(value, enum_limit);
+
+    // update the max/min and other aggregates here
+    // transfer values from kind (ie the current leaf value) to aggregate (ie in the schema we're building)
+    if let Some(updated_aggregate) = merged_aggregate(kind, &self.aggregate) {
+      self.aggregate = updated_aggregate;
     }
+    self.last.set(indexes, *position);
   }
 
-  // Don't calculate a hash
-  #[allow(dead_code)]
-  fn key(val: &str) -> Step {
-    Self::Key(val.into(),RefCell::new(None))
+  /// Where the first value of this kind came from.
+  pub fn first(&self) -> &Seen {
+    &self.first
   }
 
-  // calculate hash lazily
-  fn maybe_cache_hash(&self) -> u64 {
-    match self {
-      Self::Index => 0u64, // TODO what other value should be used here?
-      Self::Key(v,hashref) => {
-        let maybe_hash = *hashref.borrow();
-        if let Some(hashv) = maybe_hash {
-          hashv
-        } else {
-          use std::hash::Hash;
-          use std::hash::Hasher;
-          let mut hasher = <rustc_hash::FxHasher as std::default::Default>::default();
-          v.hash(&mut hasher);
-          let hashv = hasher.finish();
-          hashref.replace(Some(hashv));
-          hashv
+  /// Where the most recent value of this kind came from.
+  pub fn last(&self) -> &Seen {
+    &self.last
+  }
+
+  // count the value, and stop counting once there are more than enum_limit distinct values.
+  fn add_distinct(&mut self, value : &JsonEvent<&str>, enum_limit : usize) {
+    let (Some(distinct), JsonEvent::String(v)) = (self.distinct.as_mut(), value) else { return };
+    if let Some(count) = distinct.get_mut(*v) {
+      *count += 1
+    } else if distinct.len() < enum_limit {
+      distinct.insert(v.to_string(), 1);
+    } else {
+      // too many, so this is not an enum. Also frees the memory.
+      self.distinct = None
+    }
+  }
+
+  /// Fold other into self, for when two paths turn out to be the same path.
+  fn merge(&mut self, other : Leaf, enum_limit : usize) {
+    self.count += other.count;
+
+    // without positions, there's no way to tell which was first, so keep self
+    if matches!((other.first.position(), self.first.position()), (Some(other_first), Some(first)) if other_first < first) { self.first = other.first }
+    if matches!((other.last.position(), self.last.position()), (Some(other_last), Some(last)) if other_last > last) { self.last = other.last }
+
+    if let Some(updated_aggregate) = merged_aggregate(&other.aggregate, &self.aggregate) {
+      self.aggregate = updated_aggregate;
+    }
+
+    for sample in other.samples {
+      if self.samples.len() < SAMPLE_LIMIT && !self.samples.contains(&sample) { self.samples.push(sample) }
+    }
+
+    self.distinct = match (self.distinct.take(), other.distinct) {
+      (Some(mut distinct), Some(other_distinct)) => {
+        for (v, count) in other_distinct {
+          *distinct.entry(v).or_default() += count;
         }
+        Some(distinct).filter(|distinct| distinct.len() <= enum_limit)
       }
+      // either one has too many values
+      _ => None,
+    };
+  }
+
+  /// If this leaf is an enum, its values with their counts, most frequent first.
+  ///
+  /// Only when some value turned up more than once, otherwise every path with a few
+  /// distinct strings, like a single record's ids, would look like an enum.
+  fn enum_values(&self) -> Option<Vec<(String,u64)>> {
+    let distinct = self.distinct.as_ref().filter(|distinct| self.count > distinct.len() as u64)?;
+    let mut values = distinct.iter().map(|(v,count)| (v.clone(), *count)).collect::<Vec<(String,u64)>>();
+    values.sort_by(|(av,acount),(bv,bcount)| bcount.cmp(acount).then(av.cmp(bv)));
+    Some(values)
+  }
+
+  // keep the value if it's one of the first few distinct values
+  fn add_sample(&mut self, value : &JsonEvent<&str>) {
+    if self.samples.len() >= SAMPLE_LIMIT || self.count > SAMPLE_SEARCH { return }
+
+    let sample = match value {
+      JsonEvent::String(v) if v.chars().count() > SAMPLE_LENGTH => {
+        let truncated = v.chars().take(SAMPLE_LENGTH).collect::<String>();
+        format!("{}…", serde_json::Value::String(truncated))
+      }
+      JsonEvent::String(v) => serde_json::Value::String(v.to_string()).to_string(),
+      JsonEvent::Number(v) => v.to_string(),
+      JsonEvent::Boolean(v) => v.to_string(),
+      JsonEvent::Null => "null".into(),
+      _ => return,
+    };
+
+    if !self.samples.contains(&sample) { self.samples.push(sample) }
+  }
+}
+
+/// The max/min and other aggregates of both. None when there is nothing to update.
+fn merged_aggregate(value_type : &SchemaType, aggregate : &SchemaType) -> Option<SchemaType> {
+  use SchemaType::*;
+  use NumberType::*;
+  match (value_type, aggregate) {
+    (&String(val_n), &String(agg_n)) => Some(String(std::cmp::max(val_n,agg_n))),
+    (&Number(Unsigned(val_max)), &Number(Unsigned(agg_max))) => Some(Number(Unsigned(std::cmp::max(val_max,agg_max)))),
+    (&Number(Signed(val_min,val_max)), &Number(Signed(agg_min,agg_max))) => Some(Number(Signed(std::cmp::min(val_min,agg_min), std::cmp::max(val_max,agg_max)))),
+    (&Number(Float(val_min,val_max)), &Number(Float(agg_min,agg_max))) => Some(Number(Float(f64::min(val_min,agg_min), f64::max(val_max,agg_max)))),
+    _ => None, // because no aggregates are collected for other types, so no need to update anything
+  }
+}
+
+impl std::fmt::Display for Leaf {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+    // the aggregate, because that has the max/min etc of all the values
+    write!(f, "{:?}:{}", self.aggregate, self.count)?;
+    if let Some(values) = self.enum_values() {
+      let values = values
+        .iter()
+        .map(|(v,count)| format!("{}:{count}", serde_json::Value::String(v.clone())))
+        .collect::<Vec<String>>();
+      write!(f, " Enum{{{}}}", values.join(","))?;
     }
+    Ok(())
   }
 }
 
-impl std::cmp::PartialEq for Step {
-  fn eq(&self, other: &Self) -> bool {
-    use Step::*;
-    match (self,other) {
-      (Index, Index) => true,
-      (Index, Key(_,_)) => false,
-      (Key(_,_), Index) => false,
-      (Key(_sv,shash_cell),Key(_ov,ohash_cell)) => {
-        // definitely makes a difference to restrict hash calculation to shorter strings.
-        // There must be some proper inflection point where the probability of longer strings
-        // being equal outweighs the probably that calculating the hash upfront will be faster.
-        if _sv.len() == _ov.len() && _sv.len() < STEP_LENGTH_WORTH_HASHING {
-          match (*shash_cell.borrow(), *ohash_cell.borrow()) {
-            ( None ,  None) => self.maybe_cache_hash() == other.maybe_cache_hash(),
-            ( Some(shash),  Some(ohash)) => shash == ohash,
-            ( Some(shash),  None) => shash == other.maybe_cache_hash(),
-            ( None,  Some(ohash)) => self.maybe_cache_hash() == ohash,
-          }
-        } else {
-          // assuming that the String PartialEq will check length first
-          _sv == _ov
-        }
+/// One per kind of SchemaType. Unknown values all share a slot, but there shouldn't be any of those anyway.
+const KIND_SLOTS : usize = 7;
+
+/// The leaves of a single path, with a fixed slot for each kind, so finding the
+/// leaf for a value is an array index rather than a search.
+#[derive(Debug,Clone,Default)]
+struct LeafKinds([Option<Leaf>; KIND_SLOTS]);
+
+impl LeafKinds {
+  fn slot(kind : &SchemaType) -> usize {
+    match kind {
+      SchemaType::String(_) => 0,
+      SchemaType::Number(NumberType::Unsigned(_)) => 1,
+      SchemaType::Number(NumberType::Signed(_,_)) => 2,
+      SchemaType::Number(NumberType::Float(_,_)) => 3,
+      SchemaType::Boolean => 4,
+      SchemaType::Null => 5,
+      SchemaType::Unknown(_) => 6,
+    }
+  }
+
+  fn add(&mut self, incoming : &Incoming, enum_limit : usize) {
+    match &mut self.0[Self::slot(incoming.kind)] {
+      Some(leaf) => leaf.add(incoming, enum_limit),
+      slot @ None => *slot = Some(Leaf::new(incoming, enum_limit)),
+    }
+  }
+
+  /// Fold other into self, for when two paths turn out to be the same path.
+  fn merge(&mut self, other : LeafKinds, enum_limit : usize) {
+    for (slot, other_slot) in self.0.iter_mut().zip(other.0) {
+      match (slot, other_slot) {
+        (Some(leaf), Some(other_leaf)) => leaf.merge(other_leaf, enum_limit),
+        (slot @ None, other_slot) => *slot = other_slot,
+        (Some(_), None) => (),
       }
     }
   }
-}
 
+  fn iter(&self) -> impl Iterator<Item = &Leaf> {
+    self.0.iter().flatten()
+  }
+
+  fn len(&self) -> usize {
+    self.iter().count()
+  }
 
-impl std::hash::Hash for Step {
-  /// Delegate to the RefCell hash if possible
-  fn hash<H>(&self, hsh: &mut H) where H: std::hash::Hasher {
-    // yes, we're hashing a hash here.
-    hsh.write_u64(self.maybe_cache_hash())
+  fn is_empty(&self) -> bool {
+    self.0.iter().all(Option::is_none)
+  }
+
+  /// Number of values of all kinds.
+  fn count(&self) -> u64 {
+    self.iter().map(|leaf| leaf.count).sum()
   }
 }
+
+/// One step of a schema path.
+#[derive(Debug,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum Step {
+  Key(String),
+  Index,
+  // index kept because the array might be a tuple
+  Position(u64),
+}
+
 impl std::fmt::Display for Step {
   fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
     match &self {
-      Step::Key(v, _) => write!(f, "{v}"),
+      Step::Key(v) => write!(f, "{v}"),
       Step::Index => write!(f, "[]"),
+      Step::Position(i) => write!(f, "[{i}]"),
     }
   }
 }
 
-// TODO look at ways to make this immutable and cache its hash
-#[derive(Debug,PartialOrd,Ord,PartialEq,Eq,Hash)]
+/// The steps from the root to a node of the trie. Only built for display, not for every event.
+#[derive(Debug,Clone,PartialEq,Eq)]
 struct SchemaPath(Vec<Step>);
 
+impl<'a> From<&'a Step> for crate::pathformat::Part<'a> {
+  fn from(step : &'a Step) -> Self {
+    use crate::pathformat::Part;
+    match step {
+      Step::Key(key) => Part::Key(key.into()),
+      Step::Index => Part::Any,
+      Step::Position(i) => Part::Index(*i),
+    }
+  }
+}
+
+impl From<crate::pathformat::Part<'_>> for Step {
+  fn from(part : crate::pathformat::Part<'_>) -> Self {
+    use crate::pathformat::Part;
+    match part {
+      Part::Key(key) => Step::Key(key.into_owned()),
+      Part::Any => Step::Index,
+      Part::Index(i) => Step::Position(i),
+    }
+  }
+}
+
+/// In the notation selected by --path-format.
 impl std::fmt::Display for SchemaPath {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    let string_parts = self.0.iter().map(ToString::to_string).collect::<Vec<String>>();
-    let repr = string_parts.join("/");
+    use crate::pathformat::PathFormat;
+    write!(f, "{}", crate::pathformat::write(self.0.iter().map(Into::into), PathFormat::global()))
+  }
+}
 
-    write!(f,"{repr}")
+/// Which records are collected, where the records are the elements of a top-level array,
+/// or the values of an ndjson stream.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Sampling {
+  /// the first n records, and then stop reading
+  First(u64),
+  /// each record with this probability, so about this fraction of them
+  Fraction(f64),
+}
+
+/// How many records were sampled.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Sampled {
+  pub sampling : Sampling,
+  /// records that were collected
+  pub taken : u64,
+  /// records that were read, including the skipped ones
+  pub seen : u64,
+  /// where parsing stopped, for First
+  pub bytes : Option<u64>,
+}
+
+impl std::fmt::Display for Sampled {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.sampling {
+      Sampling::First(_) => {
+        write!(f, "sampled the first {} records", self.taken)?;
+        match self.bytes {
+          Some(bytes) => write!(f, ", reading {bytes} bytes"),
+          None => Ok(()),
+        }
+      }
+      Sampling::Fraction(_) => {
+        let rate = if self.seen == 0 { 0.0 } else { self.taken as f64 / self.seen as f64 };
+        write!(f, "sampled {} of {} records ({:.1}%)", self.taken, self.seen, rate * 100.0)
+      }
+    }
   }
 }
 
-pub struct EventConverter();
+/// Which records are collected, for Sampling.
+#[derive(Debug)]
+struct Sampler {
+  sampling : Option<Sampling>,
+  // for Sampling::Fraction
+  rng : fastrand::Rng,
+  // records so far, and how many of them were taken
+  seen : u64,
+  taken : u64,
+}
+
+impl Sampler {
+  fn new(sampling : Option<Sampling>, seed : Option<u64>) -> Self {
+    let rng = seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+    Self{sampling, rng, seen: 0, taken: 0}
+  }
+
+  // what to do with the next record
+  fn sample(&mut self) -> Sample {
+    let Some(sampling) = self.sampling else { return Sample::Take };
+    let sample = match sampling {
+      Sampling::First(n) if self.taken >= n => return Sample::Stop,
+      Sampling::First(_) => Sample::Take,
+      Sampling::Fraction(p) => if self.rng.f64() < p { Sample::Take } else { Sample::Skip },
+    };
+    self.seen += 1;
+    if sample == Sample::Take { self.taken += 1 }
+    sample
+  }
+
+  /// How many records were sampled, if sampling and there were any records.
+  fn sampled(&self, bytes : Option<u64>) -> Option<Sampled> {
+    let sampling = self.sampling?;
+    if self.seen == 0 { return None }
+    Some(Sampled{sampling, taken: self.taken, seen: self.seen, bytes})
+  }
+}
+
+/// The kind of a leaf value.
+fn kind_of(ev : &JsonEvent<&str>) -> SchemaType {
+  // So the big question is: should this translation happen: in the parser thread; or in the processor thread?
+  match ev {
+    JsonEvent::String(v) => {
+      if *v == "NaN" {
+        SchemaType::Number(NumberType::Float(f64::NAN,f64::NAN))
+      } else {
+        SchemaType::String(v.len() as u64)
+      }
+    }
+
+    JsonEvent::Number(v) => {
+      let number_value : serde_json::Number = match serde_json::from_str(v) {
+        Ok(n) => n,
+        Err(msg) => panic!("{v} appears to be not-a-number {msg}"),
+      };
+
+      if number_value.is_u64() {
+        let n = number_value.as_u64().unwrap();
+        SchemaType::Number(NumberType::Unsigned(n))
+      } else if number_value.is_i64() {
+        let i = number_value.as_i64().unwrap();
+        SchemaType::Number(NumberType::Signed(i,i))
+      } else if number_value.is_f64() {
+        let f = number_value.as_f64().unwrap();
+        SchemaType::Number(NumberType::Float(f64::min(f,0.0),f64::max(f, 0.0)))
+      } else {
+        SchemaType::Unknown(v.to_string())
+      }
+    }
+
+    JsonEvent::Boolean(_v) => SchemaType::Boolean,
+    JsonEvent::Null => SchemaType::Null,
+    ev => SchemaType::Unknown(format!("{ev:?}")),
+  }
+}
+
+/// Converts the events of the parser to LeafValues, for collecting schemas on the other side of a Sender.
+///
+/// A single schema doesn't need this, see walker.
+pub struct EventConverter {
+  positions : Option<crate::parser::PositionTracker>,
+  sampler : std::cell::RefCell<Sampler>,
+}
 
 impl Default for EventConverter {
   fn default() -> Self {
@@ -229,49 +543,44 @@ impl Default for EventConverter {
 }
 
 impl EventConverter {
-  pub fn new() -> Self {Self()}
+  pub fn new() -> Self {
+    Self{positions: None, sampler: std::cell::RefCell::new(Sampler::new(None, None))}
+  }
 
-  fn collect_type(&self, _path : &JsonPath, ev : &JsonEvent<String>)
-  -> SchemaType
-  {
-    // So the big question is: should this translation happen: in the parser thread; or in the processor thread?
-    match ev {
-      JsonEvent::String(v) => {
-        if v == "NaN" {
-          SchemaType::Number(NumberType::Float(f64::NAN,f64::NAN))
-        } else {
-          SchemaType::String(v.len() as u64)
-        }
-      }
+  /// Send the position of each leaf value, from the parser's tracker.
+  pub fn with_positions(mut self, tracker : Option<crate::parser::PositionTracker>) -> Self {
+    self.positions = tracker;
+    self
+  }
 
-      JsonEvent::Number(v) => {
-        let number_value : serde_json::Number = match serde_json::from_str(v) {
-          Ok(n) => n,
-          Err(msg) => panic!("{v} appears to be not-a-number {msg}"),
-        };
-
-        if number_value.is_u64() {
-          let n = number_value.as_u64().unwrap();
-          SchemaType::Number(NumberType::Unsigned(n))
-        } else if number_value.is_i64() {
-          let i = number_value.as_i64().unwrap();
-          SchemaType::Number(NumberType::Signed(i,i))
-        } else if number_value.is_f64() {
-          let f = number_value.as_f64().unwrap();
-          SchemaType::Number(NumberType::Float(f64::min(f,0.0),f64::max(f, 0.0)))
-        } else {
-          SchemaType::Unknown(v.to_string())
-        }
-      }
+  /// Only convert some of the records, and skip the rest.
+  pub fn with_sampling(mut self, sampling : Option<Sampling>) -> Self {
+    self.sampler.get_mut().sampling = sampling;
+    self
+  }
 
-      JsonEvent::Boolean(_v) => SchemaType::Boolean,
-      JsonEvent::Null => SchemaType::Null,
-      ev => SchemaType::Unknown(format!("{ev:?}")),
-    }
+  /// The same seed samples the same records of the same input.
+  pub fn with_seed(mut self, seed : Option<u64>) -> Self {
+    if let Some(seed) = seed { self.sampler.get_mut().rng.seed(seed) }
+    self
+  }
+
+  /// How many records were sampled, if sampling and there were any records.
+  pub fn sampled(&self, bytes : Option<u64>) -> Option<Sampled> {
+    self.sampler.borrow().sampled(bytes)
   }
 }
 
-type SendValue = SchemaType;
+/// What EventConverter sends to SchemaCollector: the type of the leaf, the leaf itself for the samples,
+/// and where it was in the input.
+#[derive(Debug,Clone)]
+pub struct LeafValue {
+  pub kind : SchemaType,
+  pub value : JsonEvent<String>,
+  pub position : Option<crate::parser::Position>,
+}
+
+type SendValue = LeafValue;
 type SendEvent = crate::sender::Event<SendValue>;
 
 impl<'l> Handler<'l, SendValue, Arc<SendEvent>, dyn Sender<SendEvent, Arc<SendEvent>> + 'l> for EventConverter {
@@ -279,24 +588,112 @@ impl<'l> Handler<'l, SendValue, Arc<SendEvent>, dyn Sender<SendEvent, Arc<SendEv
   #[inline]
   fn match_path(&self, _json_path : &JsonPath) -> bool {true}
 
-  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<String>, tx : &mut (dyn Sender<SendEvent, Arc<SendEvent>> + 'l))
+  // only the records, ie the elements of the top-level array, are sampled
+  fn sample(&self, parents : &JsonPath, _index : u64) -> Sample {
+    if !parents.is_empty() { return Sample::Take }
+    self.sampler.borrow_mut().sample()
+  }
+
+  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<&str>, tx : &mut (dyn Sender<SendEvent, Arc<SendEvent>> + 'l))
   -> Result<(),Box<dyn std::error::Error>>
   {
     if !self.match_path(path) { return Ok(()) }
-    let schema_type = self.collect_type(path, &ev);
+    let kind = kind_of(&ev);
+    let position = self.positions.as_ref().map(|tracker| tracker.get());
     tx
-      .send(Arc::new(Event::Value(path.into(), schema_type)))
-      .unwrap_or_else(|err| panic!("cannot send {ev:?} because {err:?}"));
+      .send(Arc::new(Event::Value(path.into(), LeafValue{kind, value: ev.into_owned(), position})))
+      .unwrap_or_else(|err| panic!("cannot send leaf value because {err:?}"));
     Ok(())
   }
+
+  // so the collector can count containers, which it needs for presence of the containers and their children.
+  // Only --group-by goes through here, the walker counts containers in the trie itself.
+  fn maybe_send_path(&self, path : &JsonPath, depth : usize, _container : crate::handler::Container, tx : &mut (dyn Sender<SendEvent, Arc<SendEvent>> + 'l))
+  -> Result<(),Box<dyn std::error::Error>>
+  {
+    tx.send(Arc::new(Event::Path(depth as u64, path.into())))
+  }
 }
 
-type LeafKinds = std::collections::HashSet<Leaf>;
-type LeafPaths = std::collections::HashMap<SchemaPath, LeafKinds>;
+/// Statistics for each array or object path.
+#[derive(Debug,Clone,Default)]
+struct Container {
+  // how many times this path occurred
+  count : u64,
+  // highest index + 1 seen in the current occurrence. Always 0 for objects.
+  length : u64,
+  // min and max length of the finished occurrences
+  lengths : Option<(u64,u64)>,
+  // whether the current occurrence has not yet been folded into lengths
+  open : bool,
+}
+
+impl Container {
+  // a new occurrence of this path
+  fn start(&mut self) {
+    self.finish();
+    self.count += 1;
+    self.open = true;
+  }
+
+  fn finish(&mut self) {
+    if self.open {
+      let length = self.length;
+      self.lengths = Some(self.lengths.map_or((length,length), |(min,max)| (min.min(length), max.max(length))));
+      self.length = 0;
+      self.open = false;
+    }
+  }
+
+  fn merge(&mut self, other : &Container) {
+    self.count += other.count;
+    self.lengths = match (self.lengths, other.lengths) {
+      (Some((amin,amax)), Some((bmin,bmax))) => Some((amin.min(bmin), amax.max(bmax))),
+      (lengths, None) | (None, lengths) => lengths,
+    };
+  }
+
+  // Constant non-zero length, and short enough. A single occurrence always has a
+  // constant length, so that's not enough to say it's a tuple.
+  fn is_tuple(&self, tuple_limit : usize) -> bool {
+    self.count > 1 && matches!(self.lengths, Some((min,max)) if min == max && max > 0 && max as usize <= tuple_limit)
+  }
+}
+
+/// A parse error, and where it happened.
+#[derive(Debug,Clone)]
+pub struct RecordedError {
+  pub path : crate::sendpath::SendPath,
+  // includes the line and column
+  pub message : String,
+}
+
+impl std::fmt::Display for RecordedError {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+    write!(f, "{} at path '{}'", self.message, self.path)
+  }
+}
+
+/// Only this many errors are kept, but all of them are counted.
+const RECORDED_ERROR_LIMIT : usize = 1000;
 
 #[derive(Debug)]
 pub struct SchemaCollector {
-  leaf_paths : LeafPaths
+  // leaves and containers for each path
+  paths : trie::PathTrie,
+  bytes_processed : Option<crate::parser::ByteCounter>,
+  // string paths with at most this many distinct values are enums
+  enum_limit : usize,
+  // arrays with a constant length of at most this are tuples
+  tuple_limit : usize,
+  errors : Vec<RecordedError>,
+  error_count : u64,
+  // stop once there are this many errors
+  max_errors : Option<std::num::NonZeroU64>,
+  snapshots : Option<snapshot::Snapshots>,
+  sampling : Option<Sampling>,
+  seed : Option<u64>,
+  sampled : Option<Sampled>,
 }
 
 impl Default for SchemaCollector {
@@ -307,129 +704,402 @@ impl Default for SchemaCollector {
 
 impl SchemaCollector {
   pub fn new() -> Self {
-    Self {leaf_paths: LeafPaths::new()}
+    Self {paths: trie::PathTrie::new(), bytes_processed: None, enum_limit: DEFAULT_ENUM_LIMIT, tuple_limit: 0, errors: vec![], error_count: 0, max_errors: None, snapshots: None, sampling: None, seed: None, sampled: None}
+  }
+
+  /// Stop collecting once there are this many parse errors.
+  pub fn with_max_errors(mut self, max_errors : std::num::NonZeroU64) -> Self {
+    self.max_errors = Some(max_errors);
+    self
+  }
+
+  /// Number of parse errors.
+  pub fn error_count(&self) -> u64 {
+    self.error_count
+  }
+
+  /// The parse errors, in the order they happened. Only the first few are kept.
+  pub fn errors(&self) -> &[RecordedError] {
+    &self.errors
+  }
+
+  fn too_many_errors(&self) -> bool {
+    matches!(self.max_errors, Some(max_errors) if self.error_count >= max_errors.get())
+  }
+
+  /// Write snapshots of the partial schema while collecting.
+  pub fn with_snapshots(mut self, snapshots : snapshot::Snapshots) -> Self {
+    self.snapshots = Some(snapshots);
+    self
+  }
+
+  /// Whether collecting stopped early because of SIGINT.
+  pub fn interrupted(&self) -> bool {
+    self.snapshots.as_ref().is_some_and(snapshot::Snapshots::interrupted)
+  }
+
+  // Whether collecting stopped before the end of the input, but there's still a useful partial schema.
+  fn stopped(&self) -> bool {
+    self.too_many_errors() || self.interrupted()
+  }
+
+  /// Arrays whose length is always the same, and at most tuple_limit, keep their
+  /// index positions, eg `coordinates/[0]` and `coordinates/[1]`. 0 turns that off.
+  pub fn with_tuple_limit(mut self, tuple_limit : usize) -> Self {
+    self.tuple_limit = tuple_limit;
+    self
+  }
+
+  /// String paths with at most this many distinct values are displayed as enums. 0 turns that off.
+  pub fn with_enum_limit(mut self, enum_limit : usize) -> Self {
+    self.enum_limit = enum_limit;
+    self
+  }
+
+  /// Only collect some of the records, for a quicker approximate schema of a large input.
+  pub fn with_sampling(mut self, sampling : Sampling) -> Self {
+    self.sampling = Some(sampling);
+    self
+  }
+
+  /// The same seed samples the same records of the same input, otherwise they're different every time.
+  pub fn with_seed(mut self, seed : u64) -> Self {
+    self.seed = Some(seed);
+    self
+  }
+
+  /// How many records were sampled, if they were.
+  pub fn sampled(&self) -> Option<Sampled> {
+    self.sampled
+  }
+
+  fn set_sampled(&mut self, sampled : Option<Sampled>) {
+    self.sampled = sampled;
+    if let Some(sampled) = sampled { self.set_records(sampled.taken) }
+  }
+
+  // The length of the top-level array is the number of records that were collected, rather than
+  // the highest index, which counts the skipped records too.
+  fn set_records(&mut self, records : u64) {
+    // like update_length
+    if self.tuple_limit == 0 { return }
+    if let Some(container) = self.paths[trie::ROOT].container.as_mut() {
+      container.length = records
+    }
+  }
+
+  // An empty collector with the same settings, but no snapshots or limit on errors, which are
+  // up to whoever is sending to it.
+  fn like(&self) -> Self {
+    Self {enum_limit: self.enum_limit, tuple_limit: self.tuple_limit, sampling: self.sampling, seed: self.seed, ..Self::new()}
+  }
+
+  /// The nodes that have leaves, depth first.
+  fn leaf_ids(&self) -> impl Iterator<Item = trie::NodeId> + '_ {
+    self.paths.ids().into_iter().filter(|id| !self.paths[*id].kinds.is_empty())
+  }
+
+  /// Number of leaf values, ie the sum of all counts.
+  pub fn total_leaves(&self) -> u64 {
+    self.leaf_ids().map(|id| self.paths[id].kinds.count()).sum()
+  }
+
+  /// Length of the longest leaf path.
+  pub fn max_depth(&self) -> usize {
+    self.leaf_ids().map(|id| self.paths[id].depth).max().unwrap_or(0)
+  }
+
+  /// Bytes read by the parser, if the parser knows that.
+  pub fn bytes_processed(&self) -> Option<u64> {
+    self.bytes_processed.as_ref().map(|count| count.load(std::sync::atomic::Ordering::Relaxed))
+  }
+
+  /// For a path ending in a key, how often it was present (as either a leaf or
+  /// a container) as a fraction of how often its containing object was present.
+  /// Paths ending in an index are always present, by definition.
+  fn presence(&self, id : trie::NodeId) -> Option<f64> {
+    let node = &self.paths[id];
+    match (&node.step, node.parent) {
+      (Some(Step::Key(_)), Some(parent)) => {
+        let parent_count = self.paths[parent].container.as_ref()?.count;
+        let containers = node.container.as_ref().map(|container| container.count).unwrap_or(0);
+        Some((node.kinds.count() + containers) as f64 / parent_count as f64)
+      }
+      _ => None,
+    }
+  }
+
+  /// Display the schema as a standalone html or markdown document.
+  pub fn report(&self, format : report::Format) -> report::Report<'_> {
+    report::Report::new(self, format)
+  }
+
+  /// Display the schema as an indented tree rather than one line per path.
+  pub fn tree(&self) -> tree::Tree<'_> {
+    tree::Tree::from(self)
+  }
+
+  // If the node at id is an element of an array, the array is at least as long as its index.
+  fn update_length(&mut self, id : trie::NodeId) {
+    // lengths are only needed for tuples
+    if self.tuple_limit == 0 { return }
+    let node = &self.paths[id];
+    let index = match node.step {
+      Some(Step::Position(i)) => i,
+      // Too long for a tuple anyway, so the exact length doesn't matter.
+      Some(Step::Index) => self.tuple_limit as u64,
+      Some(Step::Key(_)) | None => return,
+    };
+    if let Some(container) = node.parent.and_then(|parent| self.paths[parent].container.as_mut()) {
+      container.length = container.length.max(index + 1)
+    }
+  }
+
+  /// A copy of the schema so far, with tuples resolved, for displaying while still collecting.
+  fn partial(&self) -> SchemaCollector {
+    let mut partial = SchemaCollector {
+      paths: self.paths.clone(),
+      bytes_processed: self.bytes_processed.clone(),
+      errors: self.errors.clone(),
+      error_count: self.error_count,
+      snapshots: None,
+      ..SchemaCollector::new().with_enum_limit(self.enum_limit).with_tuple_limit(self.tuple_limit)
+    };
+    partial.resolve_tuples();
+    partial
+  }
+
+  fn write_snapshot(&mut self) -> std::io::Result<()> {
+    let Some(mut snapshots) = self.snapshots.take() else { return Ok(()) };
+    let result = self.with_partial(|partial| snapshots.write(partial));
+    self.snapshots = Some(snapshots);
+    result
+  }
+
+  // Call f with the schema so far. Resolving tuples changes the paths, so that has to happen on a copy.
+  fn with_partial<T>(&self, f : impl FnOnce(&SchemaCollector) -> T) -> T {
+    if self.tuple_limit > 0 {
+      f(&self.partial())
+    } else {
+      f(self)
+    }
+  }
+
+  /// Collapse the positions of arrays that turned out not to be tuples, and merge
+  /// the paths that are now the same.
+  ///
+  /// Whether an array is a tuple can only be known after all of its occurrences,
+  /// so this happens once all events have been received.
+  fn resolve_tuples(&mut self) {
+    if self.tuple_limit == 0 { return }
+    for node in self.paths.nodes_mut() {
+      if let Some(container) = node.container.as_mut() { container.finish() }
+    }
+    self.resolve_positions(trie::ROOT);
+  }
+
+  // Top down, because merging the positions of an array also merges the statistics of the arrays underneath it.
+  fn resolve_positions(&mut self, id : trie::NodeId) {
+    let node = &self.paths[id];
+    let is_tuple = node.container.as_ref().is_some_and(|container| container.is_tuple(self.tuple_limit));
+    if !is_tuple && self.paths.has_positions(id) {
+      self.paths.collapse_positions(id, self.enum_limit);
+    }
+    for child in self.paths[id].children.clone() {
+      self.resolve_positions(child);
+    }
+  }
+
+  // Before each event, because SIGINT stops collecting.
+  fn check_interrupted(&self) -> Result<(), Box<dyn std::error::Error>> {
+    // returning an error from here stops the handler
+    if self.interrupted() { return Err("interrupted".into()) }
+    Ok(())
+  }
+
+  // After each event, for the snapshots.
+  fn tick(&mut self, leaf : bool) -> std::io::Result<()> {
+    if self.snapshots.as_mut().is_some_and(|snapshots| snapshots.tick(leaf)) {
+      self.write_snapshot()
+    } else {
+      Ok(())
+    }
+  }
+
+  // a leaf value at id
+  fn collect_leaf(&mut self, id : trie::NodeId, incoming : &Incoming) {
+    self.update_length(id);
+    self.paths[id].kinds.add(incoming, self.enum_limit)
+  }
+
+  // the start of an array or object at id
+  fn collect_container(&mut self, id : trie::NodeId) {
+    self.update_length(id);
+    self.paths[id].container.get_or_insert_with(Container::default).start()
+  }
+
+  // Collect these, because printing them would mix them into the schema output. Too many stops collecting.
+  fn collect_error(&mut self, path : crate::sendpath::SendPath, message : String) -> Result<(), Box<dyn std::error::Error>> {
+    self.error_count += 1;
+    if self.errors.len() < RECORDED_ERROR_LIMIT {
+      self.errors.push(RecordedError{path, message});
+    }
+    // returning an error from here stops the handler
+    if self.too_many_errors() {
+      return Err(format!("stopped after {} errors", self.error_count).into())
+    }
+    Ok(())
+  }
+
+  fn process_event(&mut self, ev: &Arc<Event<LeafValue>>) -> Result<(), Box<dyn std::error::Error>> {
+    self.check_interrupted()?;
+    self.collect_event(ev)?;
+    Ok(self.tick(matches!(**ev, Event::Value(..)))?)
   }
 
   // This is where we aggregate the types from the stream of incoming types
-  fn process_event(&mut self, ev: &Event<SchemaType>) {
-    match ev {
-      Event::Path(_p, _v) => todo!(),
-      Event::Value(p, value_type) => {
-        let path = p.0.iter().map(|step| {
-          // replace all indexes in path with generic placeholder. Because we
-          // want the schema not the full tree.
-          match step {
-            crate::jsonpath::Step::Key(v) => Step::key_with_hash(v),
-            crate::jsonpath::Step::Index(_) => Step::Index,
-          }
-        }).collect::<Vec<Step>>();
-        let path = SchemaPath(path);
-
-        // leaf_paths is path => Set<Leaf>
-        match self.leaf_paths.get_mut(&path) {
-          Some(leaf_kinds) => {
-            // find the current type in leaf_kinds
-            use SchemaType::*;
-            use NumberType::*;
-            let kind_option = leaf_kinds.iter().find(|Leaf{kind: stored_kind, ..}| {
-              #[allow(clippy::match_like_matches_macro)] // no actually it reads better like this
-              match (value_type, stored_kind) {
-                (String(_), String(_)) => true,
-                (Number(Unsigned(_)), Number(Unsigned(_))) => true,
-                (Number(Signed(_,_)), Number(Signed(_,_))) => true,
-                (Number(Float(_,_)), Number(Float(_,_))) => true,
-                (Boolean, Boolean) => true,
-                (Null, Null) => true,
-                _ => false,
-              }
-            });
-
-            // This is is now a particular SchemaType stored at leaf
-            // either create a new type, or update the existing type with current counts and values
-            match kind_option {
-              Some(kind) => {
-                // increment count
-                *kind.count.borrow_mut() += 1;
-
-                // update the max/min and other aggregates here
-                // transfer values from value_type (ie the current leaf value) to aggregate (ie in the schema we're building)
-                let updated_aggregate_option = match (value_type,&*kind.aggregate.borrow()) {
-                  (&String(val_n), &String(agg_n)) => Some(String(std::cmp::max(val_n,agg_n))),
-                  (&Number(Unsigned(val_max)), &Number(Unsigned(agg_max))) => Some(Number(Unsigned(std::cmp::max(val_max,agg_max)))),
-                  (&Number(Signed(val_min,val_max)), &Number(Signed(agg_min,agg_max))) => Some(Number(Signed(std::cmp::min(val_min,agg_min), std::cmp::max(val_max,agg_max)))),
-                  (&Number(Float(val_min,val_max)), &Number(Float(agg_min,agg_max))) => Some(Number(Float(f64::min(val_min,agg_min), f64::max(val_max,agg_max)))),
-                  _ => None, // because no aggregates are collected for other types, so no need to update anything
-                };
-
-                if let Some(updated_aggregate) = updated_aggregate_option {
-                  kind.aggregate.replace(updated_aggregate);
-                }
-              }
-              None => { leaf_kinds.insert(Leaf::new(value_type.clone())); }
-            }
-          },
-          None => {
-            // There are as yet no leafs for this path, so create a new leaf_kinds structure
-            #[allow(clippy::mutable_key_type)] // addressed by std::hash::Hash of Leaf
-            let mut leaf_kinds = LeafKinds::new();
-            leaf_kinds.insert(Leaf::new(value_type.clone()));
-            self.leaf_paths.insert(path, leaf_kinds);
-          }
-        }
+  fn collect_event(&mut self, ev: &Arc<Event<LeafValue>>) -> Result<(), Box<dyn std::error::Error>> {
+    match &**ev {
+      Event::Path(_depth, p) => {
+        let id = self.paths.find_or_insert(p, self.tuple_limit);
+        self.collect_container(id)
+      }
+      Event::Value(p, LeafValue{kind, value, position}) => {
+        let id = self.paths.find_or_insert(p, self.tuple_limit);
+        let incoming = Incoming{kind, value: value.as_deref(), indexes: p.indexes(), position: *position};
+        self.collect_leaf(id, &incoming)
       }
       Event::Finished => (),
-      // print these as they happen.
-      // TODO maybe collect them all in the schema?
-      Event::Error(path, err) => println!("{err} at path '{path}'"),
+      Event::Error(path, err) => self.collect_error(path.clone(), err.clone())?,
     }
+    Ok(())
   }
 }
 
-impl std::fmt::Display for SchemaCollector {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>
-  {
-    for (p,kinds) in &self.leaf_paths {
-      const WIDTH : usize = 40;
-      // because otherwise 50 width is applied to each element of k
+/// Format the kinds of a single path, which is either a single kind, or an array of kinds.
+fn kinds_fmt(kinds : &LeafKinds) -> String {
+  const WIDTH : usize = 40;
+  // because otherwise 50 width is applied to each element of k
 
-      // kinds is a Set, which doesn't really have a concept of .first()
-      // so just collect the pieces as an iterator.
-      let mut kfmts = kinds
-        .iter()
-        .map(|k| format!("{k:WIDTH$}") )
-        .collect::<Vec<String>>();
+  let mut kfmts = kinds
+    .iter()
+    .map(|k| format!("{k:WIDTH$}") )
+    .collect::<Vec<String>>();
 
-      let kfmt = match kinds.len() {
-        0 => String::new(),
-        // no point creating another string here, and first == last
-        1 => kfmts.pop().unwrap(),
-        _ => format!("[{}]", kfmts.join(","))
-      };
+  match kinds.len() {
+    0 => String::new(),
+    // no point creating another string here, and first == last
+    1 => kfmts.pop().unwrap(),
+    _ => format!("[{}]", kfmts.join(","))
+  }
+}
 
-      writeln!(f, "{kfmt:35} {p}")?;
+impl std::fmt::Display for SchemaCollector {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>
+  {
+    for id in self.leaf_ids() {
+      let kfmt = kinds_fmt(&self.paths[id].kinds);
+      writeln!(f, "{kfmt:35} {}", self.paths.path(id))?;
     };
     Ok(())
   }
 }
 
-impl Sender<Event<SchemaType>, Arc<Event<SchemaType>>> for SchemaCollector {
+impl Sender<Event<LeafValue>, Arc<Event<LeafValue>>> for SchemaCollector {
   // Fit in with what visitor wants
-  fn send(&mut self, ev: Arc<Event<SchemaType>>) -> Result<(), Box<dyn std::error::Error>> {
-    self.process_event(&ev);
-    Ok(())
+  fn send(&mut self, ev: Arc<Event<LeafValue>>) -> Result<(), Box<dyn std::error::Error>> {
+    self.process_event(&ev)
+  }
+}
+
+/// The ways the schema can be displayed.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Output {
+  /// one line per path
+  Flat,
+  /// indented tree
+  Tree,
+  /// standalone document
+  Report(report::Format),
+  /// json, which can be read back, eg to generate data
+  Json,
+  /// Graphviz graph of the containers
+  Dot,
+}
+
+impl std::str::FromStr for Output {
+  type Err = String;
+
+  fn from_str(s : &str) -> Result<Self, Self::Err> {
+    match s {
+      "flat" => Ok(Output::Flat),
+      "tree" => Ok(Output::Tree),
+      "json" => Ok(Output::Json),
+      "dot" => Ok(Output::Dot),
+      "html" | "md" | "markdown" => Ok(Output::Report(s.parse()?)),
+      other => Err(format!("unknown output {other}, expected flat, tree, json, dot, html or md")),
+    }
+  }
+}
+
+/// Collect the schema of the input into the collector, without displaying it.
+pub fn collect(jev : &mut dyn JsonEventSource<String>, mut collector : SchemaCollector) -> Result<SchemaCollector, Box<dyn std::error::Error>> {
+  let mut sampler = Sampler::new(collector.sampling, collector.seed);
+  collector.bytes_processed = jev.byte_counter();
+
+  if let Err(err) = walker::collect(jev, &mut collector, &mut sampler) {
+    // Stopping because of too many errors or SIGINT still leaves a useful partial schema.
+    if !collector.stopped() { return Err(err) }
+  }
+  collector.set_sampled(sampler.sampled(collector.bytes_processed()));
+  collector.resolve_tuples();
+  Ok(collector)
+}
+
+fn write_output(wr : &mut dyn std::io::Write, collector : &SchemaCollector, output : Output) -> std::io::Result<()> {
+  // the other outputs have their own place for this
+  if let (Some(sampled), Output::Flat | Output::Tree) = (collector.sampled(), output) {
+    writeln!(wr, "# {sampled}")?
+  }
+  match output {
+    Output::Flat => writeln!(wr,"{collector}"),
+    Output::Tree => write!(wr,"{}", collector.tree()),
+    Output::Report(format) => write!(wr,"{}", collector.report(format)),
+    Output::Json => write!(wr,"{}", saved::Saved::from(collector)),
+    Output::Dot => write!(wr,"{}", dot::Dot::from(collector)),
   }
 }
 
 pub fn schema(wr :&mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>) {
-  // collect and display schema of input
-  let mut collector = SchemaCollector::new();
+  schema_with(wr, jev, SchemaCollector::new(), Output::Flat).unwrap_or_else(|err| eprintln!("{err}"))
+}
+
+/// collect and display schema of input, using the given collector settings.
+///
+/// Parse errors go to stderr, so they don't get mixed up with the schema. If
+/// there were any, the result is an error.
+pub fn schema_with(wr :&mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, collector : SchemaCollector, output : Output)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let collector = match collect(jev, collector) {
+    Ok(collector) => collector,
+    Err(err) => return Err(format!("ending event reading because {err:?}").into()),
+  };
+
+  write_output(wr, &collector, output).expect("error writing schema");
+
+  for err in collector.errors() {
+    eprintln!("{err}");
+  }
 
-  // translate start/end streaming events to leaf types
-  let visitor = EventConverter::new();
+  if collector.interrupted() {
+    return Err(format!("interrupted after {} bytes", collector.bytes_processed().unwrap_or(0)).into())
+  }
 
-  match visitor.value(jev, JsonPath::new(), 0, &mut collector ) {
-    Ok(()) => writeln!(wr,"{collector}").expect("error writing schema"),
-    Err(err) => { eprintln!("ending event reading because {err:?}") },
+  match collector.error_count() {
+    0 => Ok(()),
+    n if n as usize > collector.errors().len() => Err(format!("parse errors: {n}, only the first {} shown", collector.errors().len()).into()),
+    n => Err(format!("parse errors: {n}").into()),
   }
 }
 
@@ -447,3 +1117,175 @@ pub fn sizes(wr : &mut dyn std::io::Write) -> std::io::Result<()> {
   writeln!(wr, "schema::Leaf {}", size_of::<crate::schema::Leaf>())?;
   Ok(())
 }
+
+#[cfg(test)]
+mod test_enum {
+  use super::*;
+  use crate::parser::JsonEventParser;
+
+  fn enum_of(json : &'static str, enum_limit : usize) -> Option<Vec<(String,u64)>> {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = collect(&mut jevstream, SchemaCollector::new().with_enum_limit(enum_limit)).unwrap();
+    let id = collector.paths.find(&[Step::Index, Step::Key("state".into())]).unwrap();
+    let values = collector.paths[id].kinds.iter().find(|leaf| matches!(leaf.aggregate, SchemaType::String(_)))?.enum_values();
+    values
+  }
+
+  #[test]
+  fn within_limit() {
+    let values = enum_of(r#"[{"state": "open"}, {"state": "closed"}, {"state": "open"}]"#, 2);
+    assert_eq!(values, Some(vec![("open".into(), 2), ("closed".into(), 1)]));
+  }
+
+  #[test]
+  fn over_limit() {
+    let values = enum_of(r#"[{"state": "open"}, {"state": "closed"}, {"state": "merged"}]"#, 2);
+    assert_eq!(values, None);
+  }
+
+  #[test]
+  fn all_different() {
+    assert_eq!(enum_of(r#"[{"state": "xy"}]"#, 2), None);
+    assert_eq!(enum_of(r#"[{"state": "open"}, {"state": "closed"}]"#, 2), None);
+  }
+
+  #[test]
+  fn turned_off() {
+    let values = enum_of(r#"[{"state": "open"}]"#, 0);
+    assert_eq!(values, None);
+  }
+}
+
+#[cfg(test)]
+mod test_tuples {
+  use super::*;
+  use crate::parser::JsonEventParser;
+
+  fn paths_of(json : &'static str, tuple_limit : usize) -> Vec<String> {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = collect(&mut jevstream, SchemaCollector::new().with_tuple_limit(tuple_limit)).unwrap();
+    let mut paths = collector.leaf_ids().map(|id| collector.paths.path(id).to_string()).collect::<Vec<String>>();
+    paths.sort();
+    paths
+  }
+
+  const JSON : &str = r#"[
+    {"coordinates": [1.5, 2], "tags": ["a", "b"]},
+    {"coordinates": [3, 4.5], "tags": ["c"]}
+  ]"#;
+
+  #[test]
+  fn constant_length() {
+    assert_eq!(paths_of(JSON, 2), vec!["[]/coordinates/[0]", "[]/coordinates/[1]", "[]/tags/[]"]);
+  }
+
+  #[test]
+  fn too_long() {
+    assert_eq!(paths_of(JSON, 1), vec!["[]/coordinates/[]", "[]/tags/[]"]);
+  }
+
+  #[test]
+  fn turned_off() {
+    assert_eq!(paths_of(JSON, 0), vec!["[]/coordinates/[]", "[]/tags/[]"]);
+  }
+
+  #[test]
+  fn single_occurrence() {
+    assert_eq!(paths_of(r#"{"pair": [1, "x"]}"#, 4), vec!["pair/[]"]);
+  }
+}
+
+#[cfg(test)]
+mod test_collector {
+  use super::*;
+  use crate::parser::JsonEventParser;
+
+  #[test]
+  fn send_sync() {
+    fn is_send_sync<T : Send + Sync>() {}
+    is_send_sync::<SchemaCollector>();
+  }
+
+  #[test]
+  fn kinds_in_slots() {
+    let mut jevstream = JsonEventParser::new(Box::new(r#"[1, "a", null, 2, -3, "b", null]"#.as_bytes()));
+    let collector = collect(&mut jevstream, SchemaCollector::new().with_enum_limit(0)).unwrap();
+    let id = collector.paths.find(&[Step::Index]).unwrap();
+    let kinds = collector.paths[id].kinds.iter().map(ToString::to_string).collect::<Vec<String>>();
+    assert_eq!(kinds, vec!["String(1):2", "Number(Unsigned(2)):2", "Number(Signed(-3, -3)):1", "Null:2"]);
+  }
+
+  #[test]
+  fn max_errors_one() {
+    let one = std::num::NonZeroU64::MIN;
+    let mut jevstream = JsonEventParser::new(Box::new(r#"[1, 2"#.as_bytes()));
+    let collector = collect(&mut jevstream, SchemaCollector::new().with_max_errors(one)).unwrap();
+    assert_eq!(collector.error_count(), 1);
+    assert_eq!(collector.errors().len(), 1);
+    // without errors, nothing is stopped
+    assert!(!SchemaCollector::new().with_max_errors(one).stopped());
+  }
+}
+
+#[cfg(test)]
+mod test_sampling {
+  use super::*;
+  use crate::parser::JsonEventParser;
+
+  fn sampled(json : &'static str, sampling : Sampling, ndjson : bool) -> SchemaCollector {
+    sampled_with(json, SchemaCollector::new().with_enum_limit(0).with_sampling(sampling), ndjson)
+  }
+
+  fn sampled_with(json : &'static str, collector : SchemaCollector, ndjson : bool) -> SchemaCollector {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    if ndjson { jevstream = jevstream.with_ndjson() }
+    collect(&mut jevstream, collector).unwrap()
+  }
+
+  #[test]
+  fn first_records() {
+    let json = r#"[{"a": 1}, {"a": 2, "b": 3}, {"c": [4]}]"#;
+    let collector = sampled(json, Sampling::First(2), false);
+    let mut out = vec![];
+    write_output(&mut out, &collector, Output::Flat).unwrap();
+    // up to the start of the third record, rather than all of the input that was read into the buffer
+    let bytes = json.find(r#"{"c""#).unwrap() + 1;
+    assert_eq!(String::from_utf8(out).unwrap(), format!("# sampled the first 2 records, reading {bytes} bytes\nNumber(Unsigned(2)):2               []/a\nNumber(Unsigned(3)):1               []/b\n\n"));
+  }
+
+  #[test]
+  fn seeded() {
+    // different values, so that different samples have different schemas
+    let json : &'static str = Box::leak((0..1000).map(|i| format!("{{\"a\": {i}}}\n")).collect::<String>().into_boxed_str());
+    let collector = || SchemaCollector::new().with_sampling(Sampling::Fraction(0.5)).with_seed(7);
+    let (first, second) = (sampled_with(json, collector(), true), sampled_with(json, collector(), true));
+    assert_eq!(first.sampled(), second.sampled());
+    let id = first.paths.find(&[Step::Index, Step::Key("a".into())]).unwrap();
+    let seen = |collector : &SchemaCollector| collector.paths[id].kinds.iter().map(|kind| format!("{kind}")).collect::<Vec<String>>();
+    assert_eq!(seen(&first), seen(&second));
+  }
+
+  #[test]
+  fn length_of_sampled_records() {
+    let json : &'static str = Box::leak("[1, 2]\n".repeat(100).into_boxed_str());
+    let collector = sampled_with(json, SchemaCollector::new().with_tuple_limit(2).with_sampling(Sampling::Fraction(0.5)), true);
+    let taken = collector.sampled().unwrap().taken;
+    assert_eq!(collector.paths[trie::ROOT].container.as_ref().unwrap().lengths, Some((taken, taken)));
+  }
+
+  #[test]
+  fn fraction_of_ndjson() {
+    let json : &'static str = Box::leak("{\"a\": [1, 2]}\n".repeat(1000).into_boxed_str());
+    let collector = sampled(json, Sampling::Fraction(0.1), true);
+    let sampled = collector.sampled().unwrap();
+    assert_eq!(sampled.seen, 1000);
+    assert!((50..150).contains(&sampled.taken), "{sampled}");
+    assert_eq!(collector.total_leaves(), 2 * sampled.taken);
+    assert!(sampled.to_string().starts_with(&format!("sampled {} of 1000 records (", sampled.taken)), "{sampled}");
+  }
+
+  #[test]
+  fn not_records() {
+    assert_eq!(sampled(r#"{"a": [1, 2, 3]}"#, Sampling::First(1), false).sampled(), None);
+  }
+}
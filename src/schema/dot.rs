@@ -0,0 +1,139 @@
+/*!
+Displays the structure of the schema as a Graphviz DOT graph, eg for design reviews.
+
+There is one graph node per object or array path, and one edge per key or `[]` step from a
+container to a container underneath it. The leaves of a container are listed in its label, with
+their kinds and counts. Edges are labelled with how often the key is present in its object, and are
+thicker the more often it is present.
+
+```text
+jch -s --emit dot events.json | dot -Tsvg > events.svg
+```
+*/
+
+use super::SchemaCollector;
+use super::trie::NodeId;
+
+/// Display the schema as a DOT graph.
+pub struct Dot<'a>(&'a SchemaCollector);
+
+impl<'a> From<&'a SchemaCollector> for Dot<'a> {
+  fn from(collector : &'a SchemaCollector) -> Self {
+    Self(collector)
+  }
+}
+
+// quoted and escaped, for node ids and labels
+fn quoted(s : &str) -> String {
+  format!("\"{}\"", escaped(s))
+}
+
+fn escaped(s : &str) -> String {
+  s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Edge widths go from 1 for a key that's hardly ever present, to this for one that always is.
+const MAX_PENWIDTH : f64 = 4.0;
+
+impl Dot<'_> {
+  // The node id is its path, so that the graph is readable as text too.
+  fn name(&self, id : NodeId) -> String {
+    match self.0.paths.path(id).to_string() {
+      path if path.is_empty() => quoted("."),
+      path => quoted(&path),
+    }
+  }
+
+  fn presence_pct(&self, id : NodeId) -> Option<String> {
+    self.0.presence(id).map(|presence| format!("{:.1}%", presence * 100.0))
+  }
+
+  // The path and count of the container, and then one line per leaf underneath it. In DOT, \l ends a left-justified line.
+  fn label(&self, id : NodeId) -> String {
+    let paths = &self.0.paths;
+    let node = &paths[id];
+    let path = match paths.path(id).to_string() {
+      path if path.is_empty() => ".".to_string(),
+      path => path,
+    };
+    let count = node.container.as_ref().map_or(0, |container| container.count);
+    let mut label = format!("{}  ({count})\\l", escaped(&path));
+
+    // a scalar top-level value is its own leaf
+    let leaves = std::iter::once(id).filter(|_| node.container.is_none()).chain(node.children.iter().copied());
+    for child in leaves {
+      let child_node = &paths[child];
+      if child_node.kinds.is_empty() { continue }
+      let kinds = child_node.kinds.iter().map(ToString::to_string).collect::<Vec<String>>().join(", ");
+      let step = child_node.step.as_ref().map(ToString::to_string).unwrap_or_default();
+      let presence = self.presence_pct(child).map(|pct| format!("  {pct}")).unwrap_or_default();
+      label.push_str(&escaped(&format!("{step}  {kinds}{presence}")));
+      label.push_str("\\l");
+    }
+    label
+  }
+}
+
+impl std::fmt::Display for Dot<'_> {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let paths = &self.0.paths;
+    writeln!(f, "digraph schema {{")?;
+    if let Some(sampled) = self.0.sampled() {
+      writeln!(f, "  // {sampled}")?;
+    }
+    writeln!(f, "  rankdir=LR;")?;
+    writeln!(f, "  node [shape=box, fontname=monospace];")?;
+    for id in paths.ids() {
+      let node = &paths[id];
+      // the root is always there, even when it's not a container
+      if node.container.is_none() && id != super::trie::ROOT { continue }
+      writeln!(f, "  {} [label=\"{}\"];", self.name(id), self.label(id))?;
+
+      for child in node.children.iter().copied().filter(|child| paths[*child].container.is_some()) {
+        let step = paths[child].step.as_ref().map(ToString::to_string).unwrap_or_default();
+        let (label, penwidth) = match self.0.presence(child) {
+          Some(presence) => (format!("{step}  {:.1}%", presence * 100.0), 1.0 + (MAX_PENWIDTH - 1.0) * presence.min(1.0)),
+          // indexes are always present
+          None => (step, MAX_PENWIDTH),
+        };
+        writeln!(f, "  {} -> {} [label={}, penwidth={penwidth:.1}];", self.name(id), self.name(child), quoted(&label))?;
+      }
+    }
+    writeln!(f, "}}")
+  }
+}
+
+#[cfg(test)]
+mod test_dot {
+  use crate::parser::JsonEventParser;
+  use super::super::SchemaCollector;
+
+  fn dot_of(json : &'static str) -> String {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, SchemaCollector::new().with_enum_limit(0)).unwrap();
+    super::Dot::from(&collector).to_string()
+  }
+
+  #[test]
+  fn graph() {
+    let dot = dot_of(r#"[{"id": 1, "tags": ["a"], "meta": {"say \"hi\"": true}}, {"id": 2, "tags": []}]"#);
+    let expected = r#"digraph schema {
+  rankdir=LR;
+  node [shape=box, fontname=monospace];
+  "." [label=".  (1)\l"];
+  "." -> "[]" [label="[]", penwidth=4.0];
+  "[]" [label="[]  (2)\lid  Number(Unsigned(2)):2  100.0%\l"];
+  "[]" -> "[]/tags" [label="tags  100.0%", penwidth=4.0];
+  "[]" -> "[]/meta" [label="meta  50.0%", penwidth=2.5];
+  "[]/tags" [label="[]/tags  (2)\l[]  String(1):1\l"];
+  "[]/meta" [label="[]/meta  (1)\lsay \"hi\"  Boolean:1  100.0%\l"];
+}
+"#;
+    assert_eq!(dot, expected);
+  }
+
+  #[test]
+  fn scalar() {
+    assert_eq!(dot_of("3"), "digraph schema {\n  rankdir=LR;\n  node [shape=box, fontname=monospace];\n  \".\" [label=\".  (0)\\l  Number(Unsigned(3)):1\\l\"];\n}\n");
+  }
+}
@@ -0,0 +1,144 @@
+/*!
+Shows where the values at a path came from, for finding an example of a rare path or an
+unexpected kind in the input.
+
+For each kind at the path, this shows where it was first and last seen, and then the whole record
+containing the first one. The record is the element of the top-level array, or the whole input if
+that isn't an array. Which means reading the input again, so it has to be a file.
+*/
+
+use std::io::Read;
+
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::sendpath::SendPath;
+
+use super::SchemaCollector;
+use super::SchemaPath;
+use super::Seen;
+use super::Step;
+
+/// Parse a path as it is displayed, eg `[]/payload/commits/[]/sha` or `coordinates/[0]`. Root is `.`
+/// In the notation selected by --path-format.
+impl TryFrom<&str> for SchemaPath {
+  type Error = String;
+
+  fn try_from(path : &str) -> Result<Self, Self::Error> {
+    let parts = crate::pathformat::read(path, crate::pathformat::PathFormat::global())?;
+    Ok(SchemaPath(parts.into_iter().map(Step::from).collect()))
+  }
+}
+
+// at is the path of the leaf that seen is for
+fn seen_fmt(seen : &Seen, at : &SchemaPath) -> String {
+  match seen.position() {
+    Some(position) => format!("at {position} (byte {}) in {}", position.offset, seen.path(at).written()),
+    None => format!("in {}", seen.path(at).written()),
+  }
+}
+
+/// Where the record containing the value at path is in the input.
+fn record_range(jev : &mut dyn JsonEventSource<String>, path : &SendPath) -> Result<std::ops::Range<u64>, Box<dyn std::error::Error>> {
+  let tracker = jev.position_tracker().ok_or("parser doesn't track positions")?;
+  let ev = match (path.first(), jev.next_event()?) {
+    (Some(crate::jsonpath::Step::Index(index)), JsonEvent::StartArray) => {
+      for _ in 0..index {
+        let ev = jev.next_event()?;
+        crate::handler::skip_value(jev, ev)?;
+      }
+      jev.next_event()?
+    }
+    (_, ev) => ev,
+  };
+  let start = tracker.get().offset;
+  crate::handler::skip_value(jev, ev)?;
+  // the record ends where whatever comes after it starts
+  jev.next_event()?;
+  Ok(start..tracker.get().offset)
+}
+
+/// The text of the record containing the value at path, exactly as it is in the input.
+fn record_text(reopen : &dyn Fn() -> Box<dyn std::io::BufRead>, path : &SendPath) -> Result<String, Box<dyn std::error::Error>> {
+  let range = record_range(&mut crate::parser::JsonEventParser::new(reopen()).with_positions(), path)?;
+  let mut input = reopen();
+  std::io::copy(&mut (&mut input).take(range.start), &mut std::io::sink())?;
+  let mut text = String::new();
+  input.take(range.end - range.start).read_to_string(&mut text)?;
+  Ok(text.trim_end_matches(|c : char| c.is_whitespace() || c == ',').into())
+}
+
+/// Write where the values at path were seen, and the record where each kind was first seen.
+///
+/// reopen provides the input again, to read the records from.
+pub fn explain(wr : &mut dyn std::io::Write, collector : &SchemaCollector, path : &str, reopen : &dyn Fn() -> Box<dyn std::io::BufRead>)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  let schema_path = SchemaPath::try_from(path)?;
+  let kinds = collector.paths
+    .find(&schema_path.0)
+    .map(|id| &collector.paths[id].kinds)
+    .filter(|kinds| !kinds.is_empty())
+    .ok_or_else(|| format!("no values at path '{path}'"))?;
+
+  for leaf in kinds.iter() {
+    writeln!(wr, "{path}  {leaf}")?;
+    writeln!(wr, "  first seen {}", seen_fmt(leaf.first(), &schema_path))?;
+    writeln!(wr, "  last seen {}", seen_fmt(leaf.last(), &schema_path))?;
+    writeln!(wr, "{}", record_text(reopen, &leaf.first().path(&schema_path))?)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test_explain {
+  use crate::parser::JsonEventParser;
+
+  const JSON : &str = r#"[
+{"id": 1, "release": {"name": "a"}},
+{"id": 2},
+{"id": 3, "release": {"name": null}},
+{"id": 4, "release": {"name": "b"}}
+]"#;
+
+  fn explain_of(path : &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut jevstream = JsonEventParser::new(Box::new(JSON.as_bytes())).with_positions();
+    let collector = super::super::collect(&mut jevstream, super::super::SchemaCollector::new().with_enum_limit(0))?;
+    let mut out = vec![];
+    super::explain(&mut out, &collector, path, &|| Box::new(JSON.as_bytes()))?;
+    Ok(String::from_utf8(out)?)
+  }
+
+  #[test]
+  fn first_and_last() {
+    let explained = explain_of("[]/release/name").unwrap();
+    let expected = r#"[]/release/name  String(1):2
+  first seen at 2:31 (byte 32) in [0]/release/name
+  last seen at 5:31 (byte 118) in [3]/release/name
+{"id": 1, "release": {"name": "a"}}
+[]/release/name  Null:1
+  first seen at 4:31 (byte 80) in [2]/release/name
+  last seen at 4:31 (byte 80) in [2]/release/name
+{"id": 3, "release": {"name": null}}
+"#;
+    assert_eq!(explained, expected);
+  }
+
+  #[test]
+  fn missing() {
+    assert_eq!(explain_of("[]/nope").unwrap_err().to_string(), "no values at path '[]/nope'");
+    // a container, but not a leaf
+    assert!(explain_of("[]/release").is_err());
+  }
+
+  #[test]
+  fn scalar_records() {
+    let json = "[1, \"two\" ,\n 3.0]";
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes())).with_positions();
+    let collector = super::super::collect(&mut jevstream, super::super::SchemaCollector::new()).unwrap();
+    let mut out = vec![];
+    super::explain(&mut out, &collector, "[]", &|| Box::new(json.as_bytes())).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("in [1]\n\"two\"\n"), "{out}");
+    assert!(out.contains("in [2]\n3.0\n"), "{out}");
+  }
+}
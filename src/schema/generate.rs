@@ -0,0 +1,238 @@
+/*!
+Generate json that looks like the input of a saved schema, without containing any of it, eg for load
+tests and fixtures.
+
+Each path is present about as often as it was in the input, and each kind is chosen in proportion
+to its count. Strings are random, up to the longest length seen, unless they were an enum in which
+case the values are chosen in proportion to their counts. Numbers are within the range seen.
+
+Records are written one at a time, so there's no limit to how many can be generated.
+*/
+
+use std::io::Write;
+
+use super::NumberType;
+use super::SchemaType;
+use super::Step;
+use super::saved::SavedKind;
+use super::saved::SavedNode;
+
+/// How the records are written.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Format {
+  /// one json array of records
+  Array,
+  /// one record per line
+  Ndjson,
+}
+
+pub struct Generator {
+  rng : fastrand::Rng,
+}
+
+// What to generate at a path, once it's known to be present.
+enum Choice<'a> {
+  Leaf(&'a SavedKind),
+  Container,
+  Nothing,
+}
+
+impl Generator {
+  /// The same seed generates the same data.
+  pub fn new(seed : Option<u64>) -> Self {
+    let rng = match seed {
+      Some(seed) => fastrand::Rng::with_seed(seed),
+      None => fastrand::Rng::new(),
+    };
+    Self{rng}
+  }
+
+  // true with probability count / total
+  fn chance(&mut self, count : u64, total : u64) -> bool {
+    count >= total || self.rng.u64(0..total) < count
+  }
+
+  // one of the kinds of node, or the container, in proportion to their counts.
+  fn choose<'a>(&mut self, node : &'a SavedNode) -> Choice<'a> {
+    let total = node.count();
+    if total == 0 { return Choice::Nothing }
+    let mut pick = self.rng.u64(0..total);
+    for kind in &node.kinds {
+      if pick < kind.count { return Choice::Leaf(kind) }
+      pick -= kind.count;
+    }
+    Choice::Container
+  }
+
+  fn leaf(&mut self, wr : &mut dyn Write, kind : &SavedKind) -> std::io::Result<()> {
+    match (&kind.kind, &kind.values) {
+      (SchemaType::String(_), Some(values)) if !values.is_empty() => {
+        let total = values.iter().map(|(_, count)| count).sum::<u64>();
+        let mut pick = self.rng.u64(0..total.max(1));
+        let value = values.iter().find(|(_, count)| if pick < *count { true } else { pick -= count; false }).unwrap_or(&values[0]);
+        write!(wr, "{}", serde_json::Value::String(value.0.clone()))
+      }
+      (SchemaType::String(max_length), _) => {
+        let length = self.rng.u64(0..=*max_length);
+        let value = (0..length).map(|_| self.rng.alphanumeric()).collect::<String>();
+        // alphanumeric never needs escaping
+        write!(wr, "\"{value}\"")
+      }
+      (SchemaType::Number(NumberType::Unsigned(max)), _) => write!(wr, "{}", self.rng.u64(0..=*max)),
+      (SchemaType::Number(NumberType::Signed(min, max)), _) => write!(wr, "{}", self.rng.i64(*min..=*max)),
+      (SchemaType::Number(NumberType::Float(min, max)), _) => {
+        let value = min + self.rng.f64() * (max - min);
+        // json has no NaN, so make it a float that is
+        let value = if value.is_finite() { value } else { 0.0 };
+        write!(wr, "{}", serde_json::Value::from(value))
+      }
+      (SchemaType::Boolean, _) => write!(wr, "{}", self.rng.bool()),
+      (SchemaType::Null, _) | (SchemaType::Unknown(_), _) => write!(wr, "null"),
+    }
+  }
+
+  fn array(&mut self, wr : &mut dyn Write, node : &SavedNode) -> std::io::Result<()> {
+    write!(wr, "[")?;
+    let positions = node.children.iter().filter(|child| matches!(child.step, Some(Step::Position(_)))).collect::<Vec<&SavedNode>>();
+    if positions.is_empty() {
+      if let Some(elements) = node.children.iter().find(|child| child.step == Some(Step::Index)) {
+        let arrays = node.container.map_or(1, |(count, _)| count).max(1);
+        let length = match node.container {
+          Some((_, Some((min, max)))) => self.rng.u64(min..=max),
+          // about the same average length as the input
+          _ => self.rng.u64(0..=(2 * elements.count()).div_ceil(arrays)),
+        };
+        for i in 0..length {
+          if i > 0 { write!(wr, ",")? }
+          self.value(wr, elements)?;
+        }
+      }
+    } else {
+      // a tuple always has all its positions
+      for (i, position) in positions.into_iter().enumerate() {
+        if i > 0 { write!(wr, ",")? }
+        self.value(wr, position)?;
+      }
+    }
+    write!(wr, "]")
+  }
+
+  fn object(&mut self, wr : &mut dyn Write, node : &SavedNode) -> std::io::Result<()> {
+    write!(wr, "{{")?;
+    let objects = node.container.map_or(1, |(count, _)| count);
+    let mut first = true;
+    for child in &node.children {
+      let Some(Step::Key(key)) = &child.step else { continue };
+      if !self.chance(child.count(), objects) { continue }
+      if !first { write!(wr, ",")? }
+      first = false;
+      write!(wr, "{}:", serde_json::Value::String(key.clone()))?;
+      self.value(wr, child)?;
+    }
+    write!(wr, "}}")
+  }
+
+  /// Write one occurrence of node.
+  pub fn value(&mut self, wr : &mut dyn Write, node : &SavedNode) -> std::io::Result<()> {
+    match self.choose(node) {
+      Choice::Leaf(kind) => self.leaf(wr, kind),
+      // A container without any keys was either always an empty object, or always an empty array.
+      // There's no way to tell which, so it's an array, which is the more likely.
+      Choice::Container if node.children.iter().any(|child| matches!(child.step, Some(Step::Key(_)))) => self.object(wr, node),
+      Choice::Container => self.array(wr, node),
+      Choice::Nothing => write!(wr, "null"),
+    }
+  }
+}
+
+/// Write records like the records of the saved schema at root. The records are the elements of the
+/// top-level array, or the top-level value if that isn't an array.
+pub fn generate(wr : &mut dyn Write, root : &SavedNode, records : u64, format : Format, generator : &mut Generator)
+-> std::io::Result<()>
+{
+  let record = root.children.iter()
+    .find(|child| child.step == Some(Step::Index) && root.kinds.is_empty())
+    .unwrap_or(root);
+
+  if format == Format::Array { write!(wr, "[")? }
+  for i in 0..records {
+    match format {
+      Format::Array if i > 0 => writeln!(wr, ",")?,
+      Format::Array => writeln!(wr)?,
+      Format::Ndjson => (),
+    }
+    generator.value(wr, record)?;
+    if format == Format::Ndjson { writeln!(wr)? }
+  }
+  if format == Format::Array { writeln!(wr, "\n]")? }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test_generate {
+  use crate::parser::JsonEventParser;
+  use super::super::SchemaCollector;
+  use super::Format;
+  use super::Generator;
+
+  const JSON : &str = r#"[
+    {"id": 3, "kind": "a", "tags": ["x", "yy"], "score": -1.5, "gone": null},
+    {"id": 9, "kind": "b", "tags": [], "score": 2.0},
+    {"id": 7, "kind": "a", "tags": ["zzz"], "score": 0.5}
+  ]"#;
+
+  fn root_of(json : &'static str) -> super::SavedNode {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, SchemaCollector::new()).unwrap();
+    let saved = super::super::saved::Saved::from(&collector).to_string();
+    super::super::saved::read(&mut saved.as_bytes()).unwrap()
+  }
+
+  fn generated(records : u64, format : Format) -> String {
+    let mut out = vec![];
+    super::generate(&mut out, &root_of(JSON), records, format, &mut Generator::new(Some(42))).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn respects_schema() {
+    let records : Vec<serde_json::Value> = serde_json::from_str(&generated(500, Format::Array)).unwrap();
+    assert_eq!(records.len(), 500);
+    let mut gone = 0;
+    for record in &records {
+      let id = record["id"].as_u64().unwrap();
+      assert!(id <= 9, "{record}");
+      assert!(["a", "b"].contains(&record["kind"].as_str().unwrap()), "{record}");
+      let score = record["score"].as_f64().unwrap();
+      assert!((-1.5..=2.0).contains(&score), "{record}");
+      for tag in record["tags"].as_array().unwrap() {
+        assert!(tag.as_str().unwrap().len() <= 3, "{record}");
+      }
+      if record.get("gone").is_some() { gone += 1 }
+    }
+    // present in 1 of 3 records
+    assert!((100..250).contains(&gone), "{gone}");
+  }
+
+  #[test]
+  fn ndjson() {
+    let out = generated(10, Format::Ndjson);
+    assert_eq!(out.lines().count(), 10);
+    for line in out.lines() {
+      let record : serde_json::Value = serde_json::from_str(line).unwrap();
+      assert!(record.is_object());
+    }
+  }
+
+  #[test]
+  fn seeded() {
+    assert_eq!(generated(20, Format::Ndjson), generated(20, Format::Ndjson));
+  }
+
+  #[test]
+  fn empty_arrays() {
+    let mut out = vec![];
+    super::generate(&mut out, &root_of(r#"[{"a": []}, {"a": []}]"#), 2, Format::Ndjson, &mut Generator::new(Some(1))).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "{\"a\":[]}\n{\"a\":[]}\n");
+  }
+}
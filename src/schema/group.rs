@@ -0,0 +1,338 @@
+/*!
+Separate schemas for each value of a discriminator field. For example the GitHub events in the
+README have a `type` field, and the shape of their `payload` depends on it.
+
+The discriminator is relative to each record, ie each element of the top-level array, or the whole
+input if that isn't an array. The events of a record are buffered until its discriminator turns up,
+after which they go straight to the collector for that value. Only the first BUFFER_LIMIT events of
+a record are buffered, so memory is bounded, and a discriminator that comes later than that is not
+found.
+*/
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::handler::Handler;
+use crate::jsonpath::JsonPath;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::sender::Event;
+use crate::sender::Sender;
+use crate::sendpath::SendPath;
+
+use super::EventConverter;
+use super::LeafValue;
+use super::Output;
+use super::SchemaCollector;
+
+/// The group for records where the discriminator was not found.
+pub const MISSING : &str = "(missing)";
+
+/// How many events of a record to buffer while looking for its discriminator.
+const BUFFER_LIMIT : usize = 10_000;
+
+type SendEvent = Arc<Event<LeafValue>>;
+
+/// One group: the number of records, and their schema.
+type Group = (u64, SchemaCollector);
+
+/// Sends the events of each record to the collector for the value of its discriminator.
+pub struct GroupedCollector {
+  // settings for the collector of each group. Its snapshots and error count are for all the groups.
+  template : SchemaCollector,
+  // as it was given, for headings
+  written : String,
+  // relative to the record
+  discriminator : Vec<crate::jsonpath::Step>,
+  groups : BTreeMap<String, Group>,
+  // the start of the top-level container, which every group gets.
+  root : Option<SendEvent>,
+  // None is the whole input
+  record : Option<Option<u64>>,
+  // the group of the current record, once the discriminator has been seen
+  group : Option<String>,
+  buffer : Vec<SendEvent>,
+}
+
+// The record containing path, if the input is an array of records.
+fn record_of(path : &SendPath) -> Option<u64> {
+  path.first_index()
+}
+
+fn write_heading(wr : &mut dyn std::io::Write, discriminator : &str, name : &str, records : u64) -> std::io::Result<()> {
+  writeln!(wr, "# {discriminator} = {name}  ({records} records)\n")
+}
+
+// The discriminator value as a group name
+fn group_name(value : &JsonEvent<String>) -> String {
+  match value {
+    JsonEvent::String(v) | JsonEvent::Number(v) => v.clone(),
+    JsonEvent::Boolean(v) => v.to_string(),
+    JsonEvent::Null => "null".into(),
+    other => format!("{other:?}"),
+  }
+}
+
+impl GroupedCollector {
+  /// discriminator is a path relative to each record, eg `type` or `payload/action`, in the notation selected by --path-format.
+  pub fn new(template : SchemaCollector, discriminator : &str) -> Result<Self, String> {
+    let steps = crate::pathformat::read(discriminator, crate::pathformat::PathFormat::global())?
+      .into_iter()
+      .map(crate::jsonpath::Step::try_from)
+      .collect::<Result<_, _>>()?;
+    Ok(Self{template, written: discriminator.into(), discriminator: steps, groups: BTreeMap::new(), root: None, record: None, group: None, buffer: vec![]})
+  }
+
+  fn group_mut(&mut self, name : &str) -> Result<&mut Group, Box<dyn std::error::Error>> {
+    if !self.groups.contains_key(name) {
+      let mut collector = self.template.like();
+      if let Some(root) = &self.root { collector.send(root.clone())? }
+      self.groups.insert(name.into(), (0, collector));
+    }
+    Ok(self.groups.get_mut(name).expect("just inserted"))
+  }
+
+  // Send the buffer to the group of the current record.
+  fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    let name = self.group.clone().unwrap_or_else(|| MISSING.into());
+    let buffer = std::mem::take(&mut self.buffer);
+    let (_, collector) = self.group_mut(&name)?;
+    for ev in buffer {
+      collector.send(ev)?;
+    }
+    Ok(())
+  }
+
+  // The current record is complete.
+  fn finish_record(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if self.record.take().is_none() { return Ok(()) }
+    self.group.get_or_insert_with(|| MISSING.into());
+    self.flush()?;
+    let name = self.group.take().expect("set above");
+    self.group_mut(&name)?.0 += 1;
+    Ok(())
+  }
+
+  fn route(&mut self, ev : SendEvent, path : &SendPath) -> Result<(), Box<dyn std::error::Error>> {
+    let record = record_of(path);
+    if self.record != Some(record) {
+      self.finish_record()?;
+      self.record = Some(record);
+    }
+
+    if let Some(name) = &self.group {
+      return self.groups.get_mut(name).expect("created when the group was found").1.send(ev)
+    }
+
+    if let Event::Value(_, LeafValue{value, ..}) = &*ev {
+      // path relative to the record
+      let depth = self.discriminator.len() + usize::from(record.is_some());
+      if path.len() == depth && path.ends_with(&self.discriminator) { self.group = Some(group_name(value)) }
+    }
+    self.buffer.push(ev);
+    if self.group.is_some() {
+      self.flush()
+    } else if self.buffer.len() >= BUFFER_LIMIT {
+      self.group = Some(MISSING.into());
+      self.flush()
+    } else {
+      Ok(())
+    }
+  }
+
+  fn write_snapshot(&mut self) -> std::io::Result<()> {
+    let Some(mut snapshots) = self.template.snapshots.take() else { return Ok(()) };
+    let result = snapshots.write_with(self.template.bytes_processed(), |wr, output| {
+      for (name, records, collector) in self.groups() {
+        write_heading(wr, &self.written, name, records)?;
+        collector.with_partial(|partial| super::write_output(wr, partial, output))?;
+        writeln!(wr)?;
+      }
+      Ok(())
+    });
+    self.template.snapshots = Some(snapshots);
+    result
+  }
+
+  /// (discriminator value, number of records, schema) for each group, ordered by value.
+  pub fn groups(&self) -> impl Iterator<Item = (&str, u64, &SchemaCollector)> {
+    self.groups.iter().map(|(name, (records, collector))| (name.as_str(), *records, collector))
+  }
+}
+
+impl Sender<Event<LeafValue>, SendEvent> for GroupedCollector {
+  fn send(&mut self, ev : SendEvent) -> Result<(), Box<dyn std::error::Error>> {
+    if self.template.interrupted() { return Err("interrupted".into()) }
+    let leaf = matches!(*ev, Event::Value(..));
+    let error = matches!(*ev, Event::Error(..));
+    match &*ev {
+      Event::Path(_, path) if path.is_empty() => self.root = Some(ev.clone()),
+      Event::Path(_, path) | Event::Value(path, _) | Event::Error(path, _) => {
+        let path = path.clone();
+        self.route(ev, &path)?
+      }
+      Event::Finished => {
+        self.finish_record()?;
+        for (_, collector) in self.groups.values_mut() {
+          collector.send(ev.clone())?;
+        }
+      }
+    }
+
+    // --max-errors is for the whole input, not for each group
+    if error {
+      self.template.error_count += 1;
+      if self.template.too_many_errors() {
+        return Err(format!("stopped after {} errors", self.template.error_count).into())
+      }
+    }
+    if self.template.snapshots.as_mut().is_some_and(|snapshots| snapshots.tick(leaf)) {
+      self.write_snapshot()?
+    }
+    Ok(())
+  }
+}
+
+/// Collect a schema for each value of discriminator, using the settings of template.
+pub fn collect(jev : &mut dyn JsonEventSource<String>, template : SchemaCollector, discriminator : &str)
+-> Result<GroupedCollector, Box<dyn std::error::Error>>
+{
+  let visitor = EventConverter::new()
+    .with_positions(jev.position_tracker())
+    .with_sampling(template.sampling)
+    .with_seed(template.seed);
+  let mut grouped = GroupedCollector::new(template, discriminator)?;
+  let bytes_processed = jev.byte_counter();
+  grouped.template.bytes_processed = bytes_processed.clone();
+
+  if let Err(err) = visitor.value(jev, JsonPath::new(), 0, &mut grouped) {
+    // same as for a single schema, too many errors still leaves useful partial schemas.
+    if !grouped.template.stopped() { return Err(err) }
+  }
+  grouped.finish_record()?;
+
+  // the sample is of all the records, not of each group
+  grouped.template.sampled = visitor.sampled(bytes_processed.as_ref().map(|bytes| bytes.load(std::sync::atomic::Ordering::Relaxed)));
+  for (records, collector) in grouped.groups.values_mut() {
+    collector.bytes_processed = bytes_processed.clone();
+    if grouped.template.sampled.is_some() { collector.set_records(*records) }
+    collector.resolve_tuples();
+  }
+  Ok(grouped)
+}
+
+/// Collect and display a schema for each value of discriminator.
+pub fn schema_with(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, template : SchemaCollector, discriminator : &str, output : Output)
+-> Result<(), Box<dyn std::error::Error>>
+{
+  if matches!(output, Output::Report(super::report::Format::Html) | Output::Json | Output::Dot) {
+    return Err("html reports, json and dot can't be grouped, because that would be several documents".into())
+  }
+  let grouped = collect(jev, template, discriminator)?;
+  if let Some(sampled) = grouped.template.sampled() {
+    writeln!(wr, "# {sampled}\n")?;
+  }
+
+  let mut error_count = 0;
+  for (name, records, collector) in grouped.groups() {
+    write_heading(wr, discriminator, name, records)?;
+    super::write_output(wr, collector, output)?;
+    writeln!(wr)?;
+    for err in collector.errors() {
+      eprintln!("{err}");
+    }
+    error_count += collector.error_count();
+  }
+
+  if grouped.template.interrupted() {
+    let bytes = grouped.groups().find_map(|(_, _, collector)| collector.bytes_processed()).unwrap_or(0);
+    return Err(format!("interrupted after {bytes} bytes").into())
+  }
+
+  match error_count {
+    0 => Ok(()),
+    n => Err(format!("parse errors: {n}").into()),
+  }
+}
+
+#[cfg(test)]
+mod test_group {
+  use crate::parser::JsonEventParser;
+  use super::super::SchemaCollector;
+
+  // group name => sorted leaf paths
+  fn groups_of(json : &'static str, discriminator : &str) -> Vec<(String, u64, Vec<String>)> {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let grouped = super::collect(&mut jevstream, SchemaCollector::new(), discriminator).unwrap();
+    grouped.groups().map(|(name, records, collector)| {
+      let mut paths = collector.leaf_ids().map(|id| collector.paths.path(id).to_string()).collect::<Vec<String>>();
+      paths.sort();
+      (name.to_string(), records, paths)
+    }).collect()
+  }
+
+  const JSON : &str = r#"[
+    {"type": "push", "payload": {"commits": [{"sha": "a"}]}},
+    {"payload": {"action": "opened"}, "type": "issue"},
+    {"type": "push", "payload": {"commits": []}},
+    {"payload": {}}
+  ]"#;
+
+  #[test]
+  fn by_type() {
+    assert_eq!(groups_of(JSON, "type"), vec![
+      (super::MISSING.to_string(), 1, vec![]),
+      ("issue".to_string(), 1, vec!["[]/payload/action".to_string(), "[]/type".to_string()]),
+      ("push".to_string(), 2, vec!["[]/payload/commits/[]/sha".to_string(), "[]/type".to_string()]),
+    ]);
+  }
+
+  #[test]
+  fn nested_discriminator() {
+    let groups = groups_of(JSON, "payload/action");
+    let names = groups.iter().map(|(name, records, _)| (name.as_str(), *records)).collect::<Vec<(&str, u64)>>();
+    assert_eq!(names, vec![(super::MISSING, 3), ("opened", 1)]);
+  }
+
+  #[test]
+  fn presence_within_group() {
+    let mut jevstream = JsonEventParser::new(Box::new(JSON.as_bytes()));
+    let grouped = super::collect(&mut jevstream, SchemaCollector::new(), "type").unwrap();
+    let (_, _, push) = grouped.groups().find(|(name, _, _)| *name == "push").unwrap();
+    let id = push.paths.find(&[super::super::Step::Index, super::super::Step::Key("type".into())]).unwrap();
+    assert_eq!(push.presence(id), Some(1.0));
+  }
+
+  #[test]
+  fn max_errors_for_all_groups() {
+    let ndjson = "{\"type\": \"a\", \"x\": 1}\n{\"type\": \"a\", x}\n{\"type\": \"b\", y}\n{\"type\": \"c\", z}\n{\"type\": \"d\"}\n";
+    let mut jevstream = JsonEventParser::new(Box::new(ndjson.as_bytes())).with_ndjson();
+    let grouped = super::collect(&mut jevstream, SchemaCollector::new().with_max_errors(3.try_into().unwrap()), "type").unwrap();
+    let errors = grouped.groups().map(|(name, _, collector)| (name, collector.error_count())).collect::<Vec<(&str, u64)>>();
+    // each bad line is two errors, so each group on its own would not have stopped
+    assert_eq!(errors, vec![("a", 2), ("b", 1)]);
+  }
+
+  #[test]
+  fn snapshots_of_all_groups() {
+    let file = std::env::temp_dir().join(format!("jch-test-group-snapshot-{}", std::process::id()));
+    let snapshots = super::super::snapshot::Snapshots::new(super::super::Output::Flat).every_leaves(5).to_file(&file);
+    let mut jevstream = JsonEventParser::new(Box::new(JSON.as_bytes()));
+    super::collect(&mut jevstream, SchemaCollector::new().with_snapshots(snapshots), "type").unwrap();
+
+    // after the 5th leaf, the "push" of the third record
+    let snapshot = std::fs::read_to_string(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    assert!(snapshot.starts_with("# partial schema after 5 leaves, "), "{snapshot}");
+    assert!(snapshot.contains("# type = issue  (1 records)\n"), "{snapshot}");
+    assert!(snapshot.contains("# type = push  (1 records)\n"), "{snapshot}");
+    assert!(snapshot.contains(" []/payload/commits/[]/sha\n"), "{snapshot}");
+  }
+
+  #[test]
+  fn whole_document() {
+    assert_eq!(groups_of(r#"{"type": "x", "a": 1}"#, "type"), vec![
+      ("x".to_string(), 1, vec!["a".to_string(), "type".to_string()]),
+    ]);
+  }
+}
@@ -0,0 +1,309 @@
+/*!
+Renders the schema as a single self-contained document, either html or markdown.
+
+Intended for attaching to a data catalogue, so it has a summary header, and then
+the path tree with kinds, counts, ranges, presence and sample values for each path.
+*/
+
+use super::tree::Node;
+use super::trie::NodeId;
+use super::LeafKinds;
+use super::NumberType;
+use super::SchemaCollector;
+use super::SchemaType;
+
+/// The kinds of document that can be produced.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Format {
+  Html,
+  Markdown,
+}
+
+impl std::str::FromStr for Format {
+  type Err = String;
+
+  fn from_str(s : &str) -> Result<Self, Self::Err> {
+    match s {
+      "html" => Ok(Format::Html),
+      "md" | "markdown" => Ok(Format::Markdown),
+      other => Err(format!("unknown report format {other}, expected html or md")),
+    }
+  }
+}
+
+/// Borrows the collected schema, and Display renders it as a document.
+pub struct Report<'a> {
+  collector : &'a SchemaCollector,
+  format : Format,
+}
+
+/// One row per kind of a path.
+struct Row {
+  kind : &'static str,
+  count : u64,
+  range : String,
+  samples : String,
+}
+
+fn kind_name(kind : &SchemaType) -> &'static str {
+  match kind {
+    SchemaType::String(_) => "string",
+    SchemaType::Number(NumberType::Unsigned(_)) => "unsigned",
+    SchemaType::Number(NumberType::Signed(_,_)) => "signed",
+    SchemaType::Number(NumberType::Float(_,_)) => "float",
+    SchemaType::Boolean => "boolean",
+    SchemaType::Null => "null",
+    SchemaType::Unknown(_) => "unknown",
+  }
+}
+
+fn range_of(aggregate : &SchemaType) -> String {
+  match aggregate {
+    SchemaType::String(max_len) => format!("length ≤ {max_len}"),
+    SchemaType::Number(NumberType::Unsigned(max)) => format!("≤ {max}"),
+    SchemaType::Number(NumberType::Signed(min,max)) => format!("{min} … {max}"),
+    SchemaType::Number(NumberType::Float(min,max)) => format!("{min} … {max}"),
+    _ => String::new(),
+  }
+}
+
+fn rows_of(kinds : &LeafKinds) -> Vec<Row> {
+  let mut rows = kinds.iter().map(|leaf| Row {
+    kind: kind_name(&leaf.aggregate),
+    count: leaf.count,
+    range: range_of(&leaf.aggregate),
+    // enums have all their values, so those are better than samples
+    samples: match leaf.enum_values() {
+      Some(values) => values
+        .iter()
+        .map(|(v,count)| format!("{} ×{count}", serde_json::Value::String(v.clone())))
+        .collect::<Vec<String>>()
+        .join(", "),
+      None => leaf.samples.join(", "),
+    },
+  }).collect::<Vec<Row>>();
+  rows.sort_by_key(|row| row.kind);
+  rows
+}
+
+fn html_escape(s : &str) -> String {
+  s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn md_escape(s : &str) -> String {
+  s.replace('|', "\\|")
+}
+
+const HTML_STYLE : &str = "
+body { font-family: sans-serif; margin: 2em; }
+code { font-size: 1.05em; }
+details { margin-left: 1.5em; }
+summary { cursor: pointer; padding: 0.1em 0; }
+.note { color: #666; margin-left: 0.5em; }
+table { border-collapse: collapse; margin: 0.3em 0 0.6em 1.5em; }
+th, td { border: 1px solid #ccc; padding: 0.15em 0.6em; text-align: left; }
+th { background: #f4f4f4; }
+";
+
+impl<'a> Report<'a> {
+  pub fn new(collector : &'a SchemaCollector, format : Format) -> Self {
+    Self{collector, format}
+  }
+
+  fn path_name(&self, id : NodeId) -> String {
+    let path = self.collector.paths.path(id);
+    if path.0.is_empty() { ".".into() } else { path.to_string() }
+  }
+
+  fn presence_note(&self, id : NodeId) -> Option<String> {
+    self.collector
+      .presence(id)
+      .map(|presence| format!("present in {:.1}%", presence * 100.0))
+  }
+
+  fn summary(&self) -> Vec<(&'static str, String)> {
+    let bytes = self.collector.bytes_processed()
+      .map(|bytes| bytes.to_string())
+      .unwrap_or_else(|| "unknown".into());
+    let mut summary = vec![
+      ("Total leaves", self.collector.total_leaves().to_string()),
+      ("Leaf paths", self.collector.leaf_ids().count().to_string()),
+      ("Maximum depth", self.collector.max_depth().to_string()),
+      ("Bytes processed", bytes),
+      ("Parse errors", self.collector.error_count().to_string()),
+    ];
+    if let Some(sampled) = self.collector.sampled() {
+      summary.push(("Sampled", sampled.to_string()));
+    }
+    summary
+  }
+
+  fn write_html_node(&self, f : &mut std::fmt::Formatter<'_>, name : &str, node : &Node<'_>) -> std::fmt::Result {
+    let (paths, values) = node.aggregate();
+    writeln!(f, "<details open>")?;
+    write!(f, "<summary><code>{}</code>", html_escape(name))?;
+    if let Some(note) = self.presence_note(node.id) {
+      write!(f, "<span class=\"note\">{note}</span>")?;
+    }
+    if !node.children.is_empty() {
+      write!(f, "<span class=\"note\">{values} values in {paths} paths</span>")?;
+    }
+    writeln!(f, "</summary>")?;
+
+    if let Some((_, kinds)) = node.leaf {
+      writeln!(f, "<table>")?;
+      writeln!(f, "<tr><th>kind</th><th>count</th><th>range</th><th>samples</th></tr>")?;
+      for Row{kind, count, range, samples} in rows_of(kinds) {
+        writeln!(f, "<tr><td>{kind}</td><td>{count}</td><td>{}</td><td><code>{}</code></td></tr>", html_escape(&range), html_escape(&samples))?;
+      }
+      writeln!(f, "</table>")?;
+    }
+
+    for (step, child) in &node.children {
+      self.write_html_node(f, &step.to_string(), child)?;
+    }
+    writeln!(f, "</details>")
+  }
+
+  fn write_html(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "<!DOCTYPE html>")?;
+    writeln!(f, "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Schema report</title>")?;
+    writeln!(f, "<style>{HTML_STYLE}</style>\n</head>\n<body>")?;
+    writeln!(f, "<h1>Schema report</h1>")?;
+    writeln!(f, "<table>")?;
+    for (name, value) in self.summary() {
+      writeln!(f, "<tr><th>{name}</th><td>{value}</td></tr>")?;
+    }
+    writeln!(f, "</table>")?;
+    writeln!(f, "<h2>Paths</h2>")?;
+    self.write_html_node(f, ".", &Node::root(self.collector))?;
+    if !self.collector.errors().is_empty() {
+      writeln!(f, "<h2>Errors</h2>\n<ul>")?;
+      for err in self.collector.errors() {
+        writeln!(f, "<li>{}</li>", html_escape(&err.to_string()))?;
+      }
+      writeln!(f, "</ul>")?;
+    }
+    writeln!(f, "</body>\n</html>")
+  }
+
+  fn write_md_tree(&self, f : &mut std::fmt::Formatter<'_>, name : &str, node : &Node<'_>, indent : usize) -> std::fmt::Result {
+    let (paths, values) = node.aggregate();
+    write!(f, "{:indent$}- `{name}`", "")?;
+    if let Some(note) = self.presence_note(node.id) {
+      write!(f, " {note}")?;
+    }
+    if !node.children.is_empty() {
+      write!(f, " ({values} values in {paths} paths)")?;
+    }
+    writeln!(f)?;
+    for (step, child) in &node.children {
+      self.write_md_tree(f, &step.to_string(), child, indent + 2)?;
+    }
+    Ok(())
+  }
+
+  // depth-first, so the table is in the same order as the tree
+  fn write_md_rows(&self, f : &mut std::fmt::Formatter<'_>, node : &Node<'_>) -> std::fmt::Result {
+    if let Some((id, kinds)) = node.leaf {
+      let presence = self.collector.presence(id)
+        .map(|presence| format!("{:.1}%", presence * 100.0))
+        .unwrap_or_default();
+      for Row{kind, count, range, samples} in rows_of(kinds) {
+        writeln!(f, "| `{}` | {kind} | {count} | {range} | {presence} | {} |", md_escape(&self.path_name(id)), md_escape(&samples))?;
+      }
+    }
+    for child in node.children.values() {
+      self.write_md_rows(f, child)?;
+    }
+    Ok(())
+  }
+
+  fn write_markdown(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let root = Node::root(self.collector);
+    writeln!(f, "# Schema report\n")?;
+    for (name, value) in self.summary() {
+      writeln!(f, "- **{name}**: {value}")?;
+    }
+    writeln!(f, "\n## Path tree\n\n<details open>\n<summary>Path tree</summary>\n")?;
+    self.write_md_tree(f, ".", &root, 0)?;
+    writeln!(f, "\n</details>\n\n## Paths\n")?;
+    writeln!(f, "| Path | Kind | Count | Range | Presence | Samples |")?;
+    writeln!(f, "|---|---|---|---|---|---|")?;
+    self.write_md_rows(f, &root)?;
+    if !self.collector.errors().is_empty() {
+      writeln!(f, "\n## Errors\n")?;
+      for err in self.collector.errors() {
+        writeln!(f, "- {err}")?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for Report<'_> {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.format {
+      Format::Html => self.write_html(f),
+      Format::Markdown => self.write_markdown(f),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test_report {
+  use super::Format;
+  use crate::parser::JsonEventParser;
+
+  fn report_of(json : &'static str, format : Format) -> String {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, super::super::SchemaCollector::new()).unwrap();
+    collector.report(format).to_string()
+  }
+
+  const JSON : &str = r#"[{"a": 1, "b": "x<y"}, {"a": 3}, {"a": 2, "b": "z"}, {"a": 1}]"#;
+
+  #[test]
+  fn markdown() {
+    let report = report_of(JSON, Format::Markdown);
+    assert!(report.contains("- **Total leaves**: 6\n"), "{report}");
+    assert!(report.contains("- **Maximum depth**: 2\n"), "{report}");
+    assert!(report.contains(&format!("- **Bytes processed**: {}\n", JSON.len())), "{report}");
+    assert!(report.contains("- **Parse errors**: 0\n"), "{report}");
+    assert!(!report.contains("## Errors"), "{report}");
+    assert!(report.contains("| `[]/a` | unsigned | 4 | ≤ 3 | 100.0% | 1, 3, 2 |\n"), "{report}");
+    assert!(report.contains("| `[]/b` | string | 2 | length ≤ 3 | 50.0% | \"x<y\", \"z\" |\n"), "{report}");
+  }
+
+  #[test]
+  fn html() {
+    let report = report_of(JSON, Format::Html);
+    assert!(report.starts_with("<!DOCTYPE html>"), "{report}");
+    assert!(report.contains("<summary><code>b</code><span class=\"note\">present in 50.0%</span></summary>"), "{report}");
+    assert!(report.contains("<td><code>&quot;x&lt;y&quot;, &quot;z&quot;</code></td>"), "{report}");
+  }
+
+  #[test]
+  fn optional_containers() {
+    let json = r#"[{"a": {"b": 1}, "c": [2]}, {"c": []}]"#;
+    let report = report_of(json, Format::Markdown);
+    assert!(report.contains("  - `a` present in 50.0% (1 values in 1 paths)\n"), "{report}");
+    assert!(report.contains("  - `c` present in 100.0% (1 values in 1 paths)\n"), "{report}");
+    let report = report_of(json, Format::Html);
+    assert!(report.contains("<summary><code>a</code><span class=\"note\">present in 50.0%</span>"), "{report}");
+  }
+
+  #[test]
+  fn errors() {
+    let report = report_of(r#"[{"a": 1}, }, {"a": 2}]"#, Format::Markdown);
+    assert!(report.contains("- **Parse errors**: 1\n"), "{report}");
+    assert!(report.contains("\n## Errors\n\n- error at 1:12: "), "{report}");
+  }
+
+  #[test]
+  fn format() {
+    assert_eq!("md".parse::<Format>(), Ok(Format::Markdown));
+    assert_eq!("html".parse::<Format>(), Ok(Format::Html));
+    assert!("pdf".parse::<Format>().is_err());
+  }
+}
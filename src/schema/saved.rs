@@ -0,0 +1,258 @@
+/*!
+The schema as json, so that it can be saved and read back later, eg to generate data that looks like the input.
+
+Each node of the trie becomes an object with its kinds, its container statistics, and its children
+in the order they were first seen. The nesting follows the paths, so reading it back doesn't need to
+parse paths.
+
+Array lengths are only known when tuples are enabled, because that's the only time they're collected.
+Without them, the length of an array can still be estimated from how many elements it had on average.
+*/
+
+use serde_json::json;
+use serde_json::Value;
+
+use super::Leaf;
+use super::NumberType;
+use super::SchemaCollector;
+use super::SchemaType;
+use super::Step;
+use super::trie::NodeId;
+use super::trie::PathTrie;
+
+/// Saved schemas with a different version can't be read.
+pub const VERSION : u64 = 1;
+
+/// Display the schema as json.
+pub struct Saved<'a>(&'a SchemaCollector);
+
+impl<'a> From<&'a SchemaCollector> for Saved<'a> {
+  fn from(collector : &'a SchemaCollector) -> Self {
+    Self(collector)
+  }
+}
+
+fn kind_json(leaf : &Leaf) -> Value {
+  let mut kind = match &leaf.aggregate {
+    SchemaType::String(max_length) => json!({"kind": "string", "max_length": max_length}),
+    SchemaType::Number(NumberType::Unsigned(max)) => json!({"kind": "unsigned", "max": max}),
+    SchemaType::Number(NumberType::Signed(min, max)) => json!({"kind": "signed", "min": min, "max": max}),
+    // NaN and infinities become null
+    SchemaType::Number(NumberType::Float(min, max)) => json!({"kind": "float", "min": min, "max": max}),
+    SchemaType::Boolean => json!({"kind": "boolean"}),
+    SchemaType::Null => json!({"kind": "null"}),
+    SchemaType::Unknown(value) => json!({"kind": "unknown", "value": value}),
+  };
+  kind["count"] = leaf.count.into();
+  if let Some(values) = leaf.enum_values() {
+    kind["enum"] = values.into_iter().map(|(value, count)| json!([value, count])).collect();
+  }
+  kind
+}
+
+fn node_json(paths : &PathTrie, id : NodeId) -> Value {
+  let node = &paths[id];
+  let mut json = serde_json::Map::new();
+  match &node.step {
+    Some(Step::Key(key)) => { json.insert("key".into(), key.as_str().into()); }
+    Some(Step::Index) => { json.insert("index".into(), true.into()); }
+    Some(Step::Position(i)) => { json.insert("position".into(), (*i).into()); }
+    None => (),
+  }
+  if !node.kinds.is_empty() {
+    json.insert("kinds".into(), node.kinds.iter().map(kind_json).collect());
+  }
+  if let Some(container) = &node.container {
+    let mut container_json = json!({"count": container.count});
+    if let Some((min, max)) = container.lengths {
+      container_json["lengths"] = json!([min, max]);
+    }
+    json.insert("container".into(), container_json);
+  }
+  if !node.children.is_empty() {
+    json.insert("children".into(), node.children.iter().map(|child| node_json(paths, *child)).collect());
+  }
+  Value::Object(json)
+}
+
+impl std::fmt::Display for Saved<'_> {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let mut json = json!({
+      "version": VERSION,
+      "schema": node_json(&self.0.paths, super::trie::ROOT),
+    });
+    // for people reading it, the counts are of the sample
+    if let Some(sampled) = self.0.sampled() {
+      json["sampled"] = sampled.to_string().into();
+    }
+    writeln!(f, "{}", serde_json::to_string_pretty(&json).map_err(|_| std::fmt::Error)?)
+  }
+}
+
+/// One kind of leaf value of a saved schema.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SavedKind {
+  pub kind : SchemaType,
+  pub count : u64,
+  /// If the values are an enum, each value with its count.
+  pub values : Option<Vec<(String, u64)>>,
+}
+
+/// One path of a saved schema, with the paths underneath it.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct SavedNode {
+  /// None for the root
+  pub step : Option<Step>,
+  pub kinds : Vec<SavedKind>,
+  /// How often this path was an array or object, and the min and max length of the arrays, if known.
+  pub container : Option<(u64, Option<(u64, u64)>)>,
+  pub children : Vec<SavedNode>,
+}
+
+type ReadResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn u64_of(json : &Value, field : &str) -> ReadResult<u64> {
+  json[field].as_u64().ok_or_else(|| format!("expected a number for '{field}' in {json}").into())
+}
+
+fn i64_of(json : &Value, field : &str) -> ReadResult<i64> {
+  json[field].as_i64().ok_or_else(|| format!("expected a number for '{field}' in {json}").into())
+}
+
+// null is how NaN and the infinities are saved
+fn f64_of(json : &Value, field : &str) -> f64 {
+  json[field].as_f64().unwrap_or(f64::NAN)
+}
+
+impl TryFrom<&Value> for SavedKind {
+  type Error = Box<dyn std::error::Error>;
+
+  fn try_from(json : &Value) -> ReadResult<Self> {
+    let kind = match json["kind"].as_str() {
+      Some("string") => SchemaType::String(u64_of(json, "max_length")?),
+      Some("unsigned") => SchemaType::Number(NumberType::Unsigned(u64_of(json, "max")?)),
+      Some("signed") => SchemaType::Number(NumberType::Signed(i64_of(json, "min")?, i64_of(json, "max")?)),
+      Some("float") => SchemaType::Number(NumberType::Float(f64_of(json, "min"), f64_of(json, "max"))),
+      Some("boolean") => SchemaType::Boolean,
+      Some("null") => SchemaType::Null,
+      Some("unknown") => SchemaType::Unknown(json["value"].as_str().unwrap_or_default().into()),
+      _ => return Err(format!("unknown kind in {json}").into()),
+    };
+    let values = match json.get("enum") {
+      Some(Value::Array(values)) => Some(values.iter().map(|value| match (value[0].as_str(), value[1].as_u64()) {
+        (Some(value), Some(count)) => Ok((value.to_string(), count)),
+        _ => Err(format!("expected [value, count] in enum, not {value}")),
+      }).collect::<Result<Vec<(String,u64)>,String>>()?),
+      _ => None,
+    };
+    Ok(Self{kind, count: u64_of(json, "count")?, values})
+  }
+}
+
+impl TryFrom<&Value> for SavedNode {
+  type Error = Box<dyn std::error::Error>;
+
+  fn try_from(json : &Value) -> ReadResult<Self> {
+    let step = if let Some(key) = json["key"].as_str() {
+      Some(Step::Key(key.into()))
+    } else if let Some(i) = json["position"].as_u64() {
+      Some(Step::Position(i))
+    } else if json["index"].as_bool() == Some(true) {
+      Some(Step::Index)
+    } else {
+      None
+    };
+    let list = |field : &str| json[field].as_array().map(Vec::as_slice).unwrap_or_default();
+    let container = match &json["container"] {
+      Value::Null => None,
+      container => {
+        let lengths = match (container["lengths"][0].as_u64(), container["lengths"][1].as_u64()) {
+          (Some(min), Some(max)) => Some((min, max)),
+          _ => None,
+        };
+        Some((u64_of(container, "count")?, lengths))
+      }
+    };
+    Ok(Self {
+      step,
+      kinds: list("kinds").iter().map(SavedKind::try_from).collect::<ReadResult<Vec<SavedKind>>>()?,
+      container,
+      children: list("children").iter().map(SavedNode::try_from).collect::<ReadResult<Vec<SavedNode>>>()?,
+    })
+  }
+}
+
+impl SavedNode {
+  /// How often this path occurred, as a leaf of any kind or as a container.
+  pub fn count(&self) -> u64 {
+    self.kinds.iter().map(|kind| kind.count).sum::<u64>() + self.container.map_or(0, |(count, _)| count)
+  }
+}
+
+/// The schema as if it had been saved and read back.
+impl From<&SchemaCollector> for SavedNode {
+  fn from(collector : &SchemaCollector) -> Self {
+    SavedNode::try_from(&node_json(&collector.paths, super::trie::ROOT)).expect("a saved schema can be read back")
+  }
+}
+
+/// Read the root of a schema saved with `--json`.
+pub fn read(rd : &mut dyn std::io::Read) -> ReadResult<SavedNode> {
+  let json : Value = serde_json::from_reader(rd)?;
+  match json["version"].as_u64() {
+    Some(VERSION) => SavedNode::try_from(&json["schema"]),
+    Some(version) => Err(format!("can't read version {version} of saved schemas, only version {VERSION}").into()),
+    None => Err("not a saved schema, which has a version".into()),
+  }
+}
+
+#[cfg(test)]
+mod test_saved {
+  use crate::parser::JsonEventParser;
+  use super::super::SchemaCollector;
+  use super::super::SchemaType;
+  use super::super::Step;
+
+  fn saved_of(json : &'static str, collector : SchemaCollector) -> super::SavedNode {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, collector).unwrap();
+    let saved = super::Saved::from(&collector).to_string();
+    super::read(&mut saved.as_bytes()).unwrap()
+  }
+
+  #[test]
+  fn round_trip() {
+    let root = saved_of(r#"[{"a": "x", "b": -2.5}, {"a": "y"}, {"a": "x", "b": [1, 2]}]"#, SchemaCollector::new());
+    assert_eq!(root.step, None);
+    assert_eq!(root.container, Some((1, None)));
+
+    let record = &root.children[0];
+    assert_eq!(record.step, Some(Step::Index));
+    assert_eq!(record.count(), 3);
+
+    let a = &record.children[0];
+    assert_eq!(a.step, Some(Step::Key("a".into())));
+    assert_eq!(a.kinds[0].kind, SchemaType::String(1));
+    assert_eq!(a.kinds[0].values, Some(vec![("x".into(), 2), ("y".into(), 1)]));
+
+    let b = &record.children[1];
+    assert_eq!(b.count(), 2);
+    assert_eq!(b.container, Some((1, None)));
+    assert_eq!(b.children[0].kinds[0].count, 2);
+  }
+
+  #[test]
+  fn tuple_lengths() {
+    let root = saved_of(r#"[[1, "a"], [2, "b"], [3, "c"]]"#, SchemaCollector::new().with_tuple_limit(4));
+    let tuple = &root.children[0];
+    assert_eq!(tuple.container, Some((3, Some((2, 2)))));
+    let steps = tuple.children.iter().map(|child| child.step.clone().unwrap()).collect::<Vec<Step>>();
+    assert_eq!(steps, vec![Step::Position(0), Step::Position(1)]);
+  }
+
+  #[test]
+  fn wrong_version() {
+    let err = super::read(&mut r#"{"version": 99, "schema": {}}"#.as_bytes()).unwrap_err();
+    assert_eq!(err.to_string(), "can't read version 99 of saved schemas, only version 1");
+  }
+}
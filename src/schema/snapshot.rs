@@ -0,0 +1,203 @@
+/*!
+Writes the partial schema while the input is still being read, so that a long run over a huge file
+shows something before it finishes, and an interrupted run isn't wasted.
+
+A snapshot is written every so many seconds or leaves, and when SIGUSR1 arrives. SIGINT stops
+collecting, and then the schema so far is displayed as normal. A second SIGINT exits immediately,
+in case the input is stuck.
+*/
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::Output;
+use super::SchemaCollector;
+
+/// Checking the clock for every event would be noticeable, so only check it every this many events.
+const CLOCK_EVENTS : u64 = 1024;
+
+/// When and where to write snapshots of the partial schema.
+#[derive(Debug)]
+pub struct Snapshots {
+  output : Output,
+  // None is stderr
+  file : Option<std::path::PathBuf>,
+  every_leaves : Option<u64>,
+  every : Option<Duration>,
+  // set by the signal handlers
+  requested : Arc<AtomicBool>,
+  interrupted : Arc<AtomicBool>,
+  started : Instant,
+  last : Instant,
+  events : u64,
+  leaves : u64,
+  last_leaves : u64,
+}
+
+impl Snapshots {
+  /// Snapshots are displayed the same way as the final schema, and go to stderr by default.
+  pub fn new(output : Output) -> Self {
+    let now = Instant::now();
+    Self {
+      output,
+      file: None,
+      every_leaves: None,
+      every: None,
+      requested: Arc::new(AtomicBool::new(false)),
+      interrupted: Arc::new(AtomicBool::new(false)),
+      started: now,
+      last: now,
+      events: 0,
+      leaves: 0,
+      last_leaves: 0,
+    }
+  }
+
+  /// Write a snapshot every `leaves` leaf values.
+  pub fn every_leaves(mut self, leaves : u64) -> Self {
+    self.every_leaves = Some(leaves);
+    self
+  }
+
+  /// Write a snapshot every `duration`.
+  pub fn every(mut self, duration : Duration) -> Self {
+    self.every = Some(duration);
+    self
+  }
+
+  /// Replace the contents of `file` with each snapshot, rather than writing to stderr.
+  pub fn to_file(mut self, file : &std::path::Path) -> Self {
+    self.file = Some(file.into());
+    self
+  }
+
+  /// Snapshot on SIGUSR1 (where there is such a thing), and stop on SIGINT.
+  pub fn with_signals(self) -> std::io::Result<Self> {
+    use signal_hook::consts::SIGINT;
+    // order matters: the shutdown only happens if interrupted is already set, ie on the second SIGINT.
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 130, self.interrupted.clone())?;
+    signal_hook::flag::register(SIGINT, self.interrupted.clone())?;
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, self.requested.clone())?;
+    Ok(self)
+  }
+
+  /// Whether SIGINT has arrived.
+  pub fn interrupted(&self) -> bool {
+    self.interrupted.load(Ordering::Relaxed)
+  }
+
+  /// Count an event, and return whether a snapshot is due.
+  pub(super) fn tick(&mut self, leaf : bool) -> bool {
+    self.events += 1;
+    if leaf { self.leaves += 1 }
+
+    // load first, so the usual case doesn't need a write
+    (self.requested.load(Ordering::Relaxed) && self.requested.swap(false, Ordering::Relaxed))
+    || matches!(self.every_leaves, Some(n) if self.leaves - self.last_leaves >= n)
+    || (self.events.is_multiple_of(CLOCK_EVENTS) && matches!(self.every, Some(every) if self.last.elapsed() >= every))
+  }
+
+  /// Write the snapshot of collector, and start counting towards the next one.
+  pub(super) fn write(&mut self, collector : &SchemaCollector) -> std::io::Result<()> {
+    self.write_with(collector.bytes_processed(), |wr, output| super::write_output(wr, collector, output))
+  }
+
+  /// Write a snapshot of whatever write_schema writes in output, eg the schema of each group.
+  pub(super) fn write_with(&mut self, bytes : Option<u64>, write_schema : impl FnOnce(&mut dyn std::io::Write, Output) -> std::io::Result<()>)
+  -> std::io::Result<()>
+  {
+    use std::io::Write;
+    let mut buf = Vec::new();
+    // reports already have a summary with these numbers, and a header would make json unreadable
+    if !matches!(self.output, Output::Report(_) | Output::Json) {
+      let bytes = bytes
+        .map(|bytes| bytes.to_string())
+        .unwrap_or_else(|| "unknown".into());
+      writeln!(buf, "# partial schema after {} leaves, {bytes} bytes, {:.1}s", self.leaves, self.started.elapsed().as_secs_f64())?;
+    }
+    write_schema(&mut buf, self.output)?;
+
+    match &self.file {
+      // rename, so anything watching the file never sees half a snapshot
+      Some(file) => {
+        let mut tmp = file.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, &buf)?;
+        std::fs::rename(&tmp, file)?;
+      }
+      None => {
+        let mut stderr = std::io::stderr().lock();
+        stderr.write_all(&buf)?;
+        writeln!(stderr)?;
+      }
+    }
+
+    self.last = Instant::now();
+    self.last_leaves = self.leaves;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test_snapshot {
+  use super::Snapshots;
+  use super::super::Output;
+  use super::super::SchemaCollector;
+  use crate::parser::JsonEventParser;
+
+  const JSON : &str = r#"[{"a": 1}, {"a": 2}, {"a": 3}, {"b": "x"}, {"a": 4}]"#;
+
+  fn snapshot_file(name : &str) -> std::path::PathBuf {
+    let file = std::env::temp_dir().join(format!("jch-test-snapshot-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+    file
+  }
+
+  #[test]
+  fn every_leaves() {
+    let file = snapshot_file("every_leaves");
+    let snapshots = Snapshots::new(Output::Flat).every_leaves(2).to_file(&file);
+    let mut jevstream = JsonEventParser::new(Box::new(JSON.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, SchemaCollector::new().with_snapshots(snapshots)).unwrap();
+    assert_eq!(collector.total_leaves(), 5);
+
+    // the last snapshot is after the 4th leaf, which is "x"
+    let snapshot = std::fs::read_to_string(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    assert!(snapshot.starts_with("# partial schema after 4 leaves, "), "{snapshot}");
+    assert!(snapshot.contains(" []/b\n"), "{snapshot}");
+    assert!(snapshot.contains("Number(Unsigned(3)):3 "), "{snapshot}");
+  }
+
+  #[test]
+  fn requested() {
+    let file = snapshot_file("requested");
+    let snapshots = Snapshots::new(Output::Tree).to_file(&file);
+    snapshots.requested.store(true, std::sync::atomic::Ordering::Relaxed);
+    let mut jevstream = JsonEventParser::new(Box::new(JSON.as_bytes()));
+    super::super::collect(&mut jevstream, SchemaCollector::new().with_snapshots(snapshots)).unwrap();
+
+    // written on the first event, when there was nothing yet but the [ of the array
+    let snapshot = std::fs::read_to_string(&file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    // the time depends on how fast this runs
+    let (header, tree) = snapshot.split_once('\n').unwrap();
+    let seconds = header.strip_prefix("# partial schema after 0 leaves, 1 bytes, ").and_then(|rest| rest.strip_suffix('s'));
+    assert!(seconds.is_some_and(|seconds| seconds.parse::<f64>().is_ok()), "{header}");
+    assert_eq!(tree, ".  \n");
+  }
+
+  #[test]
+  fn interrupted() {
+    let snapshots = Snapshots::new(Output::Flat);
+    snapshots.interrupted.store(true, std::sync::atomic::Ordering::Relaxed);
+    let mut jevstream = JsonEventParser::new(Box::new(JSON.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, SchemaCollector::new().with_snapshots(snapshots)).unwrap();
+    assert!(collector.interrupted());
+    assert_eq!(collector.total_leaves(), 0);
+  }
+}
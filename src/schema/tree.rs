@@ -0,0 +1,142 @@
+/*!
+Displays the schema as an indented tree, like `tree(1)`, instead of one line per path.
+
+Each child is indented under its parent step, so long shared prefixes like `[]/payload/release/`
+are only shown once. Leaves show their kinds and counts, containers show the aggregate count of
+all leaf values underneath them.
+*/
+
+use std::collections::BTreeMap;
+
+use super::LeafKinds;
+use super::SchemaCollector;
+use super::Step;
+use super::trie::NodeId;
+use super::trie::PathTrie;
+
+/// One node of the tree. A path can be both a leaf and a container, for
+/// example when a value is sometimes null and sometimes an object.
+#[derive(Debug,Default)]
+pub(super) struct Node<'a> {
+  // of the node in the trie, for containers as well as leaves
+  pub(super) id : NodeId,
+  pub(super) leaf : Option<(NodeId, &'a LeafKinds)>,
+  // sorted, unlike the children in the trie
+  pub(super) children : BTreeMap<&'a Step, Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+  // Containers without any leaves underneath, like empty arrays, are left out.
+  fn of(paths : &'a PathTrie, id : NodeId) -> Self {
+    let node = &paths[id];
+    let leaf = (!node.kinds.is_empty()).then_some((id, &node.kinds));
+    let children = node.children
+      .iter()
+      .map(|child| (paths[*child].step.as_ref().expect("only the root has no step"), Self::of(paths, *child)))
+      .filter(|(_, child)| child.leaf.is_some() || !child.children.is_empty())
+      .collect();
+    Self{id, leaf, children}
+  }
+
+  /// The root node, ie the whole schema.
+  pub(super) fn root(collector : &'a SchemaCollector) -> Self {
+    Self::of(&collector.paths, super::trie::ROOT)
+  }
+
+  /// (number of leaf paths, number of leaf values) in this subtree, including this node.
+  pub(super) fn aggregate(&self) -> (u64, u64) {
+    let own = self.leaf
+      .map(|(_, kinds)| (1, kinds.count()))
+      .unwrap_or((0,0));
+
+    self.children
+      .values()
+      .map(Node::aggregate)
+      .fold(own, |(paths, values), (cpaths, cvalues)| (paths + cpaths, values + cvalues))
+  }
+
+  // The label for this node, ie whatever comes after the name.
+  fn label(&self) -> String {
+    let kinds = self.leaf.map(|(_, kinds)| super::kinds_fmt(kinds));
+    if self.children.is_empty() {
+      kinds.unwrap_or_default()
+    } else {
+      let (paths, values) = self.aggregate();
+      let aggregate = format!("({values} values in {paths} paths)");
+      match kinds {
+        Some(kinds) => format!("{kinds}  {aggregate}"),
+        None => aggregate,
+      }
+    }
+  }
+
+  fn write_children(&self, f : &mut std::fmt::Formatter<'_>, prefix : &str) -> Result<(), std::fmt::Error> {
+    let mut children = self.children.iter().peekable();
+    while let Some((step, child)) = children.next() {
+      let (connector, indent) = if children.peek().is_some() { ("├── ", "│   ") } else { ("└── ", "    ") };
+      writeln!(f, "{prefix}{connector}{step}  {}", child.label())?;
+      child.write_children(f, &format!("{prefix}{indent}"))?;
+    }
+    Ok(())
+  }
+}
+
+/// Borrows the collected schema, and Display renders it as a tree.
+#[derive(Debug)]
+pub struct Tree<'a>(Node<'a>);
+
+impl<'a> From<&'a SchemaCollector> for Tree<'a> {
+  fn from(collector : &'a SchemaCollector) -> Self {
+    Self(Node::root(collector))
+  }
+}
+
+impl std::fmt::Display for Tree<'_> {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+    // same as tree(1), the root is .
+    writeln!(f, ".  {}", self.0.label())?;
+    self.0.write_children(f, "")
+  }
+}
+
+#[cfg(test)]
+mod test_tree {
+  use crate::parser::JsonEventParser;
+
+  fn tree_of(json : &'static str) -> String {
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let collector = super::super::collect(&mut jevstream, super::super::SchemaCollector::new()).unwrap();
+    collector.tree().to_string()
+  }
+
+  #[test]
+  fn nested() {
+    let tree = tree_of(r#"[{"a": {"b": 1, "c": "xy"}, "d": true}, {"a": {"b": 2}, "d": false}]"#);
+    let expected = "\
+.  (5 values in 3 paths)
+└── []  (5 values in 3 paths)
+    ├── a  (3 values in 2 paths)
+    │   ├── b  Number(Unsigned(2)):2
+    │   └── c  String(2):1
+    └── d  Boolean:2
+";
+    assert_eq!(tree, expected);
+  }
+
+  #[test]
+  fn scalar() {
+    assert_eq!(tree_of("17"), ".  Number(Unsigned(17)):1\n");
+  }
+
+  #[test]
+  fn leaf_and_container() {
+    let tree = tree_of(r#"[{"a": null}, {"a": {"b": "z"}}]"#);
+    let expected = "\
+.  (2 values in 2 paths)
+└── []  (2 values in 2 paths)
+    └── a  Null:1  (2 values in 2 paths)
+        └── b  String(1):1
+";
+    assert_eq!(tree, expected);
+  }
+}
@@ -0,0 +1,231 @@
+/*!
+Storage for the schema, as a trie of paths.
+
+Each node is one schema path, and holds the leaves and container statistics for that path. Nodes
+live in a Vec and refer to each other by index, so finding the node for an incoming path is one
+lookup per step, starting from the root, without building or hashing a whole path.
+
+Merging nodes, which happens when arrays turn out not to be tuples, leaves the merged node
+unreachable from the root rather than shuffling the Vec. So everything that reads the trie starts
+from the root.
+*/
+
+use rustc_hash::FxHashMap;
+
+use super::Container;
+use super::LeafKinds;
+use super::SchemaPath;
+use super::Step;
+
+/// Index of a node in the trie.
+pub(super) type NodeId = usize;
+
+/// The root is the path of the top-level value, ie no steps.
+pub(super) const ROOT : NodeId = 0;
+
+#[derive(Debug,Clone,Default)]
+pub(super) struct PathNode {
+  // None for the root
+  pub(super) step : Option<Step>,
+  pub(super) parent : Option<NodeId>,
+  // number of steps from the root
+  pub(super) depth : usize,
+  // in the order they were first seen
+  pub(super) children : Vec<NodeId>,
+  // for finding children by step
+  keys : FxHashMap<String, NodeId>,
+  index : Option<NodeId>,
+  // only ever a few of these, because they're limited by tuple_limit
+  positions : Vec<(u64, NodeId)>,
+  pub(super) kinds : LeafKinds,
+  // Some if this path was ever an array or object
+  pub(super) container : Option<Container>,
+}
+
+impl PathNode {
+  fn child(&self, step : &Step) -> Option<NodeId> {
+    match step {
+      Step::Key(key) => self.keys.get(key.as_str()).copied(),
+      Step::Index => self.index,
+      Step::Position(i) => self.position(*i),
+    }
+  }
+
+  fn position(&self, i : u64) -> Option<NodeId> {
+    self.positions.iter().find(|(position, _)| *position == i).map(|(_, id)| *id)
+  }
+}
+
+#[derive(Debug,Clone)]
+pub(super) struct PathTrie {
+  nodes : Vec<PathNode>,
+  // node for each PathId, which is only valid without tuples, because then there are no positions
+  by_id : crate::pathdict::ById<NodeId>,
+}
+
+impl Default for PathTrie {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl std::ops::Index<NodeId> for PathTrie {
+  type Output = PathNode;
+
+  fn index(&self, id : NodeId) -> &PathNode {
+    &self.nodes[id]
+  }
+}
+
+impl std::ops::IndexMut<NodeId> for PathTrie {
+  fn index_mut(&mut self, id : NodeId) -> &mut PathNode {
+    &mut self.nodes[id]
+  }
+}
+
+impl PathTrie {
+  pub(super) fn new() -> Self {
+    Self{nodes: vec![PathNode::default()], by_id: Default::default()}
+  }
+
+  // make child the child of parent at step
+  fn attach(&mut self, parent : NodeId, step : Step, child : NodeId) {
+    let parent_node = &mut self.nodes[parent];
+    match &step {
+      Step::Key(key) => { parent_node.keys.insert(key.clone(), child); }
+      Step::Index => parent_node.index = Some(child),
+      Step::Position(i) => parent_node.positions.push((*i, child)),
+    }
+    parent_node.children.push(child);
+    let depth = parent_node.depth + 1;
+
+    let child_node = &mut self.nodes[child];
+    child_node.step = Some(step);
+    child_node.parent = Some(parent);
+    child_node.depth = depth;
+  }
+
+  fn add_child(&mut self, parent : NodeId, step : Step) -> NodeId {
+    let child = self.nodes.len();
+    self.nodes.push(PathNode::default());
+    self.attach(parent, step, child);
+    child
+  }
+
+  /// The node for send_path, which is created if necessary. Indexes below
+  /// tuple_limit are kept as positions, in case their array turns out to be a tuple.
+  ///
+  /// Without tuples each PathId is exactly one node, so most of the time that's a lookup in by_id.
+  pub(super) fn find_or_insert(&mut self, send_path : &crate::sendpath::SendPath, tuple_limit : usize) -> NodeId {
+    if tuple_limit > 0 { return self.walk(send_path, tuple_limit) }
+    if let Some(id) = self.by_id.get(send_path.id()) { return id }
+    let id = self.walk(send_path, tuple_limit);
+    self.by_id.insert(send_path.id(), id);
+    id
+  }
+
+  // find_or_insert one step at a time
+  fn walk(&mut self, send_path : &crate::sendpath::SendPath, tuple_limit : usize) -> NodeId {
+    send_path.steps().iter().fold(ROOT, |id, step| match step {
+      crate::jsonpath::Step::Key(key) => self.key_child(id, key),
+      crate::jsonpath::Step::Index(i) => self.index_child(id, *i, tuple_limit),
+    })
+  }
+
+  /// The child of id at key, which is created if necessary.
+  pub(super) fn key_child(&mut self, id : NodeId, key : &str) -> NodeId {
+    match self.nodes[id].keys.get(key) {
+      Some(child) => *child,
+      None => self.add_child(id, Step::Key(key.into())),
+    }
+  }
+
+  /// The child of id at index i, which is created if necessary. Indexes below tuple_limit are positions.
+  pub(super) fn index_child(&mut self, id : NodeId, i : u64, tuple_limit : usize) -> NodeId {
+    let node = &self.nodes[id];
+    let child = if (i as usize) < tuple_limit { node.position(i) } else { node.index };
+    match child {
+      Some(child) => child,
+      None if (i as usize) < tuple_limit => self.add_child(id, Step::Position(i)),
+      None => self.add_child(id, Step::Index),
+    }
+  }
+
+  /// The node for an already-collapsed path, if there is one.
+  pub(super) fn find(&self, steps : &[Step]) -> Option<NodeId> {
+    steps.iter().try_fold(ROOT, |id, step| self.nodes[id].child(step))
+  }
+
+  /// The steps from the root to id.
+  pub(super) fn path(&self, id : NodeId) -> SchemaPath {
+    let mut steps = Vec::with_capacity(self.nodes[id].depth);
+    let mut current = id;
+    while let PathNode{step: Some(step), parent: Some(parent), ..} = &self.nodes[current] {
+      steps.push(step.clone());
+      current = *parent;
+    }
+    steps.reverse();
+    SchemaPath(steps)
+  }
+
+  /// All nodes reachable from the root, depth first, in the order they were first seen.
+  pub(super) fn ids(&self) -> Vec<NodeId> {
+    let mut ids = Vec::with_capacity(self.nodes.len());
+    let mut stack = vec![ROOT];
+    while let Some(id) = stack.pop() {
+      ids.push(id);
+      stack.extend(self.nodes[id].children.iter().rev());
+    }
+    ids
+  }
+
+  /// Fold the node at from, and everything underneath it, into the node at into.
+  ///
+  /// from is not detached from its parent, that's up to the caller.
+  pub(super) fn merge(&mut self, into : NodeId, from : NodeId, enum_limit : usize) {
+    let from_node = std::mem::take(&mut self.nodes[from]);
+    let into_node = &mut self.nodes[into];
+    into_node.kinds.merge(from_node.kinds, enum_limit);
+    match (&mut into_node.container, from_node.container) {
+      (Some(container), Some(other)) => container.merge(&other),
+      (container @ None, other) => *container = other,
+      (Some(_), None) => (),
+    }
+
+    for child in from_node.children {
+      let step = self.nodes[child].step.clone().expect("only the root has no step");
+      match self.nodes[into].child(&step) {
+        Some(existing) => self.merge(existing, child, enum_limit),
+        None => self.attach(into, step, child),
+      }
+    }
+  }
+
+  /// Replace the positions under id with a single index, ie the array at id is not a tuple.
+  pub(super) fn collapse_positions(&mut self, id : NodeId, enum_limit : usize) {
+    let positions = std::mem::take(&mut self.nodes[id].positions);
+    for (_, position) in positions {
+      match self.nodes[id].index {
+        Some(index) => {
+          self.nodes[id].children.retain(|child| *child != position);
+          self.merge(index, position, enum_limit);
+        }
+        // reuse the first position as the index, so it keeps its place in children
+        None => {
+          self.nodes[position].step = Some(Step::Index);
+          self.nodes[id].index = Some(position);
+        }
+      }
+    }
+  }
+
+  /// Whether id has children at positions.
+  pub(super) fn has_positions(&self, id : NodeId) -> bool {
+    !self.nodes[id].positions.is_empty()
+  }
+
+  /// Every node, including unreachable ones.
+  pub(super) fn nodes_mut(&mut self) -> impl Iterator<Item = &mut PathNode> {
+    self.nodes.iter_mut()
+  }
+}
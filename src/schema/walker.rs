@@ -0,0 +1,178 @@
+/*!
+Collects a schema straight from the parser, for when the parser and the collector are on the same thread.
+
+This is a Visitor for the same traversal that Handler uses, but instead of building a path for each
+event and sending it to the collector, it keeps the node of the trie for the current path, and the
+indexes of that path. So each key is one lookup in the node of its object, each element is one
+lookup in the node of its array, and the text of a value is only copied for the samples and enums
+of its leaf. Nothing is allocated per event, except for paths that are new.
+
+The events are the same as for Handler, so the schema, errors and snapshots are too.
+*/
+
+use crate::handler::Container;
+use crate::handler::Sample;
+use crate::handler::Visitor;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::sendpath::Indexes;
+use crate::sendpath::SendPath;
+
+use super::trie;
+use super::trie::NodeId;
+use super::Incoming;
+use super::Sampler;
+use super::SchemaCollector;
+use super::Step;
+
+type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+// Where a value is, relative to a node of the trie. The child is only looked up once its value
+// turns up, so that an error doesn't add a node to the trie.
+#[derive(Debug,Clone,Copy)]
+enum At {
+  // the node itself
+  Here(NodeId),
+  // its child at the most recent key
+  Key(NodeId),
+  // its child at the most recent index
+  Element(NodeId),
+}
+
+impl At {
+  // the node of an array or object, once it has started
+  fn started(&self) -> NodeId {
+    match self {
+      At::Here(id) => *id,
+      At::Key(_) | At::Element(_) => unreachable!("containers are always Here once started"),
+    }
+  }
+}
+
+struct Walker<'c> {
+  collector : &'c mut SchemaCollector,
+  sampler : &'c mut Sampler,
+  positions : Option<crate::parser::PositionTracker>,
+  // of the current path
+  indexes : Indexes,
+  // the most recent key, because the parser's copy is gone after the next event
+  key : String,
+}
+
+impl Walker<'_> {
+  fn node(&mut self, at : At) -> NodeId {
+    match at {
+      At::Here(id) => id,
+      At::Key(id) => self.collector.paths.key_child(id, &self.key),
+      At::Element(id) => {
+        let index = *self.indexes.as_slice().last().expect("an index for each element");
+        self.collector.paths.index_child(id, index, self.collector.tuple_limit)
+      }
+    }
+  }
+}
+
+impl Visitor for Walker<'_> {
+  type At = At;
+
+  fn leaf(&mut self, at : At, value : JsonEvent<&str>) -> Result {
+    let id = self.node(at);
+    self.collector.check_interrupted()?;
+    let kind = super::kind_of(&value);
+    let position = self.positions.as_ref().map(|tracker| tracker.get());
+    self.collector.collect_leaf(id, &Incoming{kind: &kind, value, indexes: self.indexes.as_slice(), position});
+    Ok(self.collector.tick(true)?)
+  }
+
+  fn start(&mut self, at : At, _depth : usize, _container : Container) -> Result<At> {
+    let id = self.node(at);
+    self.collector.check_interrupted()?;
+    self.collector.collect_container(id);
+    self.collector.tick(false)?;
+    Ok(At::Here(id))
+  }
+
+  fn element(&mut self, array : &At, index : u64) -> At {
+    self.indexes.push(index);
+    At::Element(array.started())
+  }
+
+  fn end_element(&mut self) {
+    self.indexes.pop();
+  }
+
+  fn key(&mut self, object : &At, key : &str) -> At {
+    self.key.clear();
+    self.key.push_str(key);
+    At::Key(object.started())
+  }
+
+  // only the records, ie the elements of the top-level array, are sampled
+  fn sample(&mut self, array : &At, _index : u64) -> Sample {
+    if array.started() == trie::ROOT { self.sampler.sample() } else { Sample::Take }
+  }
+
+  fn error(&mut self, at : &At, message : String) -> Result {
+    self.collector.check_interrupted()?;
+    let (At::Here(id) | At::Key(id) | At::Element(id)) = *at;
+    let mut indexes = self.indexes.as_slice().iter();
+    let mut steps = self.collector.paths.path(id).0.into_iter().map(|step| match step {
+      Step::Key(key) => crate::jsonpath::Step::Key(key),
+      Step::Index | Step::Position(_) => crate::jsonpath::Step::Index(*indexes.next().expect("an index for each array")),
+    }).collect::<Vec<crate::jsonpath::Step>>();
+    // an element of the array at id, whose index is the last one
+    if let Some(index) = indexes.next() { steps.push(crate::jsonpath::Step::Index(*index)) }
+    if let At::Key(_) = at { steps.push(crate::jsonpath::Step::Key(self.key.clone())) }
+    self.collector.collect_error(SendPath::from(&steps[..]), message)?;
+    Ok(self.collector.tick(false)?)
+  }
+
+  fn finished(&mut self) -> Result {
+    self.collector.check_interrupted()?;
+    Ok(self.collector.tick(false)?)
+  }
+}
+
+/// Collect the schema of everything jevs sends into collector, sampling the records with sampler.
+pub(super) fn collect(jevs : &mut dyn JsonEventSource<String>, collector : &mut SchemaCollector, sampler : &mut Sampler) -> Result {
+  let positions = jevs.position_tracker();
+  let mut walker = Walker{collector, sampler, positions, indexes: Indexes::default(), key: String::new()};
+  walker.value(jevs, At::Here(trie::ROOT), 0)
+}
+
+#[cfg(test)]
+mod test_walker {
+  use crate::handler::Handler;
+  use crate::parser::JsonEventParser;
+  use super::super::EventConverter;
+  use super::super::SchemaCollector;
+
+  // the schema and errors, from the walker and from events
+  fn both(json : &'static str, collector : fn() -> SchemaCollector) -> (String, String) {
+    let walked = super::super::collect(&mut JsonEventParser::new(Box::new(json.as_bytes())), collector()).unwrap();
+
+    let mut sent = collector();
+    let mut jevstream = JsonEventParser::new(Box::new(json.as_bytes()));
+    let _ = EventConverter::new().value(&mut jevstream, crate::jsonpath::JsonPath::new(), 0, &mut sent);
+    sent.resolve_tuples();
+
+    let errors = |collector : &SchemaCollector| collector.errors().iter().map(ToString::to_string).collect::<Vec<String>>().join("\n");
+    (format!("{walked}{}", errors(&walked)), format!("{sent}{}", errors(&sent)))
+  }
+
+  #[test]
+  fn same_as_events() {
+    let json = r#"[{"a": [1, "x", {"b": null}], "c": [[1.5, true]]}, {"a": [], "d": {"e": {"f": -1}}}, 7]"#;
+    let (walked, sent) = both(json, SchemaCollector::new);
+    assert_eq!(walked, sent);
+    let (walked, sent) = both(json, || SchemaCollector::new().with_tuple_limit(2));
+    assert_eq!(walked, sent);
+  }
+
+  #[test]
+  fn same_errors() {
+    let (walked, sent) = both(r#"[{"a": 1}, {"a": [1, 2}, {"b": 2}]"#, SchemaCollector::new);
+    assert!(walked.contains("at path '1/a/2'"), "{walked}");
+    assert_eq!(walked, sent);
+  }
+}
@@ -30,6 +30,11 @@ impl<T> AsRef<T> for NonWrap<T> {
   fn as_ref(&self) -> &T { &self.0 }
 }
 
+impl<T> From<NonWrap<Event<T>>> for Event<T> {
+  #[inline]
+  fn from(wrapped : NonWrap<Event<T>>) -> Self { wrapped.0 }
+}
+
 /// SendValue is intended to be some kind of value - ie String, Number, Bool, Null etc. But it could be anything.
 /// In the most general sense, it's the value identified by a particular Path.
 #[derive(Debug,Clone)]
@@ -4,6 +4,10 @@ channel without excessive copying.
 */
 use crate::jsonpath::JsonPath;
 use crate::jsonpath::Step;
+use crate::pathdict;
+use crate::pathdict::Interned;
+use crate::pathdict::Link;
+use crate::pathdict::PathId;
 
 mod like_jsonpath {
   // rpds needs a special constructor for this, so that we don't run into trouble
@@ -22,47 +26,282 @@ mod like_jsonpath {
 }
 
 /// A tree path optimised for sending. Which means we can't in general keep references.
+///
+/// The steps are interned in the pathdict, so this is a shared pointer to those plus the indexes,
+/// which are the only part of a path that is different for every element of an array.
 // TODO implement a reference for sending to functions and other non-channels.
-#[derive(Debug,Clone)]
-pub struct SendPath(pub Vec<Step>);
+#[derive(Clone,Default,PartialEq,Eq)]
+pub struct SendPath {
+  interned : Interned,
+  indexes : Indexes,
+}
+
+impl SendPath {
+  /// The id of this path, without its indexes.
+  pub fn id(&self) -> PathId {
+    self.interned.id()
+  }
+
+  /// The steps of this path, without its indexes.
+  pub fn interned(&self) -> &Interned {
+    &self.interned
+  }
+
+  /// The indexes of this path, in order.
+  pub fn indexes(&self) -> &[u64] {
+    self.indexes.as_slice()
+  }
+
+  /// The full steps of this path.
+  pub fn steps(&self) -> Vec<Step> {
+    let mut indexes = self.indexes().iter();
+    self.interned.links().into_iter().map(|link| match link {
+      Link::Key(key) => Step::Key(key.as_ref().into()),
+      Link::Index => Step::Index(*indexes.next().expect("an index for each Index link")),
+    }).collect()
+  }
+
+  /// The first step, or None for the root.
+  pub fn first(&self) -> Option<Step> {
+    match self.interned.first()? {
+      Link::Key(key) => Some(Step::Key(key.as_ref().into())),
+      Link::Index => self.indexes().first().copied().map(Step::Index),
+    }
+  }
+
+  /// The first index, if the path starts with one, eg the record of `[4]/payload/ref`.
+  pub fn first_index(&self) -> Option<u64> {
+    match self.interned.first()? {
+      Link::Index => self.indexes().first().copied(),
+      Link::Key(_) => None,
+    }
+  }
+
+  /// The last step, or None for the root.
+  pub fn last(&self) -> Option<Step> {
+    match self.interned.last()? {
+      Link::Key(key) => Some(Step::Key(key.as_ref().into())),
+      Link::Index => self.indexes().last().copied().map(Step::Index),
+    }
+  }
+
+  /// Whether the last steps of this path are steps, without building the steps of this path.
+  pub fn ends_with(&self, steps : &[Step]) -> bool {
+    if steps.len() > self.len() { return false }
+    let mut indexes = self.indexes().iter().rev();
+    self.interned.links_rev().zip(steps.iter().rev()).all(|(link, step)| match (link, step) {
+      (Link::Key(key), Step::Key(step)) => key.as_ref() == step.as_str(),
+      (Link::Index, Step::Index(step)) => indexes.next() == Some(step),
+      _ => false,
+    })
+  }
+
+  /// The number of steps.
+  pub fn len(&self) -> usize {
+    self.interned.depth()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+/// How many indexes a path has before they have to be allocated. Few paths are nested deeper than this.
+const INLINE_INDEXES : usize = 4;
+
+/// The indexes of a path, which are only allocated when there are more than INLINE_INDEXES.
+#[derive(Clone,Default)]
+pub struct Indexes(Storage);
+
+#[derive(Clone)]
+enum Storage {
+  Inline(usize, [u64; INLINE_INDEXES]),
+  Allocated(Vec<u64>),
+}
+
+impl Default for Storage {
+  fn default() -> Self {
+    Storage::Inline(0, [0; INLINE_INDEXES])
+  }
+}
+
+impl Indexes {
+  pub fn as_slice(&self) -> &[u64] {
+    match &self.0 {
+      Storage::Inline(len, indexes) => &indexes[..*len],
+      Storage::Allocated(indexes) => indexes,
+    }
+  }
+
+  pub fn push(&mut self, index : u64) {
+    match &mut self.0 {
+      Storage::Inline(len, indexes) if *len < INLINE_INDEXES => { indexes[*len] = index; *len += 1 }
+      Storage::Inline(_, _) => {
+        let mut allocated = self.as_slice().to_vec();
+        allocated.push(index);
+        self.0 = Storage::Allocated(allocated)
+      }
+      Storage::Allocated(indexes) => indexes.push(index),
+    }
+  }
+
+  pub fn pop(&mut self) -> Option<u64> {
+    let last = self.as_slice().last().copied();
+    self.truncate(self.as_slice().len().saturating_sub(1));
+    last
+  }
+
+  pub fn extend(&mut self, indexes : &[u64]) {
+    for index in indexes { self.push(*index) }
+  }
+
+  /// Keep the first keep indexes. Allocated ones keep their memory, for the next push.
+  pub fn truncate(&mut self, keep : usize) {
+    match &mut self.0 {
+      Storage::Inline(len, _) => *len = std::cmp::min(*len, keep),
+      Storage::Allocated(indexes) => indexes.truncate(keep),
+    }
+  }
+
+  /// Replace these with indexes, reusing the memory.
+  pub fn set(&mut self, indexes : &[u64]) {
+    self.truncate(0);
+    self.extend(indexes);
+  }
+}
+
+impl From<&[u64]> for Indexes {
+  fn from(slice : &[u64]) -> Self {
+    let mut indexes = Indexes::default();
+    indexes.extend(slice);
+    indexes
+  }
+}
+
+impl PartialEq for Indexes {
+  fn eq(&self, other : &Self) -> bool {
+    self.as_slice() == other.as_slice()
+  }
+}
+
+impl Eq for Indexes {}
+
+impl std::fmt::Debug for Indexes {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.as_slice().fmt(f)
+  }
+}
+
+fn indexes<'a>(steps : impl IntoIterator<Item = &'a Step>) -> Indexes {
+  let mut indexes = Indexes::default();
+  for step in steps {
+    if let Step::Index(i) = step { indexes.push(*i) }
+  }
+  indexes
+}
 
 impl From<&JsonPath> for SendPath {
   fn from(path_list : &JsonPath) -> Self {
-    let steps = path_list.iter().map(std::clone::Clone::clone).collect::<Vec<Step>>();
-    // steps.reverse(); for list
-    Self(steps)
+    Self{interned: pathdict::intern(path_list.iter()), indexes: indexes(path_list.iter())}
   }
 }
 
 impl From<JsonPath> for SendPath {
   fn from(path_list : JsonPath) -> Self {
-    let steps = path_list.iter().map(std::clone::Clone::clone).collect::<Vec<Step>>();
-    // steps.reverse(); for list
-    Self(steps)
+    Self::from(&path_list)
+  }
+}
+
+impl From<&[Step]> for SendPath {
+  fn from(steps : &[Step]) -> Self {
+    Self{interned: pathdict::intern(steps), indexes: indexes(steps)}
   }
 }
 
 impl From<&SendPath> for SendPath {
   fn from(sendpath : &SendPath) -> Self {
-    Self(sendpath.0.clone())
+    sendpath.clone()
+  }
+}
+
+/// Only the steps, eg `SendPath([Key("a"), Index(0)])`, rather than the interned entries they're made from.
+impl std::fmt::Debug for SendPath {
+  fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("SendPath").field(&self.steps()).finish()
   }
 }
 
 /// This produces jq-equivalent notation
 impl std::fmt::Octal for SendPath {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-    let string_parts = self.0.iter().map(|step| format!("{step:o}")).collect::<Vec<String>>();
-    let repr = string_parts.join(",");
-    write!(f,"[{repr}]")
+    write!(f, "{}", crate::pathformat::write(self.steps().iter().map(Into::into), crate::pathformat::PathFormat::Jq))
   }
 }
 
-/// The notation currently in use.
+/// The notation selected by --path-format. Except that slash, the default, has plain indexes, eg
+/// `0/a`, as it always has had here, rather than the `[0]/a` of pathformat.
 impl std::fmt::Display for SendPath {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    let string_parts = self.0.iter().map(ToString::to_string).collect::<Vec<String>>();
-    let repr = string_parts.join("/");
+    use crate::pathformat::PathFormat;
+    match PathFormat::global() {
+      PathFormat::Slash => {
+        let string_parts = self.steps().iter().map(ToString::to_string).collect::<Vec<String>>();
+        write!(f, "{}", string_parts.join("/"))
+      }
+      format => write!(f, "{}", crate::pathformat::write(self.steps().iter().map(Into::into), format)),
+    }
+  }
+}
+
+impl SendPath {
+  /// In the notation selected by --path-format, with indexes that can't be mistaken for keys in
+  /// slash, eg `[4211]/payload/release/name`.
+  pub fn written(&self) -> String {
+    crate::pathformat::write(self.steps().iter().map(Into::into), crate::pathformat::PathFormat::global())
+  }
+}
+
+#[cfg(test)]
+mod test_steps {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let steps = vec![Step::Index(7), Step::Key("payload".into()), Step::Index(2), Step::Key("sha".into())];
+    let send_path = SendPath::from(&steps[..]);
+    assert_eq!(send_path.indexes(), &[7, 2]);
+    assert_eq!(send_path.steps(), steps);
+    assert_eq!(send_path.first(), Some(Step::Index(7)));
+    assert_eq!(send_path.first_index(), Some(7));
+    assert_eq!(send_path.last(), Some(Step::Key("sha".into())));
+    assert_eq!(send_path.len(), 4);
+    assert!(send_path.ends_with(&steps[2..]));
+    assert!(send_path.ends_with(&steps));
+    assert!(!send_path.ends_with(&[Step::Index(3), Step::Key("sha".into())]));
+    assert!(!send_path.ends_with(&[Step::Key("payload".into())]));
+  }
+
+  #[test]
+  fn deep() {
+    let steps = (0..10).map(Step::Index).collect::<Vec<Step>>();
+    let send_path = SendPath::from(&steps[..]);
+    assert_eq!(send_path.indexes(), (0..10).collect::<Vec<u64>>());
+    assert_eq!(send_path.clone(), send_path);
+    let mut indexes = Indexes::from(send_path.indexes());
+    indexes.truncate(2);
+    assert_eq!(indexes.as_slice(), &[0, 1]);
+  }
+}
+
+#[cfg(test)]
+mod test_display {
+  use super::*;
 
-    write!(f,"{repr}")
+  #[test]
+  fn slash() {
+    let path = SendPath::from(&[Step::Index(0), Step::Key("a".into()), Step::Index(7)][..]);
+    assert_eq!(path.to_string(), "0/a/7");
+    assert_eq!(path.written(), "[0]/a/[7]");
+    assert_eq!(format!("{path:o}"), r#"[0,"a",7]"#);
   }
 }
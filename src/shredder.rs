@@ -1,21 +1,55 @@
 /*!
 This writes out a file for each path, where indexes are removed from the path.
 Each file contains all the values from that path, in order.
+
+Each value is written as `[path, indexes, value]`, where path is a number from the manifest
+`_paths.json` and indexes are the ones that were removed from the filename. That's enough for the
+Unshredder to put the records back together, or just some of their columns. The arrays and objects
+are written as `[]` and `{}` where they start, so the empty ones are kept. The channel shredder
+only gets leaves, so it doesn't write those, and what it writes can't be put back together.
+
+The manifest also says whether the top-level value is an array, whose elements are the records, and
+whether the starts of arrays and objects were written.
 */
 
 use std::sync::Arc;
 
+use std::io::Write;
+
 use crate::parser;
+use crate::builder::Builder;
+use crate::builder::Node;
 use crate::handler::Handler;
 use crate::jsonpath::*;
+use crate::pathformat;
+use crate::pathformat::Part;
+use crate::pathformat::PathFormat;
 use crate::sender;
 use crate::sendpath::SendPath;
 use crate::parser::JsonEvent;
 
+/// The file in the directory with the path of each path number.
+pub const MANIFEST : &str = "_paths.json";
+
+/// Directories written with a different version can't be read.
+const VERSION : u64 = 2;
+
 pub struct ShredWriter<V,W> {
   dir : std::path::PathBuf,
   ext : String,
-  files : std::collections::hash_map::HashMap<std::path::PathBuf, std::fs::File>,
+  files : Vec<std::io::BufWriter<std::fs::File>>,
+  // index into files by filename, because several paths can have the same filename
+  filenames : std::collections::hash_map::HashMap<std::path::PathBuf, usize>,
+  // index into files, and path number, by PathId, so most leaves don't need a filename
+  by_id : crate::pathdict::ById<(usize, u64)>,
+  // the same by dotted path, for when by_id starts again because the thread has a new path dictionary
+  by_path : std::collections::hash_map::HashMap<String, (usize, u64)>,
+  // the dotted path and the filename of each path number, for the manifest
+  paths : Vec<(String, String)>,
+  // whether the starts of arrays and objects are written
+  containers : bool,
+  // whether the top-level value is an array, once the first value has been written
+  array : Option<bool>,
   // only exists so rust doesn't erase V
   _event_marker : std::marker::PhantomData<V>,
   _ewent_marker : std::marker::PhantomData<W>,
@@ -37,36 +71,72 @@ impl<V,W> ShredWriter<V, W>
     }
     Self {
       dir,
-      files: std::collections::hash_map::HashMap::new(),
+      files: vec![],
+      filenames: std::collections::hash_map::HashMap::new(),
+      by_id: Default::default(),
+      by_path: std::collections::hash_map::HashMap::new(),
+      paths: vec![],
+      containers: false,
+      array: None,
       ext: ext.to_string(),
       _event_marker : std::marker::PhantomData,
       _ewent_marker : std::marker::PhantomData,
     }
   }
 
-  /// find or create a given file for the jsonpath
-  ///
-  /// Self keeps a hashmap of
-  ///
-  /// `PathBuf => File`
+  /// The values include the starts of arrays and objects, so that the Unshredder can put them back, empty or not.
+  pub fn with_containers(mut self) -> Self {
+    self.containers = true;
+    self
+  }
+
+  /// find or create a given file for the jsonpath, along with the number of the path
   ///
-  /// so it doesn't repeatedly reopen the same files.
-  fn find_or_create<'a>(&'a mut self, send_path : &crate::sendpath::SendPath) -> &'a std::fs::File {
+  /// Self keeps the files indexed by the PathId of the path, so it doesn't
+  /// repeatedly build filenames or reopen the same files.
+  fn find_or_create<'a>(&'a mut self, send_path : &crate::sendpath::SendPath) -> (&'a mut std::io::BufWriter<std::fs::File>, u64) {
+    if let Some((file, number)) = self.by_id.get(send_path.id()) { return (&mut self.files[file], number) }
+
+    // This only happens the first time a PathId is seen,
+    // so building the path and filename has little impact on the normal case.
+    let parts = send_path.interned().links().into_iter().map(|link| match link {
+      crate::pathdict::Link::Key(key) => Part::Key(key.as_ref().to_string().into()),
+      crate::pathdict::Link::Index => Part::Any,
+    }).collect::<Vec<Part>>();
+    let path = if parts.is_empty() { ".".into() } else { pathformat::write(parts, PathFormat::Dotted) };
+    // seen before with a PathId from an earlier dictionary, so it keeps its number
+    if let Some(&(file, number)) = self.by_path.get(&path) {
+      self.by_id.insert(send_path.id(), (file, number));
+      return (&mut self.files[file], number)
+    }
+
     let pathname = self.dir.join(filename_of_path(send_path, &self.ext));
-    if self.files.contains_key(&pathname) {
-      self.files.get(&pathname).unwrap()
-    } else {
-      // expect here because by now the filename should have valid characters, and other errors are fatal anyway.
-      #[allow(clippy::expect_fun_call)]
-      let file = std::fs::File::create(&pathname).expect(format!("error for path {pathname:?}").as_str());
-      // by definition this is a None
-      if self.files.insert(pathname.clone(), file).is_some() {
-        panic!("oops with {pathname:?}")
+    let filename = pathname.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let file = match self.filenames.get(&pathname) {
+      Some(file) => *file,
+      None => {
+        // expect here because by now the filename should have valid characters, and other errors are fatal anyway.
+        #[allow(clippy::expect_fun_call)]
+        let created = std::fs::File::create(&pathname).expect(format!("error for path {pathname:?}").as_str());
+        self.files.push(std::io::BufWriter::new(created));
+        self.filenames.insert(pathname, self.files.len() - 1);
+        self.files.len() - 1
       }
-      // This only happens the first time the file is created,
-      // so the extra lookup has little impact on the normal case.
-      self.find_or_create(send_path)
-    }
+    };
+    self.paths.push((path.clone(), filename));
+    let number = self.paths.len() as u64 - 1;
+
+    self.by_path.insert(path, (file, number));
+    self.by_id.insert(send_path.id(), (file, number));
+    (&mut self.files[file], number)
+  }
+
+  /// Flush the files, and write the manifest with the path of each path number.
+  pub fn finish(&mut self) -> std::io::Result<()> {
+    for file in &mut self.files { file.flush()? }
+    let paths = self.paths.iter().map(|(path, file)| serde_json::json!({"path": path, "file": file})).collect::<Vec<_>>();
+    let manifest = serde_json::json!({"version": VERSION, "containers": self.containers, "array": self.array.unwrap_or(false), "paths": paths});
+    std::fs::write(self.dir.join(MANIFEST), serde_json::to_string_pretty(&manifest)? + "\n")
   }
 }
 
@@ -79,8 +149,19 @@ impl<V: AsRef<[u8]> + std::fmt::Debug, W> ShredWriter<V, W>
     match ev {
       Event::Value(send_path,v) =>
       {
-        let mut file = self.find_or_create(send_path);
-        use std::io::Write;
+        // the first value is either the start of the top-level value, or a leaf of it
+        self.array.get_or_insert_with(|| match send_path.first() {
+          Some(Step::Index(_)) => true,
+          // an empty msgpack array, ie the start of an array
+          None => v.as_ref() == [0x90],
+          Some(Step::Key(_)) => false,
+        });
+        let (file, number) = self.find_or_create(send_path);
+        // which path, and the indexes that the filename leaves out
+        rmp::encode::write_array_len(file, 3).unwrap();
+        rmp::encode::write_uint(file, number).unwrap();
+        rmp::encode::write_array_len(file, send_path.indexes().len() as u32).unwrap();
+        for index in send_path.indexes() { rmp::encode::write_uint(file, *index).unwrap(); }
         // is a Vec inside an Arc, hence the double as_ref
         file.write_all(v.as_ref().as_ref()).unwrap();
       },
@@ -136,7 +217,9 @@ where
           Err(err) => Event::Error(path.into(), format!("msgpack error {err:?}")),
         }
       } else if number_value.is_f64() {
-        match rmp::encode::write_f64(&mut buf, number_value.as_f64().unwrap()) {
+        // serde_json rounds differently without float_roundtrip, so parse the text again to read back the same number
+        let float = AsRef::<str>::as_ref(v).parse::<f64>().unwrap_or_else(|_| number_value.as_f64().unwrap());
+        match rmp::encode::write_f64(&mut buf, float) {
           Ok(()) => Event::Value(SendPath::from(path), buf),
           Err(err) => Event::Error(path.into(), format!("msgpack error {err:?}")),
         }
@@ -159,6 +242,21 @@ where
       }
     }
 
+    // where an array or object starts, so that the empty ones are kept
+    JsonEvent::StartArray => {
+      match rmp::encode::write_array_len(&mut buf, 0) {
+        Ok(_) => Event::Value(SendPath::from(path), buf),
+        Err(err) => Event::Error(path.into(), format!("msgpack error {err:?}")),
+      }
+    }
+
+    JsonEvent::StartObject => {
+      match rmp::encode::write_map_len(&mut buf, 0) {
+        Ok(_) => Event::Value(SendPath::from(path), buf),
+        Err(err) => Event::Error(path.into(), format!("msgpack error {err:?}")),
+      }
+    }
+
     _ => todo!(),
   }
 }
@@ -191,17 +289,25 @@ impl<'l> Handler<'l, SendValue, Arc<SendEvent>, dyn Sender<SendEvent,Arc<SendEve
   }
 
   // encode values as MessagePack, then send to shredder
-  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<String>, tx : &mut (dyn Sender<SendEvent,Arc<SendEvent>> + 'l))
+  fn maybe_send_value(&self, path : &JsonPath, ev : JsonEvent<&str>, tx : &mut (dyn Sender<SendEvent,Arc<SendEvent>> + 'l))
   -> Result<(), Box<dyn std::error::Error>>
   {
     if !self.match_path(path) { return Ok(()) }
-    let send_event = encode_to_msgpack::<JsonPath,String>(path, &ev);
+    let send_event = encode_to_msgpack::<JsonPath,&str>(path, &ev);
     // OPT must this really be in a box?
     let () = tx
       .send(Arc::new(send_event))
       .unwrap_or_else(|err| panic!("could not send event {ev:?} because {err:?}"));
     Ok(())
   }
+
+  // encode the start of arrays and objects as empty ones
+  fn maybe_send_path(&self, path : &JsonPath, _depth : usize, container : crate::handler::Container, tx : &mut (dyn Sender<SendEvent,Arc<SendEvent>> + 'l))
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    if !self.match_path(path) { return Ok(()) }
+    tx.send(Arc::new(encode_to_msgpack::<JsonPath,&str>(path, &container.start())))
+  }
 }
 
 impl AsRef<[u8]> for sender::Event<Vec<u8>> {
@@ -216,21 +322,25 @@ impl AsRef<[u8]> for sender::Event<Vec<u8>> {
   }
 }
 
-pub fn shred<Stringish>(dir : &std::path::PathBuf, maybe_readable_args : &[Stringish])
+pub fn shred<Stringish>(dir : &std::path::Path, maybe_readable_args : &[Stringish])
 where Stringish : AsRef<str> + AsRef<std::path::Path> + std::fmt::Debug
 {
   let istream = crate::make_readable(maybe_readable_args);
   let mut jevstream = parser::JsonEventParser::new(istream);
+  write_shreds(dir, &mut jevstream);
+}
 
-  // write events as Dremel-style record shred columns
-  let mut writer = crate::shredder::ShredWriter::new(dir, "mpk");
+fn write_shreds(dir : &std::path::Path, jevstream : &mut dyn parser::JsonEventSource<String>) {
+  // write the values of each path to its own file, with what the Unshredder needs to put them back together
+  let mut writer = crate::shredder::ShredWriter::new(dir, "mpk").with_containers();
 
   // serialisation format for columns
   let visitor = MsgPacker::new();
 
   visitor
-    .value(&mut jevstream, JsonPath::new(), 0, &mut writer )
+    .value(jevstream, JsonPath::new(), 0, &mut writer )
     .unwrap_or_else(|err| eprintln!("ending event reading because {err:?}") );
+  writer.finish().unwrap_or_else(|err| eprintln!("cannot write {MANIFEST} because {err}"));
 }
 
 // T = serde_json::Value, for example
@@ -262,6 +372,7 @@ where S : AsRef<str> + AsRef<std::path::Path> + std::fmt::Debug
 
         writer.write_msgpack_value(&msgpacked_event)
       }
+      writer.finish().unwrap_or_else(|err| eprintln!("cannot write {MANIFEST} because {err}"));
     }).expect("cannot create recv thread")
   };
 
@@ -272,10 +383,11 @@ where S : AsRef<str> + AsRef<std::path::Path> + std::fmt::Debug
 
     // This will send `sender::Event<plain::JsonEvent>` over the channel
     use crate::plain::Plain;
-    let visitor = Plain(|_| true, std::marker::PhantomData);
+    let visitor : Plain<sender::NonWrap<_>> = Plain(|_| true, std::marker::PhantomData);
 
     visitor.value(&mut jevstream, JsonPath::new(), 0, &mut tx).unwrap_or_else(|_| println!("uhoh"));
-    // tx dropped automatically here, so channel gets closed
+    // tx was declared outside this block, so it has to be dropped to close the channel
+    drop(tx);
   }
 
   // done with the weird hoops
@@ -283,19 +395,210 @@ where S : AsRef<str> + AsRef<std::path::Path> + std::fmt::Debug
 }
 
 
+/// One value read back from a shredded file.
+#[derive(Debug)]
+struct Entry {
+  path : usize,
+  indexes : Vec<u64>,
+  value : JsonEvent<String>,
+}
+
+fn read_exact<const N : usize>(rd : &mut impl std::io::Read) -> std::io::Result<[u8; N]> {
+  let mut buf = [0; N];
+  rd.read_exact(&mut buf)?;
+  Ok(buf)
+}
+
+fn read_string(rd : &mut impl std::io::Read, marker : rmp::Marker) -> Result<JsonEvent<String>, Box<dyn std::error::Error>> {
+  let len = match marker {
+    rmp::Marker::FixStr(len) => len.into(),
+    rmp::Marker::Str8 => u8::from_be_bytes(read_exact(rd)?).into(),
+    rmp::Marker::Str16 => u16::from_be_bytes(read_exact(rd)?).into(),
+    _ => u32::from_be_bytes(read_exact(rd)?) as usize,
+  };
+  let mut buf = vec![0; len];
+  rd.read_exact(&mut buf)?;
+  Ok(JsonEvent::String(String::from_utf8(buf)?))
+}
+
+fn float(v : f64) -> Result<JsonEvent<String>, Box<dyn std::error::Error>> {
+  let number = serde_json::Number::from_f64(v).ok_or_else(|| format!("{v} isn't a json number"))?;
+  Ok(JsonEvent::Number(number.to_string()))
+}
+
+/// A value as written by encode_to_msgpack.
+fn read_value(rd : &mut impl std::io::Read) -> Result<JsonEvent<String>, Box<dyn std::error::Error>> {
+  use rmp::Marker;
+  let number = |n : &dyn std::fmt::Display| JsonEvent::Number(n.to_string());
+  Ok(match rmp::decode::read_marker(rd).map_err(|err| err.0)? {
+    Marker::Null => JsonEvent::Null,
+    Marker::True => JsonEvent::Boolean(true),
+    Marker::False => JsonEvent::Boolean(false),
+    Marker::FixPos(n) => number(&n),
+    Marker::FixNeg(n) => number(&n),
+    Marker::U8 => number(&u8::from_be_bytes(read_exact(rd)?)),
+    Marker::U16 => number(&u16::from_be_bytes(read_exact(rd)?)),
+    Marker::U32 => number(&u32::from_be_bytes(read_exact(rd)?)),
+    Marker::U64 => number(&u64::from_be_bytes(read_exact(rd)?)),
+    Marker::I8 => number(&i8::from_be_bytes(read_exact(rd)?)),
+    Marker::I16 => number(&i16::from_be_bytes(read_exact(rd)?)),
+    Marker::I32 => number(&i32::from_be_bytes(read_exact(rd)?)),
+    Marker::I64 => number(&i64::from_be_bytes(read_exact(rd)?)),
+    Marker::F32 => float(f32::from_be_bytes(read_exact(rd)?).into())?,
+    Marker::F64 => float(f64::from_be_bytes(read_exact(rd)?))?,
+    marker @ (Marker::FixStr(_) | Marker::Str8 | Marker::Str16 | Marker::Str32) => read_string(rd, marker)?,
+    Marker::FixArray(0) => JsonEvent::StartArray,
+    Marker::FixMap(0) => JsonEvent::StartObject,
+    marker => return Err(format!("{marker:?} isn't a value that the shredder writes").into()),
+  })
+}
+
+/// The next `[path, indexes, value]`, or None at the end of the file.
+fn read_entry(rd : &mut impl std::io::BufRead) -> Result<Option<Entry>, Box<dyn std::error::Error>> {
+  if rd.fill_buf()?.is_empty() { return Ok(None) }
+  if rmp::decode::read_array_len(rd)? != 3 { return Err("expected [path, indexes, value]".into()) }
+  let path = rmp::decode::read_int(rd)?;
+  let len = rmp::decode::read_array_len(rd)?;
+  let indexes = (0..len).map(|_| rmp::decode::read_int(rd)).collect::<Result<Vec<u64>, _>>()?;
+  Ok(Some(Entry{path, indexes, value: read_value(rd)?}))
+}
+
+/// One of the files, with its next value.
+struct Column {
+  filename : String,
+  rd : std::io::BufReader<std::fs::File>,
+  next : Option<Entry>,
+}
+
+impl Column {
+  // Read the next entry whose path is selected.
+  fn advance(&mut self, paths : &[Option<Vec<Part<'static>>>]) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+      self.next = read_entry(&mut self.rd).map_err(|err| format!("{}: {err}", self.filename))?;
+      match &self.next {
+        Some(entry) if entry.path >= paths.len() => return Err(format!("{}: path {} isn't in {MANIFEST}", self.filename, entry.path).into()),
+        Some(entry) if paths[entry.path].is_none() => continue,
+        _ => return Ok(()),
+      }
+    }
+  }
+}
+
+/// Puts the records back together from the files written by ShredWriter.
+///
+/// The records are the elements of the top-level array, or the whole document if it isn't an
+/// array. Keys are in the order their paths were first seen.
+pub struct Unshredder {
+  // in the order of the first path in each file
+  columns : Vec<Column>,
+  // each path number relative to the record, or None if it isn't selected
+  paths : Vec<Option<Vec<Part<'static>>>>,
+  // whether the records are the elements of the top-level array
+  elements : bool,
+}
+
+impl Unshredder {
+  /// Read the directory dir. With columns, the records only have the values at those paths,
+  /// relative to the record, and under them.
+  pub fn open(dir : &std::path::Path, columns : Option<&[Vec<Part>]>) -> Result<Self, Box<dyn std::error::Error>> {
+    let manifest_file = dir.join(MANIFEST);
+    let manifest : serde_json::Value = serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(&manifest_file)
+      .map_err(|err| format!("{manifest_file:?}: {err}"))?))?;
+    if manifest["version"].as_u64() != Some(VERSION) { return Err(format!("{manifest_file:?} isn't version {VERSION}").into()) }
+    if manifest["containers"].as_bool() != Some(true) {
+      return Err(format!("{dir:?} doesn't have the empty arrays and objects, because it was written by the channel shredder, so it can't be put back together").into())
+    }
+    let elements = manifest["array"].as_bool().ok_or_else(|| format!("{manifest_file:?} doesn't say whether the top-level value is an array"))?;
+
+    let mut entries = vec![];
+    for entry in manifest["paths"].as_array().map(Vec::as_slice).unwrap_or_default() {
+      let (Some(path), Some(file)) = (entry["path"].as_str(), entry["file"].as_str()) else { return Err(format!("expected a path and a file, not {entry}").into()) };
+      entries.push((pathformat::read(path, PathFormat::Dotted)?, file));
+    }
+
+    let prefix = |short : &[Part], long : &[Part]| long.len() >= short.len() && short.iter().zip(long).all(|(a, b)| a == b);
+    let mut paths = vec![];
+    let mut columns_by_file : Vec<Column> = vec![];
+    for (parts, file) in entries {
+      // the top-level array isn't part of any record
+      let relative = match (elements, parts.split_first()) {
+        (false, _) => Some(parts),
+        (true, Some((Part::Any, rst))) => Some(rst.to_vec()),
+        (true, _) => None,
+      };
+      // the paths of the arrays and objects around a column are kept too, so they're there when they're empty
+      let relative = relative.filter(|relative| columns.is_none_or(|columns| columns.iter().any(|column| prefix(column, relative) || prefix(relative, column))));
+      if relative.is_some() && !columns_by_file.iter().any(|column| column.filename == file) {
+        let filename = dir.join(file);
+        let rd = std::fs::File::open(&filename).map_err(|err| format!("{filename:?}: {err}"))?;
+        columns_by_file.push(Column{filename: file.into(), rd: std::io::BufReader::new(rd), next: None});
+      }
+      paths.push(relative);
+    }
+    for column in &mut columns_by_file { column.advance(&paths)? }
+    Ok(Self{columns: columns_by_file, paths, elements})
+  }
+
+  fn record_of(elements : bool, entry : &Entry) -> u64 {
+    if elements { entry.indexes.first().copied().unwrap_or_default() } else { 0 }
+  }
+
+  // Build the record from the next values of all the columns that are in it.
+  fn assemble(&mut self, record : u64) -> Result<Node, Box<dyn std::error::Error>> {
+    let Self{columns, paths, elements} = self;
+    let mut builder = Builder::default();
+    for column in columns.iter_mut() {
+      while column.next.as_ref().is_some_and(|entry| Self::record_of(*elements, entry) == record) {
+        let Some(entry) = column.next.take() else { break };
+        let parts = paths[entry.path].as_ref().expect("only selected paths are read");
+        let mut indexes = entry.indexes.iter().skip(usize::from(*elements));
+        let steps = parts.iter().map(|part| match part {
+          Part::Key(key) => Ok(Step::Key(key.to_string())),
+          _ => indexes.next().map(|index| Step::Index(*index)).ok_or_else(|| format!("{}: fewer indexes than the path has", column.filename)),
+        }).collect::<Result<Vec<Step>, String>>()?;
+        builder.insert(&steps, entry.value).map_err(|err| format!("{}: {err}", column.filename))?;
+        column.advance(paths)?;
+      }
+    }
+    Ok(builder.take().expect("a record has at least one value"))
+  }
+}
+
+impl Iterator for Unshredder {
+  type Item = Result<Node, Box<dyn std::error::Error>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let record = self.columns.iter()
+      .filter_map(|column| column.next.as_ref())
+      .map(|entry| Self::record_of(self.elements, entry))
+      .min()?;
+    Some(self.assemble(record))
+  }
+}
+
+/// Write the records from the files in dir, one per line.
+pub fn unshred(wr : &mut dyn std::io::Write, dir : &std::path::Path, columns : Option<&[Vec<Part>]>) -> Result<(), Box<dyn std::error::Error>> {
+  for record in Unshredder::open(dir, columns)? {
+    writeln!(wr, "{}", record?.to_json())?;
+  }
+  Ok(())
+}
+
+
 /**
 Converts a path in the form `images/23423/image_name` to
 `images.image_name.mpk`. Which basically means stripping out all Index
 components.
 
-It's on the critical path for every single leaf that will be written. So it must
-be fast. That, along with the need to detect a potentially empty filename, are
-the drivers behind the fancy iterator chain.
+It used to be on the critical path for every single leaf, but now it's only
+called once for each PathId. The need to detect a potentially empty filename is
+the driver behind the fancy iterator chain.
 */
 fn filename_of_path<'a>(send_path : &'a crate::sendpath::SendPath, ext : &'a str) -> std::path::PathBuf {
-  let mut steps = send_path.0.iter().filter_map(|step|
-    if let Step::Key(step) = step {
-      Some(step.as_str())
+  let links = send_path.interned().links();
+  let mut steps = links.iter().filter_map(|link|
+    if let crate::pathdict::Link::Key(step) = link {
+      Some(step.as_ref())
     } else {
       None
     }
@@ -322,7 +625,7 @@ mod test_filename_of_path {
 
   #[test]
   fn normal() {
-    let send_path = SendPath(vec![Step::Index(0), Step::Key("uno".into()), Step::Key("duo".into()), Step::Key("tre".into())]);
+    let send_path = SendPath::from(&[Step::Index(0), Step::Key("uno".into()), Step::Key("duo".into()), Step::Key("tre".into())][..]);
     let ext = "wut";
     let path = super::filename_of_path(&send_path, ext);
     assert_eq!(path, PathBuf::from("uno.duo.tre.wut"));
@@ -330,7 +633,7 @@ mod test_filename_of_path {
 
   #[test]
   fn empty() {
-    let send_path = SendPath(vec![]);
+    let send_path = SendPath::from(&[][..]);
     let ext = "wut";
     let path = super::filename_of_path(&send_path, ext);
     assert_eq!(path, PathBuf::from("_.wut"));
@@ -338,7 +641,7 @@ mod test_filename_of_path {
 
   #[test]
   fn index_only() {
-    let send_path = SendPath(vec![Step::Index(0)]);
+    let send_path = SendPath::from(&[Step::Index(0)][..]);
     let ext = "wut";
     let path = super::filename_of_path(&send_path, ext);
     assert_eq!(path, PathBuf::from("_.wut"));
@@ -346,7 +649,7 @@ mod test_filename_of_path {
 
   #[test]
   fn several_leading_index() {
-    let send_path = SendPath(vec![Step::Index(0),Step::Index(0),Step::Index(0)]);
+    let send_path = SendPath::from(&[Step::Index(0),Step::Index(0),Step::Index(0)][..]);
     let ext = "wut";
     let path = super::filename_of_path(&send_path, ext);
     assert_eq!(path, PathBuf::from("_.wut"));
@@ -355,9 +658,96 @@ mod test_filename_of_path {
   #[test]
   fn bad_chars() {
     // space and /
-    let send_path = SendPath(vec![Step::Key("this is a bad/dangerous path".into())]);
+    let send_path = SendPath::from(&[Step::Key("this is a bad/dangerous path".into())][..]);
     let ext = "wut";
     let path = super::filename_of_path(&send_path, ext);
     assert_eq!(path, PathBuf::from("this_is_a_bad_dangerous_path.wut"));
   }
 }
+
+#[cfg(test)]
+mod test_unshred {
+  use super::*;
+
+  const JSON : &str = r#"[
+  {"id": 1, "actor": {"login": "a b"}, "commits": [{"sha": "s1", "files": ["x", "y"]}, {"sha": "s2", "files": []}], "n": 1.5},
+  {"id": -2, "commits": [], "extra": {}, "none": null},
+  "scalar",
+  {"id": 18446744073709551615, "commits": [{"files": [["z"], {}]}], "n": 6.851391196938117e-05}
+]"#;
+
+  fn unshred_of(json : &'static str, name : &str, columns : Option<&str>) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("jch-test-{}-{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    write_shreds(&dir, &mut parser::JsonEventParser::new(Box::new(json.as_bytes())));
+    let columns = columns.map(|list| list.split(',').map(|column| pathformat::read(column, PathFormat::Dotted).unwrap()).collect::<Vec<_>>());
+    let records = Unshredder::open(&dir, columns.as_deref()).unwrap().map(|record| record.unwrap().to_json()).collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    records
+  }
+
+  #[test]
+  fn round_trip() {
+    let expected = serde_json::from_str::<serde_json::Value>(JSON).unwrap();
+    let records = unshred_of(JSON, "round-trip", None);
+    let records = records.iter().map(|record| serde_json::from_str::<serde_json::Value>(record).unwrap()).collect::<Vec<_>>();
+    assert_eq!(serde_json::Value::Array(records), expected);
+  }
+
+  #[test]
+  fn columns() {
+    assert_eq!(unshred_of(JSON, "columns", Some("id,commits[].files")), vec![
+      r#"{"id":1,"commits":[{"files":["x","y"]},{"files":[]}]}"#,
+      r#"{"id":-2,"commits":[]}"#,
+      r#""scalar""#,
+      r#"{"id":18446744073709551615,"commits":[{"files":[["z"],{}]}]}"#,
+    ]);
+  }
+
+  #[test]
+  fn whole_document() {
+    assert_eq!(unshred_of(r#"{"a": [1, [2]], "b": {}}"#, "document", None), vec![r#"{"a":[1,[2]],"b":{}}"#]);
+    assert_eq!(unshred_of("3", "scalar", None), vec!["3"]);
+  }
+
+  #[test]
+  fn empty_arrays() {
+    assert_eq!(unshred_of("[]", "empty", None), Vec::<String>::new());
+    assert_eq!(unshred_of("[[]]", "empty-record", None), vec!["[]"]);
+    assert_eq!(unshred_of("[1]", "one", None), vec!["1"]);
+  }
+
+  #[test]
+  fn new_dictionary() {
+    let dir = std::env::temp_dir().join(format!("jch-test-{}-new-dictionary", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut writer = ShredWriter::<Vec<u8>, ()>::new(&dir, "mpk");
+    let paths = [vec![Step::Index(0), Step::Key("a".into())], vec![Step::Index(0), Step::Key("b".into())]];
+    for record in 0..2 {
+      // as if the dictionary were full after the first record
+      if record == 1 { crate::pathdict::restart() }
+      for steps in &paths {
+        let mut steps = steps.clone();
+        steps[0] = Step::Index(record);
+        writer.write_msgpack_value(&sender::Event::Value(SendPath::from(&steps[..]), vec![0xc0]));
+      }
+    }
+    writer.finish().unwrap();
+    let manifest = std::fs::read_to_string(dir.join(MANIFEST)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let manifest = serde_json::from_str::<serde_json::Value>(&manifest).unwrap();
+    assert_eq!(manifest["paths"], serde_json::json!([{"path": "[].a", "file": "a.mpk"}, {"path": "[].b", "file": "b.mpk"}]));
+  }
+
+  #[test]
+  fn channel_shredded() {
+    let dir = std::env::temp_dir().join(format!("jch-test-{}-channel", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.json");
+    std::fs::write(&input, JSON).unwrap();
+    channel_shred(&dir, &[input.to_str().unwrap()]);
+    let err = Unshredder::open(&dir, None).err().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(err.to_string().contains("written by the channel shredder"), "{err}");
+  }
+}
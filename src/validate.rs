@@ -0,0 +1,653 @@
+/*!
+Checks the input against a JSON Schema, eg a contract from a partner, while streaming. So the input
+is never all in memory, only the schema is.
+
+Only the keywords that can be checked one value at a time are supported: `type`, `enum` and `const`
+of leaves, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `multipleOf`, `minLength`,
+`maxLength`, `pattern`, `properties`, `patternProperties`, `additionalProperties`, `required`,
+`minProperties`, `maxProperties`, `items`, `prefixItems`, `additionalItems`, `minItems`, `maxItems`,
+`allOf`, and `$ref` within the schema. The others, like `anyOf` or `uniqueItems`, would need several
+values at once, so they're reported as not checked. `format` is only an annotation, so it isn't
+checked either.
+
+Values that the schema has nothing to say about are skipped without being converted.
+*/
+
+use std::collections::HashMap;
+
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::jsonpath::JsonPath;
+use crate::parser::JsonEvent;
+use crate::parser::JsonEventSource;
+use crate::parser::Position;
+use crate::parser::PositionTracker;
+use crate::sender;
+use crate::sender::Event;
+use crate::sender::Sender;
+
+/// Keywords that need more than one value at a time.
+const UNSUPPORTED : &[&str] = &[
+  "anyOf", "oneOf", "not", "if", "then", "else",
+  "uniqueItems", "contains", "minContains", "maxContains",
+  "propertyNames", "dependencies", "dependentRequired", "dependentSchemas",
+  "unevaluatedItems", "unevaluatedProperties", "$dynamicRef", "$recursiveRef",
+];
+
+// keywords whose values are a schema, or an array of schemas
+const SUBSCHEMAS : &[&str] = &["items", "prefixItems", "additionalItems", "additionalProperties", "allOf", "anyOf", "oneOf", "not", "if", "then", "else", "contains", "propertyNames"];
+
+// keywords whose values are maps of schemas
+const SUBSCHEMA_MAPS : &[&str] = &["properties", "patternProperties", "definitions", "$defs", "dependentSchemas"];
+
+/// `$ref`s that lead to more `$ref`s are only followed this far, in case they go round in circles.
+const REF_LIMIT : usize = 64;
+
+/// Longer strings are truncated in messages.
+const STRING_LENGTH : usize = 40;
+
+/// A JSON Schema to check the input against.
+#[derive(Debug)]
+pub struct Contract {
+  root : Value,
+  // pattern and patternProperties, compiled
+  patterns : HashMap<String, regex::Regex>,
+  // json pointers to the keywords that aren't checked
+  unsupported : Vec<String>,
+}
+
+fn is_container(value : &Value) -> bool {
+  matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+// a key as part of a json pointer
+fn escaped(key : &str) -> String {
+  key.replace('~', "~0").replace('/', "~1")
+}
+
+// a uri fragment with its %xx escapes decoded
+fn percent_decoded(fragment : &str) -> Result<std::borrow::Cow<'_, str>, String> {
+  if !fragment.contains('%') { return Ok(fragment.into()) }
+  let mut bytes = Vec::with_capacity(fragment.len());
+  let mut rest = fragment.as_bytes();
+  while let Some((&c, tail)) = rest.split_first() {
+    rest = tail;
+    if c != b'%' { bytes.push(c); continue }
+    let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    let Some(decoded) = hex else { return Err(format!("bad % escape in {fragment}")) };
+    bytes.push(decoded);
+    rest = &rest[2..];
+  }
+  String::from_utf8(bytes).map(Into::into).map_err(|_| format!("% escapes in {fragment} aren't utf-8"))
+}
+
+// the schema that a $ref refers to. It's a uri fragment, so it's percent-decoded, and then pointer does the ~0 and ~1.
+fn lookup<'c>(root : &'c Value, reference : &str) -> Result<&'c Value, String> {
+  match reference.strip_prefix('#') {
+    Some(fragment) => root.pointer(&percent_decoded(fragment)?).ok_or_else(|| format!("$ref {reference} is not in the schema")),
+    None => Err(format!("only $refs within the schema are supported, not {reference}")),
+  }
+}
+
+impl Contract {
+  /// Read the schema, and check the things that can be checked before there's any input.
+  pub fn read(rd : &mut dyn std::io::Read) -> Result<Self, Box<dyn std::error::Error>> {
+    Self::new(serde_json::from_reader(rd)?)
+  }
+
+  pub fn new(root : Value) -> Result<Self, Box<dyn std::error::Error>> {
+    let mut contract = Self{root: Value::Null, patterns: HashMap::new(), unsupported: vec![]};
+    contract.walk(&root, &root, "")?;
+    contract.root = root;
+    Ok(contract)
+  }
+
+  /// Json pointers to the keywords in the schema that aren't checked.
+  pub fn unsupported(&self) -> &[String] {
+    &self.unsupported
+  }
+
+  fn compile(&mut self, pattern : &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !self.patterns.contains_key(pattern) {
+      let regex = regex::Regex::new(pattern).map_err(|err| format!("pattern {pattern}: {err}"))?;
+      self.patterns.insert(pattern.into(), regex);
+    }
+    Ok(())
+  }
+
+  // Compile the patterns, check the $refs, and note the keywords that aren't supported, in schema and the schemas inside it.
+  fn walk(&mut self, root : &Value, schema : &Value, pointer : &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Value::Object(map) = schema else { return Ok(()) };
+    for (keyword, value) in map {
+      let here = format!("{pointer}/{}", escaped(keyword));
+      if UNSUPPORTED.contains(&keyword.as_str()) { self.unsupported.push(here.clone()) }
+      match (keyword.as_str(), value) {
+        ("pattern", Value::String(pattern)) => self.compile(pattern)?,
+        ("$ref", Value::String(reference)) => { lookup(root, reference)?; }
+        // leaves can still be checked against these
+        ("enum", Value::Array(values)) if values.iter().any(is_container) => self.unsupported.push(here),
+        ("const", value) if is_container(value) => self.unsupported.push(here),
+        (keyword, Value::Object(schemas)) if SUBSCHEMA_MAPS.contains(&keyword) => {
+          for (name, sub) in schemas {
+            if keyword == "patternProperties" { self.compile(name)? }
+            self.walk(root, sub, &format!("{here}/{}", escaped(name)))?
+          }
+        }
+        (keyword, Value::Array(schemas)) if SUBSCHEMAS.contains(&keyword) => {
+          for (i, sub) in schemas.iter().enumerate() {
+            self.walk(root, sub, &format!("{here}/{i}"))?
+          }
+        }
+        (keyword, sub) if SUBSCHEMAS.contains(&keyword) => self.walk(root, sub, &here)?,
+        _ => (),
+      }
+    }
+    Ok(())
+  }
+
+  // Add schema to schemas, along with the schemas it refers to with $ref and allOf.
+  // true allows anything, so there's no need to keep it.
+  fn resolve<'c>(&'c self, schema : &'c Value, schemas : &mut Vec<&'c Value>, refs : usize) -> Result<(), String> {
+    if refs > REF_LIMIT { return Err(format!("more than {REF_LIMIT} nested $refs")) }
+    match schema {
+      Value::Object(map) => {
+        if let Some(Value::String(reference)) = map.get("$ref") {
+          self.resolve(lookup(&self.root, reference)?, schemas, refs + 1)?
+        }
+        if let Some(Value::Array(all)) = map.get("allOf") {
+          for sub in all { self.resolve(sub, schemas, refs + 1)? }
+        }
+        schemas.push(schema);
+      }
+      Value::Bool(false) => schemas.push(schema),
+      _ => (),
+    }
+    Ok(())
+  }
+}
+
+/// A value that doesn't match the schema.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Violation {
+  pub message : String,
+  /// Where the value starts. For a missing key, that's the start of its object.
+  pub position : Option<Position>,
+}
+
+type ViolationWrapper = sender::NonWrap<Event<Violation>>;
+type ViolationSender<'l> = dyn Sender<Event<Violation>, ViolationWrapper> + 'l;
+
+// the type of a value, as named in the schema
+fn type_of(ev : &JsonEvent<String>) -> &'static str {
+  match ev {
+    JsonEvent::String(_) => "string",
+    JsonEvent::Number(_) => "number",
+    JsonEvent::Boolean(_) => "boolean",
+    JsonEvent::Null => "null",
+    JsonEvent::StartArray => "array",
+    _ => "object",
+  }
+}
+
+fn is_integer(value : &Value) -> bool {
+  match value {
+    Value::Number(n) => n.is_u64() || n.is_i64() || n.as_f64().is_some_and(|f| f.fract() == 0.0),
+    _ => false,
+  }
+}
+
+// equal, as far as the schema is concerned. So 1 and 1.0 are the same.
+fn same(a : &Value, b : &Value) -> bool {
+  match (a, b) {
+    (Value::Number(x), Value::Number(y)) => x == y || x.as_f64() == y.as_f64(),
+    _ => a == b,
+  }
+}
+
+// the value for a message
+fn describe(value : &Value) -> String {
+  match value {
+    Value::String(s) if s.chars().count() > STRING_LENGTH => {
+      format!("{}…", Value::String(s.chars().take(STRING_LENGTH).collect()))
+    }
+    value => value.to_string(),
+  }
+}
+
+// what a type keyword allows
+fn type_names(types : &Value) -> Vec<&str> {
+  match types {
+    Value::String(name) => vec![name.as_str()],
+    Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+    _ => vec![],
+  }
+}
+
+// Messages for type, enum and const, which apply to any value. value is None for containers.
+fn general(schema : &Map<String, Value>, kind : &str, value : Option<&Value>, messages : &mut Vec<String>) {
+  let got = match value {
+    Some(Value::Null) | None => kind.into(),
+    Some(value) => format!("{kind} {}", describe(value)),
+  };
+  if let Some(types) = schema.get("type") {
+    let names = type_names(types);
+    let allowed = names.iter().any(|name| *name == kind || (*name == "integer" && value.is_some_and(is_integer)));
+    if !names.is_empty() && !allowed {
+      messages.push(format!("expected {}, got {got}", names.join(" or ")));
+    }
+  }
+  if let Some(Value::Array(values)) = schema.get("enum") {
+    let allowed = match value {
+      Some(value) => values.iter().any(|allowed| same(allowed, value)),
+      // only leaves are compared, so this is only known when there are no containers in the enum
+      None => values.iter().any(is_container),
+    };
+    if !allowed { messages.push(format!("{got} is not one of the enum values")) }
+  }
+  if let Some(constant) = schema.get("const") {
+    let allowed = match value {
+      Some(value) => same(constant, value),
+      None => is_container(constant),
+    };
+    if !allowed { messages.push(format!("{got} is not the const {}", describe(constant))) }
+  }
+}
+
+// Messages for the keywords about numbers.
+fn number(schema : &Map<String, Value>, number : &serde_json::Number, messages : &mut Vec<String>) {
+  let value = number.as_f64().unwrap_or(f64::NAN);
+  let limit = |keyword : &str| schema.get(keyword).and_then(|limit| limit.as_f64().map(|f| (f, limit)));
+  // before draft 6, exclusiveMinimum and exclusiveMaximum were booleans that changed minimum and maximum
+  let exclusive = |keyword : &str| schema.get(keyword) == Some(&Value::Bool(true));
+  let value_fmt = number.to_string();
+
+  match limit("minimum") {
+    Some((min, shown)) if exclusive("exclusiveMinimum") && value <= min => messages.push(format!("{value_fmt} is not greater than the exclusive minimum {shown}")),
+    Some((min, shown)) if value < min => messages.push(format!("{value_fmt} is less than the minimum {shown}")),
+    _ => (),
+  }
+  if let Some((min, shown)) = limit("exclusiveMinimum") {
+    if value <= min { messages.push(format!("{value_fmt} is not greater than the exclusive minimum {shown}")) }
+  }
+  match limit("maximum") {
+    Some((max, shown)) if exclusive("exclusiveMaximum") && value >= max => messages.push(format!("{value_fmt} is not less than the exclusive maximum {shown}")),
+    Some((max, shown)) if value > max => messages.push(format!("{value_fmt} is more than the maximum {shown}")),
+    _ => (),
+  }
+  if let Some((max, shown)) = limit("exclusiveMaximum") {
+    if value >= max { messages.push(format!("{value_fmt} is not less than the exclusive maximum {shown}")) }
+  }
+  if let Some((divisor, shown)) = limit("multipleOf").filter(|(divisor, _)| *divisor > 0.0) {
+    let quotient = value / divisor;
+    // because floats are not exact
+    if (quotient - quotient.round()).abs() > 1e-9 * quotient.abs().max(1.0) {
+      messages.push(format!("{value_fmt} is not a multiple of {shown}"));
+    }
+  }
+}
+
+/// Sends a Violation for each value that doesn't match the contract.
+pub struct Validator<'c> {
+  contract : &'c Contract,
+  positions : Option<PositionTracker>,
+}
+
+impl<'c> Validator<'c> {
+  pub fn new(contract : &'c Contract) -> Self {
+    Self{contract, positions: None}
+  }
+
+  /// Send the position of each violation, from the parser's tracker.
+  pub fn with_positions(mut self, tracker : Option<PositionTracker>) -> Self {
+    self.positions = tracker;
+    self
+  }
+
+  fn position(&self) -> Option<Position> {
+    self.positions.as_ref().map(|tracker| tracker.get())
+  }
+
+  fn send(&self, path : &JsonPath, position : Option<Position>, messages : Vec<String>, tx : &mut ViolationSender<'_>) -> Result<(), Box<dyn std::error::Error>> {
+    for message in messages {
+      tx.send(Event::Value(path.into(), Violation{message, position}).into())?
+    }
+    Ok(())
+  }
+
+  fn resolved(&self, sub : &'c Value, schemas : &mut Vec<&'c Value>) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(self.contract.resolve(sub, schemas, 0)?)
+  }
+
+  /// Check the whole input.
+  pub fn validate(&self, jevs : &mut dyn JsonEventSource<String>, tx : &mut ViolationSender<'_>) -> Result<(), Box<dyn std::error::Error>> {
+    match jevs.next_event()? {
+      JsonEvent::Eof => (),
+      ev => {
+        let mut schemas = vec![];
+        self.resolved(&self.contract.root, &mut schemas)?;
+        self.check(jevs, JsonPath::new(), ev, &schemas, tx)?
+      }
+    }
+    tx.send(Event::Finished.into())
+  }
+
+  // Check the value that starts with ev against all of schemas.
+  fn check(&self, jevs : &mut dyn JsonEventSource<String>, path : JsonPath, ev : JsonEvent<String>, schemas : &[&'c Value], tx : &mut ViolationSender<'_>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    use JsonEvent::*;
+    let value = match &ev {
+      Eof => return Err(format!("unexpected end of input at {}", crate::sendpath::SendPath::from(path)).into()),
+      err @ Error{..} => return Err(format!("{err}").into()),
+      _ if schemas.is_empty() => return crate::handler::skip_value(jevs, ev),
+      String(s) => Some(Value::String(s.clone())),
+      Number(n) => match serde_json::from_str(n) {
+        Ok(number) => Some(Value::Number(number)),
+        // valid json, but too big for a double, eg 1e400. So it can't be compared with anything.
+        Err(_) => return self.send(&path, self.position(), vec![format!("{n} is out of range, so it can't be checked")], tx),
+      },
+      Boolean(b) => Some(Value::Bool(*b)),
+      Null => Some(Value::Null),
+      StartArray | StartObject => None,
+      ev => return Err(format!("unexpected {ev} at {}", crate::sendpath::SendPath::from(path)).into()),
+    };
+    let position = self.position();
+    let kind = type_of(&ev);
+
+    let mut messages = vec![];
+    for schema in schemas {
+      let Value::Object(schema) = schema else {
+        messages.push("no value is allowed here".into());
+        continue
+      };
+      general(schema, kind, value.as_ref(), &mut messages);
+      match &value {
+        Some(Value::Number(n)) => number(schema, n, &mut messages),
+        Some(Value::String(s)) => self.string(schema, s, &mut messages),
+        _ => (),
+      }
+    }
+    self.send(&path, position, messages, tx)?;
+
+    match ev {
+      StartArray => self.array(jevs, path, position, schemas, tx),
+      StartObject => self.object(jevs, path, position, schemas, tx),
+      _ => Ok(()),
+    }
+  }
+
+  // Messages for the keywords about strings.
+  fn string(&self, schema : &Map<String, Value>, value : &str, messages : &mut Vec<String>) {
+    let length = value.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+      if length < min { messages.push(format!("length {length} is less than minLength {min}")) }
+    }
+    if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+      if length > max { messages.push(format!("length {length} is more than maxLength {max}")) }
+    }
+    if let Some(Value::String(pattern)) = schema.get("pattern") {
+      if self.contract.patterns.get(pattern).is_some_and(|regex| !regex.is_match(value)) {
+        messages.push(format!("{} doesn't match the pattern {pattern}", describe(&Value::String(value.into()))))
+      }
+    }
+  }
+
+  // The schema for the element at index of an array, if there is one.
+  fn item(schema : &'c Map<String, Value>, index : u64) -> Option<&'c Value> {
+    let at = |tuple : &'c Vec<Value>| tuple.get(index as usize);
+    match (schema.get("prefixItems"), schema.get("items")) {
+      (Some(Value::Array(tuple)), rest) => at(tuple).or(rest),
+      // before 2020-12, items was the tuple and additionalItems the rest
+      (None, Some(Value::Array(tuple))) => at(tuple).or(schema.get("additionalItems")),
+      (_, items) => items,
+    }
+  }
+
+  // The rest of an array, after StartArray.
+  fn array(&self, jevs : &mut dyn JsonEventSource<String>, path : JsonPath, position : Option<Position>, schemas : &[&'c Value], tx : &mut ViolationSender<'_>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    let mut index = 0;
+    loop {
+      let ev = jevs.next_event()?;
+      if ev == JsonEvent::EndArray { break }
+      let mut items = vec![];
+      for schema in schemas.iter().filter_map(|schema| schema.as_object()) {
+        if let Some(sub) = Self::item(schema, index) { self.resolved(sub, &mut items)? }
+      }
+      self.check(jevs, path.push_back(index.into()), ev, &items, tx)?;
+      index += 1;
+    }
+
+    let mut messages = vec![];
+    for schema in schemas.iter().filter_map(|schema| schema.as_object()) {
+      if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if index < min { messages.push(format!("{index} items, fewer than minItems {min}")) }
+      }
+      if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if index > max { messages.push(format!("{index} items, more than maxItems {max}")) }
+      }
+    }
+    self.send(&path, position, messages, tx)
+  }
+
+  // The rest of an object, after StartObject.
+  fn object(&self, jevs : &mut dyn JsonEventSource<String>, path : JsonPath, position : Option<Position>, schemas : &[&'c Value], tx : &mut ViolationSender<'_>)
+  -> Result<(), Box<dyn std::error::Error>>
+  {
+    let objects = schemas.iter().filter_map(|schema| schema.as_object()).collect::<Vec<_>>();
+    // crossed off as they turn up
+    let mut missing = objects.iter()
+      .filter_map(|schema| schema.get("required").and_then(Value::as_array))
+      .flatten()
+      .filter_map(Value::as_str)
+      .collect::<Vec<&str>>();
+    let mut keys = 0;
+
+    loop {
+      let key = match jevs.next_event()? {
+        JsonEvent::EndObject => break,
+        JsonEvent::ObjectKey(key) => key,
+        err @ JsonEvent::Error{..} => return Err(format!("{err}").into()),
+        ev => return Err(format!("unexpected {ev} at {}", crate::sendpath::SendPath::from(path)).into()),
+      };
+      let key_position = self.position();
+      keys += 1;
+      missing.retain(|required| *required != key);
+
+      let mut values = vec![];
+      let mut allowed = true;
+      for schema in &objects {
+        let mut matched = false;
+        if let Some(sub) = schema.get("properties").and_then(|properties| properties.get(&key)) {
+          self.resolved(sub, &mut values)?;
+          matched = true;
+        }
+        if let Some(Value::Object(patterns)) = schema.get("patternProperties") {
+          for (pattern, sub) in patterns {
+            if self.contract.patterns.get(pattern).is_some_and(|regex| regex.is_match(&key)) {
+              self.resolved(sub, &mut values)?;
+              matched = true;
+            }
+          }
+        }
+        match schema.get("additionalProperties") {
+          Some(Value::Bool(false)) if !matched => allowed = false,
+          Some(sub) if !matched => self.resolved(sub, &mut values)?,
+          _ => (),
+        }
+      }
+
+      let key_path = path.push_back(key.as_str().into());
+      let ev = jevs.next_event()?;
+      if allowed {
+        self.check(jevs, key_path, ev, &values, tx)?
+      } else {
+        self.send(&key_path, key_position, vec![format!("key {} is not allowed", describe(&Value::String(key)))], tx)?;
+        crate::handler::skip_value(jevs, ev)?
+      }
+    }
+
+    let mut messages = missing.into_iter().map(|key| format!("missing required key {}", describe(&Value::String(key.into())))).collect::<Vec<String>>();
+    for schema in &objects {
+      if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+        if keys < min { messages.push(format!("{keys} keys, fewer than minProperties {min}")) }
+      }
+      if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+        if keys > max { messages.push(format!("{keys} keys, more than maxProperties {max}")) }
+      }
+    }
+    self.send(&path, position, messages, tx)
+  }
+}
+
+/// Writes violations one per line, with their position and full path.
+pub struct ViolationWriter<'w> {
+  wr : &'w mut dyn std::io::Write,
+  count : u64,
+}
+
+impl<'w> ViolationWriter<'w> {
+  pub fn new(wr : &'w mut dyn std::io::Write) -> Self {
+    Self{wr, count: 0}
+  }
+
+  /// Number of violations written.
+  pub fn count(&self) -> u64 {
+    self.count
+  }
+}
+
+impl Sender<Event<Violation>, ViolationWrapper> for ViolationWriter<'_> {
+  fn send(&mut self, ev : ViolationWrapper) -> Result<(), Box<dyn std::error::Error>> {
+    match ev.as_ref() {
+      Event::Value(path, Violation{message, position}) => {
+        self.count += 1;
+        let path = match path.written() {
+          path if path.is_empty() => ".".to_string(),
+          path => path,
+        };
+        match position {
+          Some(position) => writeln!(self.wr, "{position} {path}  {message}")?,
+          None => writeln!(self.wr, "{path}  {message}")?,
+        }
+      }
+      Event::Error(path, err) => return Err(format!("{err} at path '{path}'").into()),
+      Event::Path(..) | Event::Finished => (),
+    }
+    Ok(())
+  }
+}
+
+/// Check the input against the contract, and write the violations.
+///
+/// Keywords that can't be checked are listed on stderr. If there were any violations, the result is an error.
+pub fn validate(wr : &mut dyn std::io::Write, jev : &mut dyn JsonEventSource<String>, contract : &Contract) -> Result<(), Box<dyn std::error::Error>> {
+  for pointer in contract.unsupported() {
+    eprintln!("not checked: #{pointer}");
+  }
+  let mut writer = ViolationWriter::new(wr);
+  Validator::new(contract).with_positions(jev.position_tracker()).validate(jev, &mut writer)?;
+  match writer.count() {
+    0 => Ok(()),
+    n => Err(format!("violations: {n}").into()),
+  }
+}
+
+#[cfg(test)]
+mod test_validate {
+  use super::Contract;
+
+  // the output for json checked against schema
+  fn violations(schema : &str, json : &'static str) -> String {
+    let contract = Contract::new(serde_json::from_str(schema).unwrap()).unwrap();
+    let mut jevstream = crate::parser::JsonEventParser::new(Box::new(json.as_bytes())).with_positions();
+    let mut out = vec![];
+    let _ = super::validate(&mut out, &mut jevstream, &contract);
+    String::from_utf8(out).unwrap()
+  }
+
+  const CONTRACT : &str = r##"{
+    "type": "array",
+    "items": {"$ref": "#/$defs/event"},
+    "$defs": {
+      "event": {
+        "type": "object",
+        "required": ["id", "type"],
+        "additionalProperties": false,
+        "properties": {
+          "id": {"type": "integer", "minimum": 1},
+          "type": {"enum": ["PushEvent", "WatchEvent"]},
+          "tags": {"type": "array", "maxItems": 2, "items": {"type": "string", "maxLength": 3, "pattern": "^[a-z]+$"}},
+          "score": {"type": ["number", "null"], "exclusiveMaximum": 1, "multipleOf": 0.25}
+        }
+      }
+    }
+  }"##;
+
+  #[test]
+  fn valid() {
+    assert_eq!(violations(CONTRACT, r#"[{"id": 1, "type": "PushEvent", "tags": ["ab"], "score": 0.75}, {"id": 2.0, "type": "WatchEvent", "score": null}]"#), "");
+  }
+
+  #[test]
+  fn indexed_paths() {
+    let json = r#"[
+{"id": 1, "type": "PushEvent"},
+{"id": 0, "type": "ForkEvent", "tags": ["a", "Bc", "defg"], "score": 1, "extra": {"x": 1}},
+{"type": "PushEvent", "id": "3"}
+]"#;
+    let expected = r#"3:8 [1]/id  0 is less than the minimum 1
+3:19 [1]/type  string "ForkEvent" is not one of the enum values
+3:46 [1]/tags/[1]  "Bc" doesn't match the pattern ^[a-z]+$
+3:52 [1]/tags/[2]  length 4 is more than maxLength 3
+3:40 [1]/tags  3 items, more than maxItems 2
+3:70 [1]/score  1 is not less than the exclusive maximum 1
+3:73 [1]/extra  key "extra" is not allowed
+4:29 [2]/id  expected integer, got string "3"
+"#;
+    assert_eq!(violations(CONTRACT, json), expected);
+  }
+
+  #[test]
+  fn required() {
+    assert_eq!(violations(CONTRACT, r#"[{"tags": []}]"#), "1:2 [0]  missing required key \"id\"\n1:2 [0]  missing required key \"type\"\n");
+    assert_eq!(violations(CONTRACT, r#"{"id": 1}"#), "1:1 .  expected array, got object\n");
+  }
+
+  #[test]
+  fn tuples() {
+    let schema = r#"{"prefixItems": [{"type": "number"}, {"type": "number"}], "items": false, "minItems": 2}"#;
+    assert_eq!(violations(schema, "[1, 2]"), "");
+    assert_eq!(violations(schema, r#"[1, "2", 3]"#), "1:5 [1]  expected number, got string \"2\"\n1:10 [2]  no value is allowed here\n");
+    assert_eq!(violations(schema, "[1]"), "1:1 .  1 items, fewer than minItems 2\n");
+  }
+
+  #[test]
+  fn out_of_range() {
+    assert_eq!(violations(r#"{"items": {"type": "number"}}"#, "[1e400, 2, \"x\"]"), "1:2 [0]  1e400 is out of range, so it can't be checked\n1:12 [2]  expected number, got string \"x\"\n");
+  }
+
+  #[test]
+  fn escaped_refs() {
+    let schema = r##"{
+      "properties": {
+        "a": {"$ref": "#/$defs/a~1b"},
+        "b": {"$ref": "#/$defs/t~0x"},
+        "c": {"$ref": "#/$defs/50%25%20off"}
+      },
+      "$defs": {"a/b": {"type": "string"}, "t~x": {"type": "number"}, "50% off": {"type": "boolean"}}
+    }"##;
+    assert_eq!(violations(schema, r#"{"a": 1, "b": "2", "c": null}"#), "1:7 a  expected string, got number 1\n1:15 b  expected number, got string \"2\"\n1:25 c  expected boolean, got null\n");
+    assert!(Contract::new(serde_json::from_str(r##"{"$ref": "#/%zz"}"##).unwrap()).is_err());
+  }
+
+  #[test]
+  fn unsupported() {
+    let contract = Contract::new(serde_json::from_str(r#"{"properties": {"not": {"anyOf": [{}]}, "a": {"enum": [[1]]}}}"#).unwrap()).unwrap();
+    assert_eq!(contract.unsupported(), ["/properties/a/enum", "/properties/not/anyOf"]);
+    assert!(Contract::new(serde_json::from_str(r#"{"$ref": "other.json#/a"}"#).unwrap()).is_err());
+    assert!(Contract::new(serde_json::from_str(r##"{"items": {"$ref": "#/$defs/missing"}}"##).unwrap()).is_err());
+  }
+}
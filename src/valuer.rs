@@ -54,7 +54,7 @@ where
 
   // convert the string contained in the JsonEvent into a serde_json::Value
   // and call tx.send with that.
-  fn maybe_send_value(&self, path : &JsonPath, jev : JsonEvent<String>, tx : &mut (dyn sender::Sender<Event<SendValue>, SendWrapper> + 'l))
+  fn maybe_send_value(&self, path : &JsonPath, jev : JsonEvent<&str>, tx : &mut (dyn sender::Sender<Event<SendValue>, SendWrapper> + 'l))
   -> Result<(),Box<dyn std::error::Error>>
   {
     use JsonEvent::*;
@@ -71,7 +71,7 @@ where
         tx.send(SendWrapper::from(Event::Value(SendPath::from(path),value.into())))
       }
       Number(v) => {
-        let value : serde_json::Number = match serde_json::from_str(&v) {
+        let value : serde_json::Number = match serde_json::from_str(v) {
             Ok(n) => n,
             Err(msg) => panic!("{v} appears to be not-a-number {msg}"),
         };